@@ -0,0 +1,57 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use types::Id;
+
+// Who combines and verifies the signature of a group
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    // every member combines and verifies the group signature itself
+    Every,
+    // one leader per group combines and verifies it and sends it to the rest of the group.
+    // Members still verify the group signature, which is cheaper than combining shares, and
+    // combine it themselves when it's invalid or doesn't come within the timeout
+    Leader(Duration),
+}
+
+impl Aggregation {
+    // the leader of `group` for `msg`, it rotates with the message and differs between groups
+    pub fn leader(msg: &[u8], group: usize, m: usize) -> usize {
+        msg.iter()
+            .fold(group % m, |acc, byte| (acc * 31 + *byte as usize) % m)
+    }
+
+    // the leader of `group` for `msg` and how long its members wait for it, None with Every
+    pub fn leader_of(&self, msg: &[u8], group: usize, m: usize) -> Option<(Id, Duration)> {
+        match self {
+            Aggregation::Every => None,
+            Aggregation::Leader(timeout) => {
+                Some((Id::Bivariate(group, Self::leader(msg, group, m)), *timeout))
+            }
+        }
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    // parses "every" or "leader:<timeout in ms>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "every" => Ok(Aggregation::Every),
+            Some(("leader", timeout)) => match timeout.parse::<u64>() {
+                Ok(timeout) => Ok(Aggregation::Leader(Duration::from_millis(timeout))),
+                Err(_) => Err(format!("invalid leader timeout: {}", timeout)),
+            },
+            _ => Err(format!("unknown aggregation: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregation::Every => write!(f, "every"),
+            Aggregation::Leader(timeout) => write!(f, "leader:{}", timeout.as_millis()),
+        }
+    }
+}
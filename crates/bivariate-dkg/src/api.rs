@@ -1,21 +1,17 @@
-use std::{
-    collections::BTreeMap,
-    fs::File,
-    io::{BufRead, BufReader, Write},
-};
+use std::{collections::BTreeMap, time::Duration};
 
-use crate::dkg::{combine_dealings, combine_signatures, generate_shares};
+use crate::{aggregation::Aggregation, dissemination::Dissemination, dkg::generate_shares};
 
-use bls12_381::{G1Affine, G1Projective};
-use group::Curve;
-use ic_crypto_internal_threshold_sig_bls12381::crypto::{
-    sign_message, verify_combined_sig, verify_individual_sig,
+use bls12_381::Scalar;
+use ic_crypto_internal_threshold_sig_bls12381::types::PublicKey;
+use networking::{run_threshold_node, Combined, Flow, ThresholdScheme, Topology};
+use sign::{
+    accountable::{Accountability, Accountable, Contribution},
+    scheme::G1,
+    Coefficients, PrefixKeys, Signable, SignatureFile, SignatureGroup, Signing,
 };
-use networking::Node;
-use rand::seq::SliceRandom;
-use tokio_stream::StreamExt;
 use types::{
-    bivariate::{Dealing, Message},
+    bivariate::{Dealing, PublicCoefficients},
     Id,
 };
 
@@ -28,372 +24,301 @@ pub fn write_dealing_to_file(nodes: (u32, u32), threshold: (usize, usize)) {
         bincode::serialize(&dealing.serialize()).unwrap(),
     )
     .unwrap();
-}
-
-// setup for threshold signatures
-pub async fn run_threshold_signature(
-    my_id: (usize, usize),
-    nodes: (u32, u32),
-    threshold: (usize, usize),
-    aws: bool,
-) {
-    let addresses = {
-        let mut addresses = BTreeMap::new();
-        if aws {
-            let mut reader = BufReader::new(File::open("addresses").unwrap());
-
-            for i in 0..nodes.0 {
-                for j in 0..nodes.1 {
-                    let mut addr = String::new();
-                    reader.read_line(&mut addr).unwrap();
-                    addr.pop();
-                    addresses.insert(Id::Bivariate(i as usize, j as usize), addr);
-                }
-            }
-        } else {
-            let mut port = 30000;
+    Coefficients::Bivariate(dealing.0)
+        .write("bivariate_public_coefficients")
+        .unwrap();
 
-            for i in 0..nodes.0 {
-                for j in 0..nodes.1 {
-                    addresses.insert(
-                        Id::Bivariate(i as usize, j as usize),
-                        format!("127.0.0.1:{}", port),
-                    );
-                    port += 1;
-                }
-            }
+    // key files of an earlier refresh belong to the old dealing
+    for i in 0..nodes.0 as usize {
+        for j in 0..nodes.1 as usize {
+            let _ = std::fs::remove_file(key_file((i, j)));
         }
-        addresses
-    };
+    }
+}
 
-    run_single_node_threshold_signature(my_id, nodes, threshold, addresses).await;
+// addresses of every node, read from the addresses file on aws and on local ports otherwise
+pub(crate) fn read_addresses(nodes: (u32, u32), aws: bool) -> BTreeMap<Id, String> {
+    networking::read_addresses(
+        (0..nodes.0 as usize).flat_map(|i| (0..nodes.1 as usize).map(move |j| Id::Bivariate(i, j))),
+        aws,
+    )
 }
 
-// run threshold signature
-pub async fn run_single_node_threshold_signature(
+// the key file a refresh writes for node `my_id`, it takes the place of the share file
+pub(crate) fn key_file(my_id: (usize, usize)) -> String {
+    format!("bivariate_key_{}_{}", my_id.0, my_id.1)
+}
+
+// the public coefficients and secret share of node `my_id`, from its key file if a refresh
+// wrote one and from the share file otherwise
+pub(crate) fn read_key(
     my_id: (usize, usize),
     nodes: (u32, u32),
     threshold: (usize, usize),
-    addresses: BTreeMap<Id, String>,
-) {
-    let msg: [u8; 32] = [0; 32];
-
-    // ids of all nodes not in this group
-    let ids = addresses
-        .iter()
-        .filter_map(|(id, _)| match id {
-            Id::Bivariate(i, _) => {
-                if *i == my_id.0 {
-                    None
-                } else {
-                    Some(*id)
-                }
-            }
-            _ => None,
-        })
-        .collect::<Vec<Id>>();
-
-    // ids of nodes in this group
-    let group_ids = addresses
-        .iter()
-        .filter_map(|(id, _)| match id {
-            Id::Bivariate(i, j) => {
-                if *i == my_id.0 && *j != my_id.1 {
-                    Some(*id)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .collect::<Vec<Id>>();
-
-    // read dealing and compute keys
-    let dealing: (Vec<u8>, Vec<u8>) = bincode::deserialize(
-        &std::fs::read("bivariate_shares").expect("unable to read share file"),
-    )
-    .expect("unable to deserialize file");
-    let dealing = Dealing::deserialize(dealing.0, dealing.1, nodes.1 as usize, threshold.1);
-
-    let pk = dealing
-        .0
-        .individual_public_key((my_id.0 as u32, my_id.1 as u32));
-
-    let sk = dealing.1[my_id.0][my_id.1];
-    let group_pk = dealing.0.group_public_key(my_id.0 as u32);
-    let whole_pk = dealing.0.public_key();
-
-    let mut group_partial_sigs = BTreeMap::new();
-    let mut all_group_sigs = BTreeMap::new();
-
-    let mut node = Node::new(addresses, Id::Bivariate(my_id.0, my_id.1)).await;
-
-    let time = std::time::Instant::now();
-    let t = std::time::Instant::now();
-
-    // sign and verify
-    let my_sig = sign_message(&msg, &sk);
-    let sign_time = t.elapsed();
-    let t = std::time::Instant::now();
-    verify_individual_sig(&msg, my_sig, pk).unwrap();
-    let verify_time = t.elapsed();
-
-    node.broadcast(&my_sig.to_affine().to_uncompressed(), group_ids)
-        .await;
-
-    group_partial_sigs.insert(my_id.1, my_sig);
-
-    // wait for t' sigs from group
-    while group_partial_sigs.len() < threshold.1 {
-        let (id, share) = node.recv.next().await.expect("failed to read message");
-        let sig = G1Projective::from(
-            G1Affine::from_uncompressed_unchecked(&share.try_into().unwrap()).unwrap(),
-        );
-
-        // verify the signature
-        match id {
-            Id::Bivariate(i, j) => {
-                if i == my_id.0 {
-                    verify_individual_sig(
-                        &msg,
-                        sig,
-                        dealing.0.individual_public_key((i as u32, j as u32)),
-                    )
-                    .unwrap();
-                    group_partial_sigs.insert(j, sig);
-                } else {
-                    if !all_group_sigs.contains_key(&i) {
-                        all_group_sigs.insert(i, sig);
-                    }
-                }
-            }
-            _ => (),
+) -> (PublicCoefficients, Scalar) {
+    match std::fs::read(key_file(my_id)) {
+        Ok(bytes) => deserialize_key(&bytes, threshold).expect("unable to deserialize key file"),
+        Err(_) => {
+            let dealing = read_dealing(nodes, threshold);
+            (dealing.0, dealing.1[my_id.0][my_id.1])
         }
     }
+}
 
-    // combine the group signature and verify it
-    let t = std::time::Instant::now();
-    let group_sig = combine_signatures(&group_partial_sigs, threshold.1 as usize).unwrap();
-    let combine_time_group = t.elapsed();
-    let t = std::time::Instant::now();
-    verify_combined_sig(&msg, group_sig, group_pk).unwrap();
-    let verify_combined_time = t.elapsed();
-    all_group_sigs.insert(my_id.0, group_sig);
-
-    // randomly select n log n nodes to broadcast the group signature
-    let mut rng = rand::thread_rng();
-    let selection = nodes.1 * (((nodes.1 as f64).log(10.0) as u32) + 1);
-    let to: Vec<Id> = ids
-        .choose_multiple(&mut rng, selection as usize)
-        .map(|e| *e)
-        .collect();
-    node.broadcast(&group_sig.to_affine().to_uncompressed(), to)
-        .await;
-
-    // wait for t sigs from all nodes
-    while all_group_sigs.len() < threshold.0 {
-        let (id, share) = node.recv.next().await.expect("failed to read message");
-        let sig = G1Projective::from(
-            G1Affine::from_uncompressed_unchecked(&share.try_into().unwrap()).unwrap(),
-        );
+// write the key file of node `my_id`
+pub(crate) fn write_key(my_id: (usize, usize), key: (PublicCoefficients, Scalar)) {
+    std::fs::write(key_file(my_id), serialize_key(key)).unwrap();
+}
 
-        match id {
-            Id::Bivariate(i, j) => {
-                if i == my_id.0 {
-                    group_partial_sigs.insert(j, sig);
-                } else {
-                    if !all_group_sigs.contains_key(&i) {
-                        verify_individual_sig(&msg, sig, dealing.0.group_public_key(i as u32))
-                            .unwrap();
-                        all_group_sigs.insert(i, sig);
-                    }
-                }
-            }
-            _ => (),
+// the public key of bivariate_public_coefficients, which refreshes and reshares keep
+pub(crate) fn read_public_key() -> Result<PublicKey, String> {
+    match Coefficients::read("bivariate_public_coefficients")? {
+        Coefficients::Bivariate(coefficients) => Ok(coefficients.public_key()),
+        Coefficients::Univariate(_) => {
+            Err("bivariate_public_coefficients isn't bivariate".to_string())
         }
     }
+}
 
-    // combine and verify the final signatures
-    let t = std::time::Instant::now();
-    let final_sig = combine_signatures(&all_group_sigs, threshold.0 as usize).unwrap();
-    let combined_time = t.elapsed();
-    let t = std::time::Instant::now();
-    verify_combined_sig(&msg, final_sig, whole_pk).unwrap();
-    let verify_total_time = t.elapsed();
+// public coefficients and a scalar, as a dealing for a single node
+pub(crate) fn serialize_key(key: (PublicCoefficients, Scalar)) -> Vec<u8> {
+    bincode::serialize(&Dealing(key.0, vec![vec![key.1]]).serialize()).unwrap()
+}
 
-    // shutdown and record results
-    let total_time = time.elapsed();
-    std::thread::sleep(std::time::Duration::from_secs(1));
-    node.shutdown();
-    println!("total_time: {:?}", total_time);
-    let filename = format!(
-        "results/bivariate_threshold_signatures_{},{}_{},{}",
-        nodes.0, nodes.1, threshold.0, threshold.1
-    );
-    let mut file = std::fs::OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(filename)
-        .unwrap();
+// the key serialize_key wrote, None if it doesn't fit the thresholds or a point or the share is
+// invalid
+pub(crate) fn deserialize_key(
+    bytes: &[u8],
+    threshold: (usize, usize),
+) -> Option<(PublicCoefficients, Scalar)> {
+    let (coefficients, share): (Vec<u8>, Vec<u8>) = bincode::deserialize(bytes).ok()?;
+    let key = Dealing::deserialize(&coefficients, &share, (1, 1), threshold)?;
+    Some((key.0, key.1[0][0]))
+}
 
-    file.write_all(
-        format!(
-            "{:?},{:?},{:?},{:?},{:?},{:?},{:?}\n",
-            total_time,
-            sign_time,
-            verify_time,
-            combine_time_group,
-            verify_combined_time,
-            combined_time,
-            verify_total_time
-        )
-        .as_bytes(),
+// read the dealing written by write_dealing_to_file
+pub(crate) fn read_dealing(nodes: (u32, u32), threshold: (usize, usize)) -> Dealing {
+    let dealing: (Vec<u8>, Vec<u8>) = bincode::deserialize(
+        &std::fs::read("bivariate_shares").expect("unable to read share file"),
     )
-    .unwrap();
+    .expect("unable to deserialize file");
+    Dealing::deserialize(&dealing.0, &dealing.1, nodes, threshold)
+        .expect("share file doesn't fit the layout")
 }
 
-// setup to run the dkg
-pub async fn run_dkg(
+// setup for threshold signatures over `signable`, the signature is written to `output` if set
+// and the groups and members it was combined from to `accountability` if set. An error if the
+// run fails, a file can't be written or the record doesn't verify
+pub async fn run_threshold_signature(
     my_id: (usize, usize),
     nodes: (u32, u32),
     threshold: (usize, usize),
     aws: bool,
-) {
-    let addresses = {
-        let mut addresses = BTreeMap::new();
-        if aws {
-            let mut reader = BufReader::new(File::open("addresses").unwrap());
-
-            for i in 0..nodes.0 {
-                for j in 0..nodes.1 {
-                    let mut addr = String::new();
-                    reader.read_line(&mut addr).unwrap();
-                    addr.pop();
-                    addresses.insert(Id::Bivariate(i as usize, j as usize), addr);
-                }
-            }
-        } else {
-            let mut port = 30000;
+    topology: Topology,
+    dissemination: Dissemination,
+    aggregation: Aggregation,
+    signable: Signable,
+    output: Option<String>,
+    accountability: Option<String>,
+) -> Result<(), String> {
+    let addresses = read_addresses(nodes, aws);
+
+    run_single_node_threshold_signature(
+        my_id,
+        nodes,
+        threshold,
+        addresses,
+        topology,
+        dissemination,
+        aggregation,
+        signable,
+        output,
+        accountability,
+    )
+    .await
+}
 
-            for i in 0..nodes.0 {
-                for j in 0..nodes.1 {
-                    addresses.insert(
-                        Id::Bivariate(i as usize, j as usize),
-                        format!("127.0.0.1:{}", port),
-                    );
-                    port += 1;
-                }
-            }
+// run threshold signature
+pub async fn run_single_node_threshold_signature(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    addresses: BTreeMap<Id, String>,
+    topology: Topology,
+    dissemination: Dissemination,
+    aggregation: Aggregation,
+    signable: Signable,
+    output: Option<String>,
+    accountability: Option<String>,
+) -> Result<(), String> {
+    // the domain separated bytes we sign
+    let msg = signable.bytes();
+    let (coefficients, sk) = read_key(my_id, nodes, threshold);
+    let leader = aggregation.leader_of(&msg, my_id.0, nodes.1 as usize);
+    let flow = nested_flow(my_id, nodes, threshold, dissemination, leader);
+    let filename = format!(
+        "results/bivariate_threshold_signatures_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    let signing = Signing::<G1, _>::new(&msg, &coefficients);
+
+    // with accountability group signatures carry the contribution of their group
+    let signature = match accountability {
+        Some(accountability) => {
+            let scheme = Accountable {
+                signing,
+                nodes: (nodes.0 as usize, nodes.1 as usize),
+            };
+            let combined = run_nested(
+                my_id,
+                addresses,
+                topology,
+                (dissemination, aggregation),
+                &scheme,
+                flow,
+                || (G1::sign(&msg, &sk), None),
+                filename,
+            )
+            .await?;
+
+            // the final signature was combined from the first t valid group signatures
+            let used: BTreeMap<usize, Contribution> = combined
+                .used
+                .into_iter()
+                .filter_map(|(i, (_, contribution))| Some((i, contribution?)))
+                .collect();
+            let record = Accountability::new(&used);
+            record.verify(&msg, &coefficients, scheme.nodes)?;
+            record.write(&accountability)?;
+            combined.share.0
+        }
+        None => {
+            run_nested(
+                my_id,
+                addresses,
+                topology,
+                (dissemination, aggregation),
+                &signing,
+                flow,
+                || G1::sign(&msg, &sk),
+                filename,
+            )
+            .await?
+            .share
         }
-        addresses
     };
 
-    run_single_node_dkg(my_id, nodes, threshold, addresses).await;
+    if let Some(output) = output {
+        SignatureFile {
+            signable,
+            public_key: coefficients.public_key(),
+            signature,
+        }
+        .write(&output)?;
+    }
+    Ok(())
 }
 
-// run dkg
-async fn run_single_node_dkg(
+// run node `my_id` for nested signatures in group G under `keys`, recording the results in
+// `filename` like the scheme in G1. Fails like run_nested
+pub(crate) async fn run_nested_signature<G, K>(
     my_id: (usize, usize),
     nodes: (u32, u32),
     threshold: (usize, usize),
     addresses: BTreeMap<Id, String>,
-) {
-    // ids of all nodes
-    let ids = addresses
-        .iter()
-        .filter_map(|(id, _)| {
-            if Id::Bivariate(my_id.0, my_id.1) == *id {
-                None
-            } else {
-                Some(*id)
-            }
-        })
-        .collect::<Vec<Id>>();
-
-    // ids of my group
-    let _group_ids = addresses
-        .iter()
-        .filter_map(|(id, _)| match id {
-            Id::Bivariate(i, j) => {
-                if *i == my_id.0 && *j != my_id.1 {
-                    Some(*id)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .collect::<Vec<Id>>();
-
-    let mut node = Node::new(addresses, Id::Bivariate(my_id.0, my_id.1)).await;
-
-    // generate dealing and send it to all nodes
-    let time = std::time::Instant::now();
-    let t = std::time::Instant::now();
-    let dealing = generate_shares(nodes, threshold);
-    let generate_shares_time = t.elapsed();
-    let (serialized_coefficients, serialized_shares) = dealing.serialize();
-    let msg = Message::Shares(serialized_coefficients, serialized_shares);
-
-    let mut dealings = vec![dealing];
-
-    node.broadcast(&bincode::serialize(&msg).unwrap(), ids)
-        .await;
-
-    // wait for all nodes to send dealing
-    for _ in 0..(nodes.0 * nodes.1) - 1 {
-        let (_, msg) = node.recv.next().await.expect("failed to read message");
+    topology: Topology,
+    (dissemination, aggregation): (Dissemination, Aggregation),
+    msg: &[u8],
+    (keys, sk): (&K, Scalar),
+    filename: String,
+) -> Result<G::Signature, String>
+where
+    G: SignatureGroup,
+    K: PrefixKeys<Key = G::PublicKey>,
+{
+    let leader = aggregation.leader_of(msg, my_id.0, nodes.1 as usize);
+    run_nested(
+        my_id,
+        addresses,
+        topology,
+        (dissemination, aggregation),
+        &Signing::<G, K>::new(msg, keys),
+        nested_flow(my_id, nodes, threshold, dissemination, leader),
+        || G::sign(msg, &sk),
+        filename,
+    )
+    .await
+    .map(|combined| combined.share)
+}
 
-        let _t = std::time::Instant::now();
-        let msg: Message = bincode::deserialize(&msg).unwrap();
-        // TODO: Verify dealing
-        match msg {
-            Message::Shares(serialized_coefficients, serialized_shares) => {
-                dealings.push(Dealing::deserialize(
-                    serialized_coefficients,
-                    serialized_shares,
-                    nodes.1 as usize,
-                    threshold.1,
-                ));
-            }
-        }
+// how node `my_id` passes its shares on: its share to the rest of its group, and the group
+// share to the nodes `dissemination` picks in other groups. With a leader the members wait for
+// the share of the group from it
+pub(crate) fn nested_flow(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    dissemination: Dissemination,
+    leader: Option<(Id, Duration)>,
+) -> Flow {
+    let group_ids = (0..nodes.1 as usize)
+        .filter(|j| *j != my_id.1)
+        .map(|j| Id::Bivariate(my_id.0, j))
+        .collect();
+    Flow {
+        path: vec![my_id.0, my_id.1],
+        thresholds: vec![threshold.0, threshold.1],
+        targets: vec![dissemination.targets(my_id, nodes, threshold.1), group_ids],
+        phases: vec!["top", "group"],
+        leader,
     }
+}
 
-    // extract the keys
-    let t = std::time::Instant::now();
-    let (coefficients, _sk) = combine_dealings(my_id, &dealings);
-    let combined_time = t.elapsed();
-    let _pk = coefficients.individual_public_key((my_id.0 as u32, my_id.1 as u32));
-
-    let msg: [u8; 32] = [0; 32];
-    let t = std::time::Instant::now();
-    let my_sig = sign_message(&msg, &_sk);
-    verify_individual_sig(&msg, my_sig, _pk).unwrap();
-    let sign_time = t.elapsed();
-
-    // shutdown and record results
-    let total_time = time.elapsed();
-    std::thread::sleep(std::time::Duration::from_secs(1));
-    node.shutdown();
-    println!("total_time: {:?}", total_time);
-    let filename = format!(
-        "results/bivariate_dkg_{},{}_{},{}",
-        nodes.0, nodes.1, threshold.0, threshold.1
+// run `scheme` for node `my_id` starting from the share `share` gives, and append the timings to
+// `filename`, suffixed with the topology, dissemination and aggregation when they aren't the
+// defaults. Fails like run_threshold_node
+pub(crate) async fn run_nested<S: ThresholdScheme>(
+    my_id: (usize, usize),
+    addresses: BTreeMap<Id, String>,
+    topology: Topology,
+    (dissemination, aggregation): (Dissemination, Aggregation),
+    scheme: &S,
+    flow: Flow,
+    share: impl FnOnce() -> S::Share,
+    filename: String,
+) -> Result<Combined<S::Share>, String> {
+    let me = Id::Bivariate(my_id.0, my_id.1);
+    let run = run_threshold_node(addresses, me, topology, scheme, flow, share).await?;
+
+    let Combined {
+        combine_time,
+        verify_time,
+        batch_time,
+        ..
+    } = &run.combined;
+    let mut filename = filename;
+    let mut line = format!(
+        "{:?},{:?},{:?},{:?},{:?},{:?},{:?},{:?}",
+        run.total_time,
+        run.share_time,
+        run.verify_time,
+        combine_time[1],
+        verify_time[1],
+        combine_time[0],
+        verify_time[0],
+        batch_time,
     );
-    let mut file = std::fs::OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(filename)
-        .unwrap();
-
-    file.write_all(
-        format!(
-            "{:?},{:?},{:?},{:?}\n",
-            total_time, generate_shares_time, combined_time, sign_time,
-        )
-        .as_bytes(),
-    )
-    .unwrap();
+    // overlay runs go to their own file and also record how many connections the node held
+    if topology != Topology::FullMesh {
+        filename = format!("{}_{}", filename, topology.to_string().replace(':', "-"));
+        line = format!("{},{}", line, run.connection_count);
+    }
+    if dissemination != Dissemination::Random {
+        filename = format!("{}_{}", filename, dissemination);
+    }
+    if aggregation != Aggregation::Every {
+        filename = format!("{}_leader", filename);
+    }
+    run.append_results(&filename, &line, me);
+    Ok(run.combined)
 }
@@ -0,0 +1,106 @@
+use std::{io::Write, time::Duration};
+
+use crate::{
+    api::{read_addresses, read_key},
+    serve::Signer,
+};
+
+use networking::{Node, SessionId, Topology};
+use sign::{
+    beacon::{beacon_message, Chain, Round},
+    scheme::G1,
+};
+use types::Id;
+
+// A random beacon on the nested keys (see sign::beacon)
+//
+// Every period all nodes sign the previous output with the nested signing flow and append the
+// new round to their chain. Round r runs in session BEACON_SESSION | r, every node knows which
+// round comes next so no coordination is needed. A node restarted on its chain file picks up
+// after its last round, so nodes have to be restarted on chains of the same length.
+// A round has one period to finish. A node that misses it stops rather than skipping the round,
+// every later round signs the output of the one before.
+
+// beacon sessions have the top bit set, they never collide with daemon sessions
+const BEACON_SESSION: SessionId = 1 << 63;
+
+// run node (i, j) of the beacon until ctrl-c or until `rounds` more rounds are out
+pub async fn run_beacon(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    topology: Topology,
+    period: Duration,
+    rounds: Option<u64>,
+    chain: &str,
+) {
+    let addresses = read_addresses(nodes, aws);
+    let signer = Signer::<G1, _>::new(my_id, nodes, threshold, read_key(my_id, nodes, threshold));
+    let public_key = signer.public_key();
+
+    // carry on from the chain of a previous run, after checking it's ours
+    let existing = Chain::read(chain).expect("unable to read chain");
+    existing
+        .verify(public_key)
+        .expect("chain doesn't verify against the public key");
+    let mut previous = existing.last_output(&public_key);
+    let first = existing.rounds.len() as u64 + 1;
+    let last = rounds.map(|rounds| first + rounds - 1);
+
+    let mut node = Node::with_topology(addresses, Id::Bivariate(my_id.0, my_id.1), topology).await;
+    node.set_phase("beacon");
+
+    let filename = format!(
+        "results/beacon_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .append(true)
+        .create(true)
+        .open(&filename)
+        .unwrap();
+
+    let start = tokio::time::Instant::now();
+    let mut r = first;
+    while last.map_or(true, |last| r <= last) {
+        let session = node.open_session(BEACON_SESSION | r).unwrap();
+        let t = std::time::Instant::now();
+        let message = beacon_message(&previous).bytes();
+        let signature = tokio::select! {
+            signature = tokio::time::timeout(period, signer.sign(session, message)) => {
+                signature.unwrap_or_else(|_| Err(format!("timed out after {:?}", period)))
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let round_time = t.elapsed();
+        let round = match signature {
+            Ok(signature) => Round::new(r, signature),
+            Err(err) => {
+                println!("round {}: {}", r, err);
+                break;
+            }
+        };
+
+        Chain::append(chain, &round).expect("unable to write chain");
+        println!("round {}: {}", r, hex::encode(round.output));
+        file.write_all(format!("{},{:?}\n", r, round_time).as_bytes())
+            .unwrap();
+        previous = round.output;
+
+        // rounds start on a fixed schedule, a slow round doesn't delay the ones after it
+        tokio::select! {
+            _ = tokio::time::sleep_until(start + period * (r - first + 1) as u32) => (),
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        r += 1;
+    }
+
+    std::thread::sleep(Duration::from_secs(1));
+    node.shutdown();
+    node.traffic().append_per_peer(
+        &format!("{}_traffic", filename),
+        Id::Bivariate(my_id.0, my_id.1),
+    );
+}
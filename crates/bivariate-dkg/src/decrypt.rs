@@ -0,0 +1,69 @@
+use crate::{
+    api::{nested_flow, read_addresses, read_key},
+    dissemination::Dissemination,
+};
+
+use networking::{run_threshold_node, Combined, Topology};
+use sign::decrypt::{decrypt, decryption_share, Ciphertext, Decryption};
+use types::Id;
+
+// run a node for nested threshold decryption of `ciphertext` (see sign::decrypt)
+// the flow of threshold signatures, with decryption shares in place of signature shares. Fails if
+// the ciphertext is malformed, the run fails or the combined key doesn't decrypt it
+pub async fn run_threshold_decryption(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    topology: Topology,
+    dissemination: Dissemination,
+    ciphertext: Ciphertext,
+    output: Option<String>,
+) -> Result<(), String> {
+    let addresses = read_addresses(nodes, aws);
+    ciphertext.check()?;
+
+    let (coefficients, sk) = read_key(my_id, nodes, threshold);
+    let scheme = Decryption {
+        ciphertext: &ciphertext,
+        keys: &coefficients,
+    };
+    let me = Id::Bivariate(my_id.0, my_id.1);
+    let flow = nested_flow(my_id, nodes, threshold, dissemination, None);
+    let run = run_threshold_node(addresses, me, topology, &scheme, flow, || {
+        decryption_share(&ciphertext, &sk)
+    })
+    .await?;
+
+    // decrypt with the combined key
+    let Combined {
+        share: key,
+        combine_time,
+        verify_time,
+        batch_time,
+        ..
+    } = &run.combined;
+    let t = std::time::Instant::now();
+    let plaintext = decrypt(&ciphertext, *key)?;
+    let decrypt_time = t.elapsed();
+
+    match output {
+        Some(path) => std::fs::write(&path, &plaintext).expect("unable to write plaintext"),
+        None => println!("plaintext: {}", hex::encode(&plaintext)),
+    }
+    let filename = format!(
+        "results/bivariate_threshold_decryption_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    let line = format!(
+        "{:?},{:?},{:?},{:?},{:?},{:?}",
+        run.total_time,
+        run.share_time,
+        run.verify_time + verify_time.iter().sum::<std::time::Duration>() + *batch_time,
+        combine_time[1],
+        combine_time[0],
+        decrypt_time,
+    );
+    run.append_results(&filename, &line, me);
+    Ok(())
+}
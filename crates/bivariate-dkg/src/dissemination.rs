@@ -0,0 +1,65 @@
+use std::{fmt, str::FromStr};
+
+use rand::seq::SliceRandom;
+use types::Id;
+
+// Who a node sends its group signature to once it has combined it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dissemination {
+    // n·(⌊log10 n⌋ + 1) random nodes outside the group. Cheap, but a node can be left without
+    // t group signatures and wait forever
+    Random,
+    // member j of a group sends to members j, j + 1, ..., j + m - t' (mod m) of every other group.
+    // Every node hears from m - t' + 1 members of each group, so one of them is live whenever the
+    // group has the t' live members it needs to sign. With t groups that aren't crashed every
+    // node gets t group signatures
+    Schedule,
+}
+
+impl Dissemination {
+    // the nodes (i, j) sends its group signature to, for n groups of m nodes and group threshold
+    // t', which has to be checked with types::bivariate::check_threshold first
+    pub fn targets(&self, my_id: (usize, usize), nodes: (u32, u32), t_prime: usize) -> Vec<Id> {
+        let (n, m) = (nodes.0 as usize, nodes.1 as usize);
+        match self {
+            Dissemination::Random => {
+                let ids: Vec<Id> = (0..n)
+                    .filter(|i| *i != my_id.0)
+                    .flat_map(|i| (0..m).map(move |j| Id::Bivariate(i, j)))
+                    .collect();
+                // ⌊log10 n⌋ + 1 is the number of digits of n, which floats can get wrong at powers
+                // of ten
+                let selection = n * n.to_string().len();
+                ids.choose_multiple(&mut rand::thread_rng(), selection)
+                    .copied()
+                    .collect()
+            }
+            Dissemination::Schedule => (0..n)
+                .filter(|i| *i != my_id.0)
+                .flat_map(|i| (0..=m - t_prime).map(move |s| Id::Bivariate(i, (my_id.1 + s) % m)))
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Dissemination {
+    type Err = String;
+
+    // parses "random" or "schedule"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Dissemination::Random),
+            "schedule" => Ok(Dissemination::Schedule),
+            _ => Err(format!("unknown dissemination: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Dissemination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dissemination::Random => write!(f, "random"),
+            Dissemination::Schedule => write!(f, "schedule"),
+        }
+    }
+}
@@ -1,29 +1,213 @@
-use std::{
-    collections::BTreeMap,
-    ops::{Add, AddAssign, MulAssign},
-};
+use std::ops::{Add, AddAssign, MulAssign};
 
-use bls12_381::{G1Projective, Scalar};
-use ic_crypto_internal_threshold_sig_bls12381::crypto::x_for_index;
+use bls12_381::{G2Projective, Scalar};
+use ic_crypto_internal_bls12381_common::random_bls12_381_scalar;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::x_for_index, types::PublicKey};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use types::bivariate::{Dealing, Polynomial, PublicCoefficients};
 
 // generate shares for a dealing
-pub fn generate_shares((n, m): (u32, u32), (t, t_prime): (usize, usize)) -> Dealing {
+pub fn generate_shares(nodes: (u32, u32), threshold: (usize, usize)) -> Dealing {
+    Dealing::generate(nodes, threshold)
+}
+
+// generate shares of a random polynomial with f(0, 0) = 0 for a proactive refresh,
+// adding them to the shares of a key changes every share but not the public key
+pub fn generate_refresh((n, m): (u32, u32), (t, t_prime): (usize, usize)) -> Dealing {
     let seed = rand::random::<[u8; 32]>();
     let mut rng = ChaChaRng::from_seed(seed);
-    let poly = Polynomial::random((t, t_prime), &mut rng);
+    let mut poly = Polynomial::random((t, t_prime), &mut rng);
+    poly.coefficients[0][0] = Scalar::zero();
 
-    let shares = (0..n)
-        .map(|i| {
-            (0..m)
-                .map(|j| poly.evaluate_at(&x_for_index(i), &x_for_index(j)))
-                .collect::<Vec<Scalar>>()
+    Dealing::deal(&poly, (n, m))
+}
+
+// check a refresh dealing has f(0, 0) = 0, so it keeps the public key,
+// and that `share`, which it dealt to node `index`, matches its public coefficients
+pub fn verify_refresh(
+    coefficients: &PublicCoefficients,
+    share: Scalar,
+    index: (usize, usize),
+) -> Result<(), String> {
+    if coefficients.coefficients[0][0].0 != G2Projective::identity() {
+        return Err("refresh dealing changes the public key".to_string());
+    }
+    if G2Projective::generator() * share
+        != coefficients
+            .individual_public_key((index.0 as u32, index.1 as u32))
+            .0
+    {
+        return Err(format!(
+            "share of {:?} doesn't match the coefficients",
+            index
+        ));
+    }
+    Ok(())
+}
+
+// check the share of node `index` in a dealing matches its public coefficients
+pub fn verify_dealt_share(dealing: &Dealing, index: (usize, usize)) -> Result<(), String> {
+    let public_key = dealing
+        .0
+        .individual_public_key((index.0 as u32, index.1 as u32));
+    if G2Projective::generator() * dealing.1[index.0][index.1] != public_key.0 {
+        return Err(format!(
+            "share of {:?} doesn't match the coefficients",
+            index
+        ));
+    }
+    Ok(())
+}
+
+// add the refresh dealings a node got, their public coefficients and its share, to its key
+pub fn apply_refresh(
+    key: (PublicCoefficients, Scalar),
+    dealt: &[(&PublicCoefficients, Scalar)],
+) -> (PublicCoefficients, Scalar) {
+    dealt
+        .iter()
+        .fold(key, |(coefficients, share), (dealt, dealt_share)| {
+            (coefficients.add(dealt), share.add(dealt_share))
         })
-        .collect::<Vec<Vec<Scalar>>>();
-    let public_coefficients = PublicCoefficients::from(&poly);
-    Dealing(public_coefficients, shares)
+}
+
+// the lagrange coefficient of the evaluation at `index` when interpolating at `target`
+// from the evaluations at `indices`
+pub fn lagrange_coefficient(indices: &[usize], index: usize, target: usize) -> Scalar {
+    lagrange_at(indices, index, x_for_index(target as u32))
+}
+
+// the lagrange coefficient of the evaluation at `index` when interpolating at `at`
+fn lagrange_at(indices: &[usize], index: usize, at: Scalar) -> Scalar {
+    let x = x_for_index(index as u32);
+    indices
+        .iter()
+        .filter(|k| **k != index)
+        .fold(Scalar::one(), |coefficient, k| {
+            let x_k = x_for_index(*k as u32);
+            coefficient * (at - x_k) * (x - x_k).invert().unwrap()
+        })
+}
+
+// random masks a recovery helper sends to the other helpers, one per helper
+pub fn recovery_masks(count: usize) -> Vec<Scalar> {
+    let seed = rand::random::<[u8; 32]>();
+    let mut rng = ChaChaRng::from_seed(seed);
+    (0..count)
+        .map(|_| random_bls12_381_scalar(&mut rng))
+        .collect()
+}
+
+// the contribution of a helper to a recovery: its share weighted by its lagrange coefficient,
+// minus the masks it sent to the other helpers, plus the masks it got from them.
+// Masks cancel out in the sum of all contributions, which is the lost share
+pub fn blind_recovery_share(
+    share: Scalar,
+    coefficient: Scalar,
+    sent: &[Scalar],
+    received: &[Scalar],
+) -> Scalar {
+    let sent: Scalar = sent.iter().sum();
+    let received: Scalar = received.iter().sum();
+    share * coefficient - sent + received
+}
+
+// sum the contributions of the helpers into the share of node `index` and check it against
+// the public coefficients
+pub fn recover_share(
+    contributions: &[Scalar],
+    coefficients: &PublicCoefficients,
+    index: (usize, usize),
+) -> Result<Scalar, String> {
+    let share: Scalar = contributions.iter().sum();
+    let public_key = coefficients.individual_public_key((index.0 as u32, index.1 as u32));
+    if G2Projective::generator() * share != public_key.0 {
+        return Err(format!(
+            "recovered share of {:?} doesn't match its public key",
+            index
+        ));
+    }
+    Ok(share)
+}
+
+// generate shares of a random polynomial for the new layout of a reshare, with f(0, 0) the
+// dealer's `share` of the old key
+pub fn generate_reshare(
+    share: Scalar,
+    (n, m): (u32, u32),
+    (t, t_prime): (usize, usize),
+) -> Dealing {
+    let seed = rand::random::<[u8; 32]>();
+    let mut rng = ChaChaRng::from_seed(seed);
+    let mut poly = Polynomial::random((t, t_prime), &mut rng);
+    poly.coefficients[0][0] = share;
+
+    Dealing::deal(&poly, (n, m))
+}
+
+// check a reshare dealing shares the old share of `dealer`, that is that f(0, 0) is committed to
+// its individual public key under the old coefficients
+pub fn verify_reshare(
+    dealing: &Dealing,
+    old: &PublicCoefficients,
+    dealer: (usize, usize),
+) -> Result<(), String> {
+    let public_key = old.individual_public_key((dealer.0 as u32, dealer.1 as u32));
+    if dealing.0.coefficients[0][0] != public_key {
+        return Err(format!(
+            "reshare dealing of {:?} doesn't share its old share",
+            dealer
+        ));
+    }
+    Ok(())
+}
+
+// the weight of the share of each dealer in the old secret, for t' dealers from each of t groups:
+// the lagrange coefficient of its group at 0 times its own within the group at 0
+pub fn reshare_weights(dealers: &[(usize, usize)]) -> Vec<Scalar> {
+    let mut groups: Vec<usize> = dealers.iter().map(|(i, _)| *i).collect();
+    groups.dedup();
+    dealers
+        .iter()
+        .map(|(i, j)| {
+            let members: Vec<usize> = dealers
+                .iter()
+                .filter(|(k, _)| k == i)
+                .map(|(_, l)| *l)
+                .collect();
+            lagrange_at(&groups, *i, Scalar::zero()) * lagrange_at(&members, *j, Scalar::zero())
+        })
+        .collect()
+}
+
+// sum the weighted reshare dealings into the new public coefficients and share of node `index`,
+// f(0, 0) of the sum is the old secret so the public key stays the same
+pub fn combine_reshare(
+    index: (usize, usize),
+    dealings: &[(Scalar, &Dealing)],
+) -> (PublicCoefficients, Scalar) {
+    dealings
+        .iter()
+        .map(|(weight, dealing)| {
+            let coefficients = PublicCoefficients {
+                coefficients: dealing
+                    .0
+                    .coefficients
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|coefficient| PublicKey(coefficient.0 * weight))
+                            .collect()
+                    })
+                    .collect(),
+            };
+            (coefficients, dealing.1[index.0][index.1] * weight)
+        })
+        .reduce(|(coefficients, share), (other, other_share)| {
+            (coefficients.add(&other), share + other_share)
+        })
+        .expect("no reshare dealings")
 }
 
 // generate shares for nidkg
@@ -68,36 +252,3 @@ pub fn generate_shares_for_nidkg(
 
     (Dealing(public_coefficients, shares), pcs)
 }
-
-// combine the dealings by adding shares
-pub fn combine_dealings(
-    index: (usize, usize),
-    dealings: &Vec<Dealing>,
-) -> (PublicCoefficients, Scalar) {
-    dealings.iter().skip(1).fold(
-        (dealings[0].0.clone(), dealings[0].1[index.0][index.1]),
-        |(coefficients, shares), dealing| {
-            (
-                coefficients.add(&dealing.0),
-                shares.add(dealing.1[index.0][index.1]),
-            )
-        },
-    )
-}
-
-// TODO: move this to a common crate as this fn is duplicated in univar version
-// combine signatures with interpolation,
-pub fn combine_signatures(
-    signatures: &BTreeMap<usize, G1Projective>,
-    t: usize,
-) -> Result<G1Projective, String> {
-    if signatures.len() < t {
-        return Err("Invalid Threshold".to_string());
-    }
-
-    let signatures: Vec<(Scalar, G1Projective)> = signatures
-        .iter()
-        .map(|(k, v)| (x_for_index(*k as u32), *v))
-        .collect();
-    Ok(PublicCoefficients::interpolate_g1(&signatures).expect("Duplicate indices"))
-}
@@ -0,0 +1,80 @@
+use crate::{
+    aggregation::Aggregation,
+    api::{read_addresses, run_nested_signature},
+    dissemination::Dissemination,
+};
+
+use bls12_381::Scalar;
+use networking::Topology;
+use sign::{scheme::G2, Signable};
+use types::g1::{Dealing, PublicCoefficients};
+
+// The nested scheme with signatures in G2 and keys in G1
+//
+// Signing runs the same nested flow as api.rs with the group swapped, and the dkg the same
+// machine on dealings with keys in G1.
+
+// write a dealing with keys in G1 for threshold signatures, and its public coefficients
+// encoded like bivariate_public_coefficients
+pub fn write_dealing_to_file(nodes: (u32, u32), threshold: (usize, usize)) {
+    let dealing = Dealing::generate(nodes, threshold);
+
+    std::fs::write(
+        "bivariate_shares_g2",
+        bincode::serialize(&dealing.serialize()).unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        "bivariate_public_coefficients_g2",
+        bincode::serialize(&(dealing.0.serialize(), threshold.1)).unwrap(),
+    )
+    .unwrap();
+}
+
+// the public coefficients and secret share of node `my_id` from the share file
+pub(crate) fn read_key(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+) -> (PublicCoefficients, Scalar) {
+    let dealing: (Vec<u8>, Vec<u8>) = bincode::deserialize(
+        &std::fs::read("bivariate_shares_g2").expect("unable to read share file"),
+    )
+    .expect("unable to deserialize file");
+    let dealing = Dealing::deserialize(&dealing.0, &dealing.1, nodes, threshold)
+        .expect("share file doesn't fit the layout");
+    (dealing.0, dealing.1[my_id.0][my_id.1])
+}
+
+// run a node for nested threshold signatures in G2 over `signable`, fails if the run does
+pub async fn run_threshold_signature(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    topology: Topology,
+    dissemination: Dissemination,
+    aggregation: Aggregation,
+    signable: Signable,
+) -> Result<(), String> {
+    let addresses = read_addresses(nodes, aws);
+    let (coefficients, sk) = read_key(my_id, nodes, threshold);
+    let filename = format!(
+        "results/bivariate_threshold_signatures_g2_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+
+    run_nested_signature::<G2, _>(
+        my_id,
+        nodes,
+        threshold,
+        addresses,
+        topology,
+        (dissemination, aggregation),
+        &signable.bytes(),
+        (&coefficients, sk),
+        filename,
+    )
+    .await
+    .map(|_| ())
+}
@@ -0,0 +1,75 @@
+use crate::{aggregation::Aggregation, api::run_nested, dissemination::Dissemination};
+
+use networking::{read_addresses, Flow, ThresholdScheme, Topology};
+use sign::{scheme::G1, Signable, SignatureGroup, Signing};
+use types::{
+    heterogeneous::{Dealing, Shape},
+    Id,
+};
+
+// The nested scheme for groups of different sizes with their own thresholds
+// (see types::heterogeneous). Nodes are numbered group by group, so the addresses file and local
+// ports follow the same order.
+
+// write a dealing for groups of different sizes for threshold signatures
+pub fn write_dealing_to_file(shape: &Shape) {
+    let dealing = Dealing::generate(shape);
+
+    std::fs::write(
+        "heterogeneous_shares",
+        bincode::serialize(&dealing.serialize()).unwrap(),
+    )
+    .unwrap();
+}
+
+// read the dealing written by write_dealing_to_file
+fn read_dealing(shape: &Shape) -> Dealing {
+    let dealing: (Vec<u8>, Vec<u8>) = bincode::deserialize(
+        &std::fs::read("heterogeneous_shares").expect("unable to read share file"),
+    )
+    .expect("unable to deserialize file");
+    Dealing::deserialize(&dealing.0, &dealing.1, shape).expect("share file doesn't fit the groups")
+}
+
+// run a node for nested threshold signatures over `signable`
+// the flow of the bivariate scheme with the threshold of our group, and group signatures go to
+// every node outside the group. Fails if the run does
+pub async fn run_threshold_signature(
+    my_id: (usize, usize),
+    shape: Shape,
+    aws: bool,
+    topology: Topology,
+    signable: Signable,
+) -> Result<(), String> {
+    let addresses = read_addresses(shape.nodes().map(|(i, j)| Id::Bivariate(i, j)), aws);
+    let msg = signable.bytes();
+
+    // ids of nodes in this group and outside it
+    let (group_ids, other_ids): (Vec<Id>, Vec<Id>) = addresses
+        .keys()
+        .filter(|id| **id != Id::Bivariate(my_id.0, my_id.1))
+        .copied()
+        .partition(|id| matches!(id, Id::Bivariate(i, _) if *i == my_id.0));
+
+    let dealing = read_dealing(&shape);
+    let sk = dealing.1[my_id.0][my_id.1];
+    let flow = Flow {
+        path: vec![my_id.0, my_id.1],
+        thresholds: vec![shape.t, shape.thresholds[my_id.0]],
+        targets: vec![other_ids, group_ids],
+        phases: vec!["top", "group"],
+        leader: None,
+    };
+    run_nested(
+        my_id,
+        addresses,
+        topology,
+        (Dissemination::Random, Aggregation::Every),
+        &Signing::<G1, _>::new(&msg, &dealing.0),
+        flow,
+        || G1::sign(&msg, &sk),
+        format!("results/heterogeneous_threshold_signatures_{}", shape),
+    )
+    .await
+    .map(|_| ())
+}
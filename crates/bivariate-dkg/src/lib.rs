@@ -1,4 +1,16 @@
+pub mod aggregation;
 pub mod api;
+pub mod beacon;
+pub mod decrypt;
+pub mod dissemination;
 pub mod dkg;
+pub mod g2;
+pub mod heterogeneous;
+pub mod machine;
+pub mod recover;
+pub mod refresh;
+pub mod reshare;
+pub mod serve;
 #[cfg(test)]
 mod tests;
+pub mod trivariate;
@@ -0,0 +1,30 @@
+use networking::{SharingDkg, SharingParams};
+use types::{bivariate::Dealing, Id};
+
+// the layout of n groups of m nodes, BivariateDkg takes it with the node ids from sharing_params
+pub use types::bivariate::Params;
+
+// The bivariate dkg as a state machine without any IO
+//
+// The machine deals when it is created and hands back the messages to send, takes the messages
+// it receives from any transport and gives its key once it holds a dealing from every node.
+// It's networking::SharingDkg on bivariate dealings, the same machine the G1 keyed, heterogeneous
+// and multivariate dkgs run on, so a test can drive any number of nodes in memory and main runs
+// it over a Node with networking::run_dkg_node.
+
+pub type BivariateDkg = SharingDkg<Dealing>;
+
+// the ids of n groups of m nodes, node (i, j) at position i * m + j
+pub fn node_ids((n, m): (u32, u32)) -> Vec<Id> {
+    (0..n as usize)
+        .flat_map(|i| (0..m as usize).map(move |j| Id::Bivariate(i, j)))
+        .collect()
+}
+
+// the machine parameters of a dkg with this layout
+pub fn sharing_params(params: Params) -> SharingParams<Params> {
+    SharingParams {
+        layout: params,
+        ids: node_ids(params.nodes),
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Write,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    api::{deserialize_key, read_addresses, read_key, read_public_key, serialize_key, write_key},
+    dkg::{blind_recovery_share, lagrange_coefficient, recover_share, recovery_masks},
+};
+
+use bls12_381::Scalar;
+use networking::Node;
+use tokio_stream::StreamExt;
+use types::{bivariate::PublicCoefficients, Id};
+
+// Share recovery for a lost node
+//
+// The share f(i, j) of node (i, j) lies on the polynomial f(i, y) of degree t' - 1, held by the
+// members of group i, and on f(x, j) of degree t - 1, held by member j of every group. So t'
+// members of the group, or t nodes of the column, can interpolate it without rerunning the DKG.
+// Each helper weights its share by its lagrange coefficient, sends a random mask to every other
+// helper and hands the lost node its weighted share minus the masks it sent plus the masks it got.
+// The masks cancel in the sum, so the lost node learns its share and nothing about the shares of
+// the helpers, as long as they don't all collude with it. Helpers send their public coefficients
+// along, and the lost node checks the sum against its individual public key before writing it.
+// Coefficients whose public key isn't the published one are left out, and a recovery that doesn't
+// hear from every helper within RECOVERY_TIMEOUT fails.
+
+// how long the lost node waits for contributions, and helpers for masks
+pub const RECOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// which peers of a lost node help it recover
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    // t' members of its group
+    Row,
+    // member j of t other groups
+    Column,
+}
+
+impl Recovery {
+    // helper `k`, member k of the group for rows and member j of group k for columns
+    pub fn helper(&self, lost: (usize, usize), k: usize) -> (usize, usize) {
+        match self {
+            Recovery::Row => (lost.0, k),
+            Recovery::Column => (k, lost.1),
+        }
+    }
+
+    // the number of possible helpers and how many of them are needed
+    fn size(&self, nodes: (u32, u32), threshold: (usize, usize)) -> (usize, usize) {
+        match self {
+            Recovery::Row => (nodes.1 as usize, threshold.1),
+            Recovery::Column => (nodes.0 as usize, threshold.0),
+        }
+    }
+
+    // the first helpers that are enough to recover `lost`
+    pub fn helpers(
+        &self,
+        lost: (usize, usize),
+        nodes: (u32, u32),
+        threshold: (usize, usize),
+    ) -> Vec<(usize, usize)> {
+        let (size, needed) = self.size(nodes, threshold);
+        (0..size)
+            .map(|k| self.helper(lost, k))
+            .filter(|helper| *helper != lost)
+            .take(needed)
+            .collect()
+    }
+
+    // check `helpers` can recover `lost`: they are in the layout, without duplicates or the lost
+    // node itself, and there are enough of them
+    pub fn check_helpers(
+        &self,
+        lost: (usize, usize),
+        nodes: (u32, u32),
+        threshold: (usize, usize),
+        helpers: &[(usize, usize)],
+    ) -> Result<(), String> {
+        if lost.0 >= nodes.0 as usize || lost.1 >= nodes.1 as usize {
+            return Err(format!("lost node {:?} is outside the layout", lost));
+        }
+        let (size, needed) = self.size(nodes, threshold);
+        if let Some(helper) = helpers.iter().find(|helper| self.index(**helper) >= size) {
+            return Err(format!("helper {:?} is outside the layout", helper));
+        }
+        if helpers.contains(&lost) {
+            return Err("the lost node can't help itself".to_string());
+        }
+        if helpers.iter().collect::<BTreeSet<_>>().len() != helpers.len() {
+            return Err("helpers are listed more than once".to_string());
+        }
+        if helpers.len() < needed {
+            return Err(format!(
+                "{} helpers are needed, {} given",
+                needed,
+                helpers.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // the index of `id` along the polynomial that is interpolated
+    pub fn index(&self, id: (usize, usize)) -> usize {
+        match self {
+            Recovery::Row => id.1,
+            Recovery::Column => id.0,
+        }
+    }
+}
+
+impl FromStr for Recovery {
+    type Err = String;
+
+    // parses "row" or "column"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "row" => Ok(Recovery::Row),
+            "column" => Ok(Recovery::Column),
+            _ => Err(format!("unknown recovery: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recovery::Row => write!(f, "row"),
+            Recovery::Column => write!(f, "column"),
+        }
+    }
+}
+
+// run node (i, j) in the recovery of `lost` by `helpers`, the lost node writes its key file.
+// Nodes that are neither lost nor helping return straight away
+pub async fn run_recovery(
+    my_id: (usize, usize),
+    lost: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    recovery: Recovery,
+    helpers: Vec<(usize, usize)>,
+) -> Result<(), String> {
+    recovery.check_helpers(lost, nodes, threshold, &helpers)?;
+    if my_id != lost && !helpers.contains(&my_id) {
+        println!(
+            "{:?} isn't taking part in the recovery of {:?}",
+            my_id, lost
+        );
+        return Ok(());
+    }
+
+    // only the lost node and its helpers connect
+    let mut addresses = read_addresses(nodes, aws);
+    addresses.retain(|id, _| match id {
+        Id::Bivariate(i, j) => (*i, *j) == lost || helpers.contains(&(*i, *j)),
+        _ => false,
+    });
+
+    let mut node = Node::new(addresses, Id::Bivariate(my_id.0, my_id.1)).await;
+    node.set_phase("recovery");
+    let time = std::time::Instant::now();
+
+    let compute_time = if my_id == lost {
+        recover(&mut node, lost, threshold, &helpers).await
+    } else {
+        help(&mut node, my_id, lost, nodes, threshold, recovery, &helpers).await
+    };
+
+    // finish and record results
+    let total_time = time.elapsed();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    node.shutdown();
+    let compute_time = compute_time?;
+    println!("total_time: {:?}", total_time);
+    let traffic = node.traffic();
+
+    let filename = format!(
+        "results/bivariate_recovery_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    if my_id == lost {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .append(true)
+            .create(true)
+            .open(&filename)
+            .unwrap();
+
+        file.write_all(
+            format!(
+                "{},{:?},{:?},{}\n",
+                recovery,
+                total_time,
+                compute_time,
+                traffic.summary()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    }
+    traffic.append_per_peer(
+        &format!("{}_traffic", filename),
+        Id::Bivariate(my_id.0, my_id.1),
+    );
+    Ok(())
+}
+
+// collect a contribution from every helper, sum them and write the key file.
+// Returns the time taken to recover and check the share
+async fn recover(
+    node: &mut Node,
+    lost: (usize, usize),
+    threshold: (usize, usize),
+    helpers: &[(usize, usize)],
+) -> Result<Duration, String> {
+    let public_key = read_public_key()?;
+    let helpers: BTreeSet<Id> = helpers.iter().map(|(i, j)| Id::Bivariate(*i, *j)).collect();
+
+    let mut contributions = BTreeMap::new();
+    let collect = async {
+        while contributions.len() < helpers.len() {
+            let (id, msg) = node.recv.next().await.expect("failed to read message");
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            if !helpers.contains(&id) || contributions.contains_key(&id) {
+                continue;
+            }
+            match deserialize_key(&msg, threshold) {
+                Some(contribution) => {
+                    contributions.insert(id, contribution);
+                }
+                None => println!("invalid contribution from {:?}", id),
+            }
+        }
+    };
+    if tokio::time::timeout(RECOVERY_TIMEOUT, collect)
+        .await
+        .is_err()
+    {
+        let missing: Vec<&Id> = helpers
+            .iter()
+            .filter(|id| !contributions.contains_key(id))
+            .collect();
+        return Err(format!(
+            "no contribution from {:?} after {:?}",
+            missing, RECOVERY_TIMEOUT
+        ));
+    }
+
+    // every contribution is needed for the masks to cancel, but coefficients of another public
+    // key are left out, and of the rest the ones the recovered share matches are kept
+    let t = std::time::Instant::now();
+    let shares: Vec<Scalar> = contributions.values().map(|(_, share)| *share).collect();
+    let mut candidates: Vec<&PublicCoefficients> = Vec::new();
+    for (id, (coefficients, _)) in &contributions {
+        if coefficients.public_key() != public_key {
+            println!("{:?} sent coefficients of another public key", id);
+        } else if !candidates.contains(&coefficients) {
+            candidates.push(coefficients);
+        }
+    }
+    let (coefficients, share) = candidates
+        .into_iter()
+        .find_map(|coefficients| {
+            let share = recover_share(&shares, coefficients, lost).ok()?;
+            Some((coefficients.clone(), share))
+        })
+        .ok_or(format!(
+            "the recovered share of {:?} doesn't match the coefficients of any helper",
+            lost
+        ))?;
+    let compute_time = t.elapsed();
+
+    write_key(lost, (coefficients, share));
+    Ok(compute_time)
+}
+
+// exchange masks with the other helpers and send our contribution to the lost node.
+// Returns the time taken to compute the contribution
+async fn help(
+    node: &mut Node,
+    my_id: (usize, usize),
+    lost: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    recovery: Recovery,
+    helpers: &[(usize, usize)],
+) -> Result<Duration, String> {
+    let (coefficients, share) = read_key(my_id, nodes, threshold);
+    let indices: Vec<usize> = helpers.iter().map(|id| recovery.index(*id)).collect();
+    let others: Vec<(usize, usize)> = helpers.iter().copied().filter(|id| *id != my_id).collect();
+
+    // send a mask to every other helper
+    let sent = recovery_masks(others.len());
+    for (other, mask) in others.iter().zip(&sent) {
+        node.broadcast(&mask.to_bytes(), vec![Id::Bivariate(other.0, other.1)])
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    // wait for a mask from every other helper, keeping the first from each
+    let senders: BTreeSet<Id> = others.iter().map(|(i, j)| Id::Bivariate(*i, *j)).collect();
+    let mut received = BTreeMap::new();
+    let collect = async {
+        while received.len() < senders.len() {
+            let (id, msg) = node.recv.next().await.expect("failed to read message");
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            if !senders.contains(&id) || received.contains_key(&id) {
+                continue;
+            }
+            let mask = msg
+                .as_slice()
+                .try_into()
+                .ok()
+                .and_then(|bytes: [u8; 32]| Option::from(Scalar::from_bytes(&bytes)));
+            match mask {
+                Some(mask) => {
+                    received.insert(id, mask);
+                }
+                None => println!("invalid mask from {:?}", id),
+            }
+        }
+    };
+    if tokio::time::timeout(RECOVERY_TIMEOUT, collect)
+        .await
+        .is_err()
+    {
+        return Err(format!(
+            "only {} of {} masks arrived within {:?}",
+            received.len(),
+            others.len(),
+            RECOVERY_TIMEOUT
+        ));
+    }
+
+    // send our contribution to the lost node
+    let t = std::time::Instant::now();
+    let coefficient = lagrange_coefficient(&indices, recovery.index(my_id), recovery.index(lost));
+    let received: Vec<Scalar> = received.into_values().collect();
+    let contribution = blind_recovery_share(share, coefficient, &sent, &received);
+    let compute_time = t.elapsed();
+    node.broadcast(
+        &serialize_key((coefficients, contribution)),
+        vec![Id::Bivariate(lost.0, lost.1)],
+    )
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(compute_time)
+}
@@ -0,0 +1,260 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    api::{read_addresses, read_key, write_key},
+    dkg::{apply_refresh, generate_refresh, verify_refresh},
+    machine::{node_ids, Params},
+};
+
+use bls12_381::{G2Projective, Scalar};
+use networking::{run_dkg_node, DkgProtocol, Outgoing};
+use types::{
+    bivariate::{PublicCoefficients, RefreshMessage},
+    Id,
+};
+
+// Proactive refresh of a bivariate key
+//
+// Every node deals a random polynomial with f(0, 0) = 0 and adds the shares it gets to its own.
+// Every share changes, so shares stolen before a refresh can't be combined with shares stolen
+// after it, while the public key stays the same. Group keys change along with the shares.
+// The public coefficients of a dealing are reliably broadcast, and each node gets its share of it
+// alone. Dealings that would change the public key are left out by every node. Once a node holds
+// every dealing it reliably broadcasts the dealers whose share didn't match their coefficients.
+// A bad share can't be left out without the other nodes agreeing, so any complaint aborts the
+// refresh at every node and leaves every key as it was.
+
+// the layout and the key of a node before the refresh
+pub struct RefreshParams {
+    pub layout: Params,
+    pub key: (PublicCoefficients, Scalar),
+}
+
+pub struct RefreshDkg {
+    layout: Params,
+    ids: Vec<Id>,
+    index: (usize, usize),
+    key: (PublicCoefficients, Scalar),
+    // our messages, sent on the first call to start
+    messages: Vec<Outgoing<RefreshMessage>>,
+    commitments: BTreeMap<Id, PublicCoefficients>,
+    shares: BTreeMap<Id, Scalar>,
+    // dealers whose coefficients would change the public key
+    left_out: BTreeSet<Id>,
+    // the dealers each node complained about, ours included once we hold every dealing
+    complaints: BTreeMap<Id, Vec<Id>>,
+    // whether poll_output gave our key
+    done: bool,
+}
+
+impl RefreshDkg {
+    // whether we hold everything we need from `id`
+    fn has_dealing(&self, id: &Id) -> bool {
+        self.left_out.contains(id)
+            || (self.commitments.contains_key(id) && self.shares.contains_key(id))
+    }
+
+    // once we hold every dealing, check our shares and send the dealers whose share doesn't
+    // match to every other node
+    fn complain(&mut self) -> Vec<Outgoing<RefreshMessage>> {
+        if self.complaints.contains_key(&self.id())
+            || !self.ids.iter().all(|id| self.has_dealing(id))
+        {
+            return Vec::new();
+        }
+        let complaints: Vec<Id> = self
+            .commitments
+            .iter()
+            .filter(|(id, dealt)| verify_refresh(dealt, self.shares[id], self.index).is_err())
+            .map(|(id, _)| *id)
+            .collect();
+        self.complaints.insert(self.id(), complaints.clone());
+        vec![Outgoing {
+            to: self
+                .ids
+                .iter()
+                .filter(|id| **id != self.id())
+                .copied()
+                .collect(),
+            msg: RefreshMessage::Complaints(complaints),
+        }]
+    }
+}
+
+impl DkgProtocol for RefreshDkg {
+    type Params = RefreshParams;
+    type Message = RefreshMessage;
+    // the refreshed key, or why the refresh was aborted
+    type Output = Result<(PublicCoefficients, Scalar), String>;
+    type Error = String;
+
+    // generate our refresh dealing for node `my_id`
+    fn new(params: RefreshParams, my_id: Id) -> Result<Self, String> {
+        let RefreshParams { layout, key } = params;
+        let ids = node_ids(layout.nodes);
+        let index = match my_id {
+            Id::Bivariate(i, j) if ids.contains(&my_id) => (i, j),
+            _ => return Err(format!("{:?} isn't a node of the refresh", my_id)),
+        };
+
+        let dealing = generate_refresh(layout.nodes, layout.threshold);
+        let others: Vec<Id> = ids.iter().filter(|id| **id != my_id).copied().collect();
+        let mut messages = vec![Outgoing {
+            to: others.clone(),
+            msg: RefreshMessage::Commitments(dealing.0.serialize()),
+        }];
+        for id in others {
+            if let Id::Bivariate(i, j) = id {
+                messages.push(Outgoing {
+                    to: vec![id],
+                    msg: RefreshMessage::Share(dealing.1[i][j].to_bytes().to_vec()),
+                });
+            }
+        }
+
+        Ok(RefreshDkg {
+            layout,
+            ids,
+            index,
+            key,
+            messages,
+            commitments: BTreeMap::from([(my_id, dealing.0)]),
+            shares: BTreeMap::from([(my_id, dealing.1[index.0][index.1])]),
+            left_out: BTreeSet::new(),
+            complaints: BTreeMap::new(),
+            done: false,
+        })
+    }
+
+    fn id(&self) -> Id {
+        Id::Bivariate(self.index.0, self.index.1)
+    }
+
+    fn participants(&self) -> Vec<Id> {
+        self.ids.clone()
+    }
+
+    // the coefficients to every other node and its share to each of them
+    fn start(&mut self) -> Vec<Outgoing<RefreshMessage>> {
+        let mut messages = std::mem::take(&mut self.messages);
+        messages.extend(self.complain());
+        messages
+    }
+
+    // messages that don't fit the layout and second messages of a kind from a node are rejected
+    fn handle_message(
+        &mut self,
+        from: Id,
+        msg: RefreshMessage,
+    ) -> Result<Vec<Outgoing<RefreshMessage>>, String> {
+        if !self.ids.contains(&from) {
+            return Err(format!("{:?} isn't a node of the refresh", from));
+        }
+
+        match msg {
+            RefreshMessage::Commitments(bytes) => {
+                if self.commitments.contains_key(&from) || self.left_out.contains(&from) {
+                    return Err(format!("{:?} already sent its coefficients", from));
+                }
+                let coefficients = PublicCoefficients::from_bytes(&bytes, self.layout.threshold)
+                    .ok_or(format!("invalid coefficients from {:?}", from))?;
+                if coefficients.coefficients[0][0].0 == G2Projective::identity() {
+                    self.commitments.insert(from, coefficients);
+                } else {
+                    self.left_out.insert(from);
+                }
+            }
+            RefreshMessage::Share(bytes) => {
+                if self.shares.contains_key(&from) {
+                    return Err(format!("{:?} already sent a share", from));
+                }
+                let share = bytes
+                    .try_into()
+                    .ok()
+                    .and_then(|bytes: [u8; 32]| Option::from(Scalar::from_bytes(&bytes)))
+                    .ok_or(format!("invalid share from {:?}", from))?;
+                self.shares.insert(from, share);
+            }
+            RefreshMessage::Complaints(complaints) => {
+                if self.complaints.contains_key(&from) {
+                    return Err(format!("{:?} already complained", from));
+                }
+                self.complaints.insert(from, complaints);
+            }
+        }
+        Ok(self.complain())
+    }
+
+    // our key plus every dealing that was kept, once every node said which shares it got didn't
+    // match. Any complaint aborts the refresh
+    fn poll_output(&mut self) -> Option<Self::Output> {
+        if self.done || !self.ids.iter().all(|id| self.complaints.contains_key(id)) {
+            return None;
+        }
+        self.done = true;
+
+        if let Some((id, complaints)) = self.complaints.iter().find(|(_, c)| !c.is_empty()) {
+            return Some(Err(format!(
+                "{:?} got shares that don't match their coefficients from {:?}",
+                id, complaints
+            )));
+        }
+        let dealt: Vec<(&PublicCoefficients, Scalar)> = self
+            .commitments
+            .iter()
+            .map(|(id, dealt)| (dealt, self.shares[id]))
+            .collect();
+        Some(Ok(apply_refresh(self.key.clone(), &dealt)))
+    }
+}
+
+// run node (i, j) in a refresh of its key, the new key is written to its key file.
+// Only `reliable` refreshes run, so every node leaves out the same dealings
+pub async fn run_refresh(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    reliable: bool,
+) -> Result<(), String> {
+    if !reliable {
+        return Err("a refresh needs reliable broadcast, run it with -r".to_string());
+    }
+    let key = read_key(my_id, nodes, threshold);
+    let public_key = key.0.public_key();
+    let filename = format!(
+        "results/bivariate_refresh_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    let params = RefreshParams {
+        layout: Params { nodes, threshold },
+        key,
+    };
+
+    // check the public key stayed the same and our share matches the new coefficients
+    let key = run_dkg_node::<RefreshDkg>(
+        read_addresses(nodes, aws),
+        Id::Bivariate(my_id.0, my_id.1),
+        params,
+        reliable,
+        |key| {
+            if let Ok((coefficients, sk)) = key {
+                assert_eq!(coefficients.public_key(), public_key);
+                assert_eq!(
+                    coefficients
+                        .individual_public_key((my_id.0 as u32, my_id.1 as u32))
+                        .0,
+                    G2Projective::generator() * sk
+                );
+            }
+        },
+        &filename,
+    )
+    .await
+    .map_err(|err| err.to_string())
+    .and_then(|key| key)
+    .map_err(|err| format!("refresh aborted, key unchanged: {}", err))?;
+
+    write_key(my_id, key);
+    Ok(())
+}
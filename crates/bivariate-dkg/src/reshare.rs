@@ -0,0 +1,524 @@
+use std::{collections::BTreeMap, io::Write, str::FromStr, time::Duration};
+
+use crate::{
+    api::{key_file, read_key, read_public_key, write_key},
+    dkg::{combine_reshare, generate_reshare, reshare_weights, verify_dealt_share, verify_reshare},
+};
+
+use bls12_381::G2Projective;
+use ic_crypto_internal_threshold_sig_bls12381::types::PublicKey;
+use networking::{read_addresses, NetworkError, Node, ReliableBroadcast};
+use types::{
+    bivariate::{Dealing, Message, PublicCoefficients},
+    Id,
+};
+
+// Resharing a bivariate key to a new layout of groups
+//
+// Groups can join, retire or change size without a new dkg, and the public key stays the same.
+// Every node of the old layout deals a bivariate polynomial with the new thresholds whose f(0, 0)
+// is its old share, committed to its individual public key under the old coefficients, and sends
+// it along with those coefficients to every node of the new layout. New nodes keep the dealings
+// whose old coefficients are those a majority of the old nodes sent, with the published public
+// key, and that share the right value, take t' of them from each of the first t groups that have
+// enough, and sum them weighted by their lagrange coefficients at 0, first within the group then
+// across groups. The sum shares the old secret, so the new public key is the old one. Dealings go
+// over reliable broadcast, so every new node keeps the same ones. New nodes stop listening once
+// those dealers are settled, and abort if they aren't within RESHARE_TIMEOUT. A share that doesn't
+// match its dealing aborts the reshare at that node.
+
+// how long new nodes wait for the dealings they combine
+pub const RESHARE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// the new layout of a reshare, read from a membership file such as
+//
+// threshold 2,3
+// size 4
+// size 2 5
+// groups 0,2,new
+//
+// where old group 0 stays group 0, old group 1 retires, old group 2 becomes group 1 and a new
+// group joins as group 2. Every group has 4 members but group 2, which has 5. Members of a group
+// that shrinks beyond its new size retire and new members join a group that grows
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Membership {
+    // the new thresholds
+    pub threshold: (usize, usize),
+    // the new number of members in each group
+    pub sizes: Vec<usize>,
+    // the old group each new group continues, None for a group that joins
+    pub groups: Vec<Option<usize>>,
+}
+
+impl Membership {
+    // read a membership file
+    pub fn read(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| format!("unable to read membership file: {}", err))?
+            .parse()
+    }
+
+    // check the layout against the old one
+    pub fn validate(&self, old_nodes: (u32, u32)) -> Result<(), String> {
+        let (t, t_prime) = self.threshold;
+        if self.sizes.len() != self.groups.len() {
+            return Err("every group needs a size".to_string());
+        }
+        if t == 0 || t > self.groups.len() || t_prime == 0 {
+            return Err("thresholds don't fit the new layout".to_string());
+        }
+        if self.sizes.iter().any(|size| *size < t_prime) {
+            return Err("a group is smaller than its threshold".to_string());
+        }
+        let mut continued: Vec<usize> = self.groups.iter().flatten().copied().collect();
+        if continued.iter().any(|i| *i >= old_nodes.0 as usize) {
+            return Err("unknown old group".to_string());
+        }
+        continued.sort_unstable();
+        continued.dedup();
+        if continued.len() != self.groups.iter().flatten().count() {
+            return Err("an old group is continued twice".to_string());
+        }
+        Ok(())
+    }
+
+    // the number of groups and members in the new layout, with as many members as the largest
+    // group. Dealings have shares for every index, smaller groups leave the last ones unused
+    pub fn nodes(&self) -> (u32, u32) {
+        let m = self.sizes.iter().max().copied().unwrap_or(0);
+        (self.groups.len() as u32, m as u32)
+    }
+
+    // whether node `index` is in the new layout
+    pub fn contains(&self, index: (usize, usize)) -> bool {
+        self.sizes
+            .get(index.0)
+            .map_or(false, |size| index.1 < *size)
+    }
+
+    // every node taking part with its network id, old index and new index. Nodes of a group that
+    // continues keep their member index, and retiring groups come after the new layout, so the
+    // network ids follow the addresses file and local ports
+    pub fn processes(
+        &self,
+        old_nodes: (u32, u32),
+    ) -> Vec<(Id, Option<(usize, usize)>, Option<(usize, usize)>)> {
+        let (n, m) = (old_nodes.0 as usize, old_nodes.1 as usize);
+        let mut processes = Vec::new();
+        for (a, (old, size)) in self.groups.iter().zip(&self.sizes).enumerate() {
+            let width = (*size).max(old.map_or(0, |_| m));
+            for b in 0..width {
+                let old_index = old.filter(|_| b < m).map(|i| (i, b));
+                let new_index = (b < *size).then(|| (a, b));
+                processes.push((Id::Bivariate(a, b), old_index, new_index));
+            }
+        }
+        let retired = (0..n).filter(|i| !self.groups.contains(&Some(*i)));
+        for (r, i) in retired.enumerate() {
+            for j in 0..m {
+                processes.push((Id::Bivariate(self.groups.len() + r, j), Some((i, j)), None));
+            }
+        }
+        processes
+    }
+}
+
+impl FromStr for Membership {
+    type Err = String;
+
+    // parses "threshold <t>,<t'>", "size <m>", "size <group> <m>" for a group of another size
+    // and "groups <old group or new>,..." lines, skipping blank lines and comments starting
+    // with '#'
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid number: {}", value))
+        };
+        let (mut threshold, mut size, mut groups) = (None, None, None);
+        let mut resized = Vec::new();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(' ') {
+                Some(("threshold", value)) => {
+                    let (t, t_prime) = value
+                        .trim()
+                        .split_once(',')
+                        .ok_or(format!("invalid threshold: {}", value))?;
+                    threshold = Some((parse(t)?, parse(t_prime)?));
+                }
+                Some(("size", value)) => match value.trim().split_once(' ') {
+                    Some((group, group_size)) => {
+                        resized.push((parse(group)?, parse(group_size.trim())?))
+                    }
+                    None => size = Some(parse(value.trim())?),
+                },
+                Some(("groups", value)) => {
+                    groups = Some(
+                        value
+                            .trim()
+                            .split(',')
+                            .map(|group| match group {
+                                "new" => Ok(None),
+                                _ => parse(group).map(Some),
+                            })
+                            .collect::<Result<Vec<_>, String>>()?,
+                    )
+                }
+                _ => return Err(format!("unknown membership line: {}", line)),
+            }
+        }
+        let groups: Vec<Option<usize>> = groups.ok_or("missing groups")?;
+        let mut sizes = vec![size.ok_or("missing size")?; groups.len()];
+        for (group, group_size) in resized {
+            *sizes
+                .get_mut(group)
+                .ok_or(format!("unknown group: {}", group))? = group_size;
+        }
+        Ok(Membership {
+            threshold: threshold.ok_or("missing threshold")?,
+            sizes,
+            groups,
+        })
+    }
+}
+
+// run a node in a reshare from the old layout to `membership`, given by its old index, its new
+// index or both. New nodes write the new key to their key file, and retiring nodes remove theirs.
+// Only `reliable` reshares run, so every new node sees the same dealings
+pub async fn run_reshare(
+    my_id: (Option<(usize, usize)>, Option<(usize, usize)>),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    membership: Membership,
+    aws: bool,
+    reliable: bool,
+) -> Result<(), String> {
+    if !reliable {
+        return Err("a reshare needs reliable broadcast, run it with -r".to_string());
+    }
+    membership.validate(nodes)?;
+    let processes = membership.processes(nodes);
+    let (me, old_index, new_index) = *processes
+        .iter()
+        .find(|(_, old, new)| {
+            my_id.0.map_or(true, |index| *old == Some(index))
+                && my_id.1.map_or(true, |index| *new == Some(index))
+        })
+        .ok_or("not a node of the old or the new layout")?;
+    let new_nodes = membership.nodes();
+    let new_threshold = membership.threshold;
+
+    let addresses = read_addresses(processes.iter().map(|(id, _, _)| *id), aws);
+    // dealings go to every node of the new layout
+    let receivers: Vec<Id> = processes
+        .iter()
+        .filter(|(_, _, new)| new.is_some())
+        .map(|(id, _, _)| *id)
+        .collect();
+    let old_key = old_index.map(|index| read_key(index, nodes, threshold));
+    // new nodes keep the dealings of old nodes that hold the published key
+    let mut received = match new_index {
+        Some(_) => Some(Received::new(read_public_key()?, nodes)),
+        None => None,
+    };
+
+    let mut node = Node::new(addresses, me).await;
+
+    // deal our old share to the new layout
+    node.set_phase("dealing");
+    let time = std::time::Instant::now();
+    let mut generate_shares_time = Duration::ZERO;
+    let mut msg = Vec::new();
+    if let (Some(index), Some((coefficients, share))) = (old_index, &old_key) {
+        let t = std::time::Instant::now();
+        let dealing = generate_reshare(*share, new_nodes, new_threshold);
+        generate_shares_time = t.elapsed();
+        let (serialized_coefficients, serialized_shares) = dealing.serialize();
+        msg = bincode::serialize(&(
+            coefficients.serialize(),
+            Message::Shares(serialized_coefficients, serialized_shares),
+        ))
+        .unwrap();
+        if let Some(received) = received.as_mut() {
+            received.insert(index, dealing, coefficients.clone());
+        }
+    }
+
+    // new nodes receive dealings until the dealers they combine are settled
+    let settled = async {
+        let mut rbc = ReliableBroadcast::new(&mut node, me, receivers);
+        if old_index.is_some() {
+            rbc.broadcast(&msg).await?;
+        }
+        let received = match received.as_mut() {
+            Some(received) => received,
+            None => return Ok::<_, NetworkError>(None),
+        };
+        loop {
+            if let Some(dealers) = received.pick(nodes, threshold) {
+                return Ok(Some(dealers));
+            }
+            // our own dealing is delivered as well, but we already hold it
+            let (id, msg) = rbc.deliver().await?;
+            if let Some((dealer, dealing, old)) =
+                deserialize_dealing(&processes, id, &msg, threshold, &membership)
+            {
+                received.insert(dealer, dealing, old);
+            }
+        }
+    };
+    let dealers = tokio::time::timeout(RESHARE_TIMEOUT, settled)
+        .await
+        .map_err(|_| {
+            format!(
+                "reshare aborted, dealings missing after {:?}",
+                RESHARE_TIMEOUT
+            )
+        })?
+        .map_err(|err| format!("reshare aborted: {}", err))?;
+
+    let mut verify_time = Duration::ZERO;
+    let mut combined_dealings_time = Duration::ZERO;
+    if let (Some(index), Some(dealers), Some(received)) = (new_index, dealers, received) {
+        let dealers = dealers?;
+        // a bad share for us alone can't be left out without the other nodes agreeing
+        let t = std::time::Instant::now();
+        let dealings: Vec<&Dealing> = dealers
+            .iter()
+            .flat_map(|dealer| received.dealings[dealer].as_ref())
+            .collect();
+        for (dealer, dealing) in dealers.iter().zip(&dealings) {
+            verify_dealt_share(dealing, index)
+                .map_err(|err| format!("reshare aborted by {:?}: {}", dealer, err))?;
+        }
+        verify_time = received.verify_time + t.elapsed();
+
+        // sum the weighted dealings into our new key
+        let t = std::time::Instant::now();
+        let weighted: Vec<_> = reshare_weights(&dealers)
+            .into_iter()
+            .zip(dealings)
+            .collect();
+        let (coefficients, sk) = combine_reshare(index, &weighted);
+        combined_dealings_time = t.elapsed();
+        // dealers that agree on the public key but not on the coefficients end up here
+        if coefficients.public_key() != received.public_key {
+            return Err("reshare aborted, the dealings change the public key".to_string());
+        }
+        assert_eq!(
+            coefficients
+                .individual_public_key((index.0 as u32, index.1 as u32))
+                .0,
+            G2Projective::generator() * sk
+        );
+        write_key(index, (coefficients, sk));
+    }
+
+    // a retiring node's key file is stale, unless a node of the new layout has the same index
+    if let Some(index) = old_index {
+        if !membership.contains(index) {
+            let _ = std::fs::remove_file(key_file(index));
+        }
+    }
+
+    // finish and record results
+    let total_time = time.elapsed();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    node.shutdown();
+    println!("total_time: {:?}", total_time);
+    let traffic = node.traffic();
+
+    let filename = format!(
+        "results/bivariate_reshare_{},{}_{},{}_{},{}_{},{}",
+        nodes.0,
+        nodes.1,
+        threshold.0,
+        threshold.1,
+        new_nodes.0,
+        new_nodes.1,
+        new_threshold.0,
+        new_threshold.1
+    );
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .append(true)
+        .create(true)
+        .open(&filename)
+        .unwrap();
+
+    file.write_all(
+        format!(
+            "{:?},{:?},{:?},{:?},{}\n",
+            total_time,
+            generate_shares_time,
+            verify_time,
+            combined_dealings_time,
+            traffic.summary()
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    traffic.append_per_peer(&format!("{}_traffic", filename), me);
+    Ok(())
+}
+
+// the dealings a new node has received by old index, None for one it left out. Old nodes hold the
+// same coefficients, which a refresh changes without changing the published key, so dealings are
+// checked against the old coefficients a majority of the old nodes sent. Dealings wait until
+// there is one, so every new node leaves out the same dealings whatever order they arrive in
+pub(crate) struct Received {
+    // the published public key
+    public_key: PublicKey,
+    // the number of old nodes
+    old_nodes: usize,
+    // the old coefficients sent so far and how many old nodes sent them
+    votes: Vec<(PublicCoefficients, usize)>,
+    // the old coefficients of the majority once there is one
+    old: Option<PublicCoefficients>,
+    // dealings waiting for the majority
+    waiting: BTreeMap<(usize, usize), (Dealing, PublicCoefficients)>,
+    dealings: BTreeMap<(usize, usize), Option<Dealing>>,
+    // the time taken to check the dealings
+    verify_time: Duration,
+}
+
+impl Received {
+    pub(crate) fn new(public_key: PublicKey, old_nodes: (u32, u32)) -> Self {
+        Self {
+            public_key,
+            old_nodes: (old_nodes.0 * old_nodes.1) as usize,
+            votes: Vec::new(),
+            old: None,
+            waiting: BTreeMap::new(),
+            dealings: BTreeMap::new(),
+            verify_time: Duration::ZERO,
+        }
+    }
+
+    // take the first dealing of `dealer` and the old coefficients it sent, and check it once a
+    // majority agrees on the old coefficients
+    pub(crate) fn insert(
+        &mut self,
+        dealer: (usize, usize),
+        dealing: Dealing,
+        old: PublicCoefficients,
+    ) {
+        if self.dealings.contains_key(&dealer) || self.waiting.contains_key(&dealer) {
+            return;
+        }
+        let t = std::time::Instant::now();
+        if old.public_key() != self.public_key {
+            println!(
+                "{:?} sent coefficients of another public key, leaving it out",
+                dealer
+            );
+            self.dealings.insert(dealer, None);
+            self.verify_time += t.elapsed();
+            return;
+        }
+
+        let position = match self.votes.iter().position(|(other, _)| *other == old) {
+            Some(position) => position,
+            None => {
+                self.votes.push((old.clone(), 0));
+                self.votes.len() - 1
+            }
+        };
+        self.votes[position].1 += 1;
+        self.waiting.insert(dealer, (dealing, old));
+        if self.old.is_none() && 2 * self.votes[position].1 > self.old_nodes {
+            self.old = Some(self.votes[position].0.clone());
+        }
+        if let Some(majority) = &self.old {
+            for (dealer, (dealing, old)) in std::mem::take(&mut self.waiting) {
+                let kept = if old != *majority {
+                    println!(
+                        "{:?} sent other old coefficients than the majority, leaving it out",
+                        dealer
+                    );
+                    None
+                } else if let Err(err) = verify_reshare(&dealing, majority, dealer) {
+                    println!("{}, leaving it out", err);
+                    None
+                } else {
+                    Some(dealing)
+                };
+                self.dealings.insert(dealer, kept);
+            }
+        }
+        self.verify_time += t.elapsed();
+    }
+
+    pub(crate) fn pick(
+        &self,
+        old_nodes: (u32, u32),
+        threshold: (usize, usize),
+    ) -> Option<Result<Vec<(usize, usize)>, String>> {
+        pick_dealers(old_nodes, threshold, &self.dealings)
+    }
+}
+
+// the dealers a new node combines, t' from each of the first t old groups with enough kept
+// dealings, taking members in index order. Every new node has to pick the same dealers, so a
+// member is only passed over once its dealing is left out, and a group once it can't have t'.
+// None while that depends on dealings that haven't arrived
+pub fn pick_dealers<T>(
+    old_nodes: (u32, u32),
+    (t, t_prime): (usize, usize),
+    dealings: &BTreeMap<(usize, usize), Option<T>>,
+) -> Option<Result<Vec<(usize, usize)>, String>> {
+    let (n, m) = (old_nodes.0 as usize, old_nodes.1 as usize);
+    let mut dealers = Vec::new();
+    for i in (0..n).take_while(|_| dealers.len() < t * t_prime) {
+        let mut members = Vec::new();
+        for j in 0..m {
+            // the group is full, or the members left can't fill it
+            if members.len() == t_prime || members.len() + m - j < t_prime {
+                break;
+            }
+            match dealings.get(&(i, j)) {
+                Some(Some(_)) => members.push((i, j)),
+                Some(None) => {}
+                None => return None,
+            }
+        }
+        if members.len() == t_prime {
+            dealers.extend(members);
+        }
+    }
+    if dealers.len() < t * t_prime {
+        return Some(Err("reshare aborted, not enough valid dealings".to_string()));
+    }
+    Some(Ok(dealers))
+}
+
+// deserialize a dealing and the old coefficients sent by a node of the old layout, None if
+// either doesn't fit
+fn deserialize_dealing(
+    processes: &[(Id, Option<(usize, usize)>, Option<(usize, usize)>)],
+    id: Id,
+    msg: &[u8],
+    threshold: (usize, usize),
+    membership: &Membership,
+) -> Option<((usize, usize), Dealing, PublicCoefficients)> {
+    let dealer = match processes.iter().find(|(other, _, _)| *other == id) {
+        Some((_, Some(dealer), _)) => *dealer,
+        _ => return None,
+    };
+    let (old, msg): (Vec<u8>, Message) = bincode::deserialize(msg).ok()?;
+    match msg {
+        Message::Shares(serialized_coefficients, serialized_shares) => {
+            let dealing = Dealing::deserialize(
+                &serialized_coefficients,
+                &serialized_shares,
+                membership.nodes(),
+                membership.threshold,
+            )?;
+            let old = PublicCoefficients::from_bytes(&old, threshold)?;
+            Some((dealer, dealing, old))
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{io::Write, marker::PhantomData, sync::Arc, time::Duration};
+
+use crate::{
+    api::{read_addresses, read_key},
+    g2,
+};
+
+use bls12_381::Scalar;
+use networking::{run_threshold, Flow, Node, Session, SessionId, ThresholdCore, Topology};
+use sign::{
+    scheme::{G1, G2},
+    PrefixKeys, Signable, SignatureGroup, Signing,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot},
+};
+use tokio_stream::StreamExt;
+use types::Id;
+
+// A long running node that signs on request
+//
+// Clients connect to a unix socket and send one `<domain hex> <message hex>` line per request.
+// The node answers each with a line holding the compressed signature in hex, or `error <reason>`.
+// The node that takes a request picks a new session, tells every other node about it in the
+// default session, and all nodes sign in that session. Requests run concurrently, and one that
+// doesn't get t group signatures within SIGN_TIMEOUT fails. The socket only shows up once the
+// node is connected, so clients can wait for it. Signatures are in G1, or in G2 with keys from
+// the --g2-signatures share file.
+
+// how long a signature may take before the request fails
+pub const SIGN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// a request waiting for its signature in hex
+type Pending = (Signable, oneshot::Sender<Result<String, String>>);
+
+// everything a node needs to sign in group G, shared by all requests
+pub(crate) struct Signer<G, K> {
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    coefficients: K,
+    sk: Scalar,
+    group: PhantomData<G>,
+}
+
+// run node (i, j) until ctrl-c, answering sign requests on `socket`
+pub async fn run_server(
+    my_id: (usize, usize),
+    nodes: (u32, u32),
+    threshold: (usize, usize),
+    aws: bool,
+    topology: Topology,
+    socket: &str,
+    g2_signatures: bool,
+) {
+    if g2_signatures {
+        let key = g2::read_key(my_id, nodes, threshold);
+        let signer = Signer::<G2, _>::new(my_id, nodes, threshold, key);
+        serve(signer, aws, topology, socket).await;
+    } else {
+        let key = read_key(my_id, nodes, threshold);
+        let signer = Signer::<G1, _>::new(my_id, nodes, threshold, key);
+        serve(signer, aws, topology, socket).await;
+    }
+}
+
+async fn serve<G, K>(signer: Signer<G, K>, aws: bool, topology: Topology, socket: &str)
+where
+    G: SignatureGroup,
+    K: PrefixKeys<Key = G::PublicKey> + Send + Sync + 'static,
+{
+    let (my_id, nodes) = (signer.my_id, signer.nodes);
+    let addresses = read_addresses(nodes, aws);
+    let ids: Vec<Id> = addresses
+        .keys()
+        .filter(|id| **id != Id::Bivariate(my_id.0, my_id.1))
+        .copied()
+        .collect();
+
+    let signer = Arc::new(signer);
+
+    // a socket left over from a previous run would make bind fail
+    let _ = std::fs::remove_file(socket);
+    let mut node = Node::with_topology(addresses, Id::Bivariate(my_id.0, my_id.1), topology).await;
+    let listener = UnixListener::bind(socket).expect("unable to bind socket");
+    println!("serving on {}", socket);
+
+    let (requests, mut pending) = mpsc::channel::<Pending>(networking::CHANNEL_CAPACITY);
+    // our sessions are tagged with our index so they never collide with those of other nodes
+    let tag = ((my_id.0 * nodes.1 as usize + my_id.1 + 1) as SessionId) << 32;
+    let mut count: SessionId = 0;
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_client(stream, requests.clone()));
+                }
+                Err(err) => println!("failed to accept client: {}", err),
+            },
+            Some((signable, reply)) = pending.recv() => {
+                count += 1;
+                let session = node.open_session(tag | count).unwrap();
+                let request = bincode::serialize(&(tag | count, &signable.domain, &signable.message))
+                    .unwrap();
+                if let Err(err) = node.broadcast(&request, ids.clone()).await {
+                    let _ = reply.send(Err(err.to_string()));
+                    continue;
+                }
+
+                let signer = signer.clone();
+                tokio::spawn(async move {
+                    let signature = signer.sign(session, signable.bytes()).await;
+                    let _ = reply.send(signature.map(|signature| G::to_hex(&signature)));
+                });
+            }
+            Some((_, request)) = node.recv.next() => {
+                let request: Option<(SessionId, String, Vec<u8>)> =
+                    request.ok().and_then(|request| bincode::deserialize(&request).ok());
+                let (id, domain, message) = match request {
+                    Some(request) => request,
+                    None => continue,
+                };
+                let session = match node.open_session(id) {
+                    Ok(session) => session,
+                    Err(err) => {
+                        println!("{}", err);
+                        continue;
+                    }
+                };
+
+                let signer = signer.clone();
+                tokio::spawn(async move {
+                    let msg = Signable::new(&domain, message).bytes();
+                    if let Err(err) = signer.sign(session, msg).await {
+                        println!("session {}: {}", id, err);
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    node.shutdown();
+    let _ = std::fs::remove_file(socket);
+}
+
+// answer the requests of one client in order
+async fn serve_client(stream: UnixStream, requests: mpsc::Sender<Pending>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let signature = match parse_request(&line) {
+            Ok(signable) => {
+                let (reply, signature) = oneshot::channel();
+                if requests.send((signable, reply)).await.is_err() {
+                    return;
+                }
+                signature
+                    .await
+                    .unwrap_or_else(|_| Err("node shut down".to_string()))
+            }
+            Err(err) => Err(err),
+        };
+        let line = match signature {
+            Ok(signature) => signature,
+            Err(err) => format!("error {}", err),
+        };
+        if writer
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+// a `<domain hex> <message hex>` request line
+pub(crate) fn parse_request(line: &str) -> Result<Signable, String> {
+    let (domain, message) = line.trim().split_once(' ').ok_or("invalid request")?;
+    let domain = hex::decode(domain)
+        .ok()
+        .and_then(|domain| String::from_utf8(domain).ok())
+        .ok_or("invalid domain")?;
+    let message = hex::decode(message).map_err(|_| "invalid message")?;
+    Ok(Signable::new(&domain, message))
+}
+
+impl<G, K> Signer<G, K>
+where
+    G: SignatureGroup,
+    K: PrefixKeys<Key = G::PublicKey>,
+{
+    // node `my_id` with its public coefficients and secret share
+    pub(crate) fn new(
+        my_id: (usize, usize),
+        nodes: (u32, u32),
+        threshold: (usize, usize),
+        (coefficients, sk): (K, Scalar),
+    ) -> Self {
+        Signer {
+            my_id,
+            nodes,
+            threshold,
+            coefficients,
+            sk,
+            group: PhantomData,
+        }
+    }
+
+    pub(crate) fn public_key(&self) -> G::PublicKey {
+        self.coefficients.key(&[])
+    }
+
+    // one nested signature in `session`, or an error if it takes longer than SIGN_TIMEOUT
+    pub(crate) async fn sign(
+        &self,
+        session: Session,
+        msg: Vec<u8>,
+    ) -> Result<G::Signature, String> {
+        tokio::time::timeout(SIGN_TIMEOUT, self.sign_in(session, msg))
+            .await
+            .unwrap_or_else(|_| Err(format!("timed out after {:?}", SIGN_TIMEOUT)))
+    }
+
+    // the flow of run_single_node_threshold_signature except the group signature goes to every
+    // node outside the group and traffic isn't split by phase, requests run concurrently
+    async fn sign_in(&self, mut session: Session, msg: Vec<u8>) -> Result<G::Signature, String> {
+        let scheme = Signing::<G, K>::new(&msg, &self.coefficients);
+        let share = G::sign(&msg, &self.sk);
+        let core = ThresholdCore::new(&scheme, self.flow(), self.id(), share)?;
+        Ok(run_threshold(&mut session, core).await?.share)
+    }
+
+    fn id(&self) -> Id {
+        Id::Bivariate(self.my_id.0, self.my_id.1)
+    }
+
+    // our share goes to the other members of our group and the group share to every node outside
+    // it
+    fn flow(&self) -> Flow {
+        let (n, m) = (self.nodes.0 as usize, self.nodes.1 as usize);
+        let (i, j) = self.my_id;
+        let group_ids = (0..m)
+            .filter(|k| *k != j)
+            .map(|k| Id::Bivariate(i, k))
+            .collect();
+        let ids = (0..n)
+            .filter(|k| *k != i)
+            .flat_map(|k| (0..m).map(move |l| Id::Bivariate(k, l)))
+            .collect();
+        Flow {
+            path: vec![i, j],
+            thresholds: vec![self.threshold.0, self.threshold.1],
+            targets: vec![ids, group_ids],
+            phases: Vec::new(),
+            leader: None,
+        }
+    }
+}
+
+// send `requests` random messages to the node on `socket` over `concurrency` connections and
+// record the latencies
+pub async fn run_client(socket: &str, requests: usize, concurrency: usize, domain: &str) {
+    let domain = hex::encode(domain);
+    let time = std::time::Instant::now();
+
+    let mut clients = Vec::new();
+    for k in 0..concurrency {
+        let count = requests / concurrency + usize::from(k < requests % concurrency);
+        let stream = UnixStream::connect(socket)
+            .await
+            .expect("unable to connect to node");
+        let domain = domain.clone();
+        clients.push(tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut latencies = Vec::new();
+            let mut errors = 0;
+            for _ in 0..count {
+                let message = rand::random::<[u8; 32]>();
+                let t = std::time::Instant::now();
+                writer
+                    .write_all(format!("{} {}\n", domain, hex::encode(message)).as_bytes())
+                    .await
+                    .expect("failed to send request");
+                let reply = lines
+                    .next_line()
+                    .await
+                    .expect("failed to read reply")
+                    .expect("node closed the connection");
+                latencies.push(t.elapsed());
+                if reply.starts_with("error") {
+                    println!("{}", reply);
+                    errors += 1;
+                }
+            }
+            (latencies, errors)
+        }));
+    }
+
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for client in clients {
+        let (client_latencies, client_errors) = client.await.unwrap();
+        latencies.extend(client_latencies);
+        errors += client_errors;
+    }
+    let total_time = time.elapsed();
+    let mean = latencies.iter().sum::<std::time::Duration>() / latencies.len().max(1) as u32;
+    let max = latencies.iter().max().copied().unwrap_or_default();
+    println!("total_time: {:?}, mean latency: {:?}", total_time, mean);
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .append(true)
+        .create(true)
+        .open(format!("results/serve_{}_{}", requests, concurrency))
+        .unwrap();
+    file.write_all(format!("{:?},{:?},{:?},{}\n", total_time, mean, max, errors).as_bytes())
+        .unwrap();
+}
@@ -1,5 +1,18 @@
-use crate::dkg::*;
-use types::bivariate::{Dealing, Message};
+use crate::{
+    aggregation::Aggregation,
+    dissemination::Dissemination,
+    dkg::*,
+    recover::Recovery,
+    reshare::{pick_dealers, Membership, Received},
+};
+use bls12_381::Scalar;
+use ic_crypto_internal_threshold_sig_bls12381::crypto::{sign_message, verify_combined_sig};
+use sign::combine_signatures;
+use std::collections::{BTreeMap, BTreeSet};
+use types::{
+    bivariate::{Dealing, Message, PublicCoefficients},
+    Id,
+};
 
 // #[test]
 // fn run_121_node_dkg() {
@@ -15,7 +28,7 @@ use types::bivariate::{Dealing, Message};
 // for i in 0..11 {
 //     let mut group_signatures = Vec::new();
 //     for j in 0..11 {
-//         let (_, sk) = combine_dealings((i, j), &dealings);
+//         let (_, sk) = Dealing::combine((i, j), &dealings);
 //         let pk = public_coefficients.individual_public_key((i as u32, j as u32));
 //         group_signatures.push(sign_message(&msg, &sk));
 //         verify_individual_sig(&msg, group_signatures[j], pk).unwrap();
@@ -48,7 +61,7 @@ fn serialize() {
     );
 
     let recovered_dealing = match recovered_msg {
-        Message::Shares(c, s) => Dealing::deserialize(c, s, 11, 5),
+        Message::Shares(c, s) => Dealing::deserialize(&c, &s, (11, 11), (3, 5)).unwrap(),
     };
 
     assert_eq!(
@@ -61,3 +74,632 @@ fn serialize() {
         "Scalars do not match"
     );
 }
+
+// Tests dealings with a point off the curve, a share that isn't canonical or the wrong size are
+// rejected by the machine instead of panicking
+#[test]
+fn machine_rejects_invalid_dealings() {
+    use crate::machine::{sharing_params, BivariateDkg, Params};
+    use networking::DkgProtocol;
+
+    let (nodes, threshold) = ((2, 2), (2, 2));
+    let (coefficients, scalars) = generate_shares(nodes, threshold).serialize();
+    assert!(Dealing::deserialize(&coefficients, &scalars, nodes, threshold).is_some());
+
+    let mut off_curve = coefficients.clone();
+    off_curve[100] ^= 1;
+    let mut not_canonical = scalars.clone();
+    not_canonical[..32].copy_from_slice(&[0xff; 32]);
+    let invalid = [
+        (off_curve, scalars.clone()),
+        (coefficients.clone(), not_canonical),
+        (coefficients.clone(), scalars[32..].to_vec()),
+        (coefficients[192..].to_vec(), scalars),
+    ];
+    for (coefficients, scalars) in invalid {
+        assert!(Dealing::deserialize(&coefficients, &scalars, nodes, threshold).is_none());
+        let mut machine = BivariateDkg::new(
+            sharing_params(Params { nodes, threshold }),
+            Id::Bivariate(0, 0),
+        )
+        .unwrap();
+        assert!(machine
+            .handle_message(Id::Bivariate(0, 1), Message::Shares(coefficients, scalars))
+            .is_err());
+    }
+}
+
+// Tests every node hears from a live member of each group that can sign, for any t' live members
+#[test]
+fn schedule_reaches_every_node() {
+    let (n, m, t_prime) = (4, 5, 3);
+    let targets: Vec<Vec<BTreeSet<Id>>> = (0..n)
+        .map(|i| {
+            (0..m)
+                .map(|j| {
+                    let targets = Dissemination::Schedule.targets((i, j), (4, 5), t_prime);
+                    assert_eq!(targets.len(), (n - 1) * (m - t_prime + 1));
+                    targets.into_iter().collect()
+                })
+                .collect()
+        })
+        .collect();
+
+    for i in 0..n {
+        // every set of live members of group i large enough to sign
+        for live in (0..1 << m).filter(|live: &usize| live.count_ones() as usize >= t_prime) {
+            for k in 0..n {
+                for l in 0..m {
+                    let reached = (0..m)
+                        .filter(|j| live & (1 << j) != 0)
+                        .any(|j| targets[i][j].contains(&Id::Bivariate(k, l)));
+                    assert_eq!(reached, k != i, "({}, {}) missed group {}", k, l, i);
+                }
+            }
+        }
+    }
+
+    let random = Dissemination::Random.targets((0, 0), (4, 5), t_prime);
+    assert_eq!(random.len(), 4);
+    assert!(random.iter().all(|id| !matches!(id, Id::Bivariate(0, _))));
+    // 10·2 of the 27 nodes in other groups
+    assert_eq!(Dissemination::Random.targets((0, 0), (10, 3), 2).len(), 20);
+}
+
+// Tests thresholds outside the layout are rejected before they reach the schedule
+#[test]
+fn thresholds_fit_the_layout() {
+    use types::bivariate::check_threshold;
+
+    assert!(check_threshold((4, 5), (1, 1)).is_ok());
+    assert!(check_threshold((4, 5), (4, 5)).is_ok());
+    for threshold in [(0, 3), (5, 3), (2, 0), (2, 6)] {
+        assert!(check_threshold((4, 5), threshold).is_err());
+    }
+}
+
+// Tests leaders are members of their group and change with the message
+#[test]
+fn leader_rotates() {
+    let leaders: BTreeSet<usize> = (0..32u8)
+        .map(|byte| Aggregation::leader(&[byte; 32], 3, 11))
+        .collect();
+    assert!(leaders.iter().all(|leader| *leader < 11));
+    assert!(leaders.len() > 1);
+    assert_eq!(Aggregation::leader(&[], 13, 11), 2);
+
+    assert_eq!(
+        "leader:500".parse::<Aggregation>(),
+        Ok(Aggregation::Leader(std::time::Duration::from_millis(500)))
+    );
+    assert!("leader".parse::<Aggregation>().is_err());
+}
+
+// Tests a refresh changes every share but not the public key, and refreshed shares still sign
+#[test]
+fn refresh_keeps_public_key() {
+    let (nodes, threshold) = ((3, 4), (2, 3));
+    let dealings: Vec<Dealing> = (0..3).map(|_| generate_shares(nodes, threshold)).collect();
+    let refresh: Vec<Dealing> = (0..3).map(|_| generate_refresh(nodes, threshold)).collect();
+
+    let mut group_signatures = BTreeMap::new();
+    let msg = rand::random::<[u8; 32]>();
+    for i in 0..2 {
+        let mut signatures = BTreeMap::new();
+        for j in 0..3 {
+            let dealt: Vec<(&PublicCoefficients, Scalar)> = refresh
+                .iter()
+                .map(|dealing| (&dealing.0, dealing.1[i][j]))
+                .collect();
+            for (coefficients, share) in &dealt {
+                verify_refresh(coefficients, *share, (i, j)).unwrap();
+            }
+            let key = Dealing::combine((i, j), &dealings);
+            let (coefficients, sk) = apply_refresh(key.clone(), &dealt);
+            assert_eq!(coefficients.public_key(), key.0.public_key());
+            assert_ne!(sk, key.1);
+            signatures.insert(j, sign_message(&msg, &sk));
+        }
+        group_signatures.insert(i, combine_signatures(&signatures, 3).unwrap());
+    }
+    let sig = combine_signatures(&group_signatures, 2).unwrap();
+    verify_combined_sig(
+        &msg,
+        sig,
+        Dealing::combine((0, 0), &dealings).0.public_key(),
+    )
+    .unwrap();
+
+    // a dealing of a fresh key would change the public key
+    assert!(verify_refresh(&dealings[0].0, dealings[0].1[0][0], (0, 0)).is_err());
+    assert!(verify_refresh(&refresh[0].0, refresh[0].1[0][1], (0, 0)).is_err());
+}
+
+// Tests the refresh machine run in memory keeps the public key, hands each node only its own
+// share, and its keys still sign
+#[test]
+fn refresh_machine_runs_without_network() {
+    use crate::{
+        machine::{node_ids, Params},
+        refresh::{RefreshDkg, RefreshParams},
+    };
+    use networking::{run_in_memory, DkgProtocol};
+    use types::bivariate::RefreshMessage;
+
+    let layout = Params {
+        nodes: (3, 3),
+        threshold: (2, 2),
+    };
+    let dealings: Vec<Dealing> = (0..3)
+        .map(|_| generate_shares(layout.nodes, layout.threshold))
+        .collect();
+    let key = |i, j| Dealing::combine((i, j), &dealings);
+    let machines: Vec<RefreshDkg> = node_ids(layout.nodes)
+        .into_iter()
+        .map(|id| match id {
+            Id::Bivariate(i, j) => {
+                let params = RefreshParams {
+                    layout,
+                    key: key(i, j),
+                };
+                RefreshDkg::new(params, id).unwrap()
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+
+    // the coefficients go to everyone else, each share to its node alone
+    let mut machine = RefreshDkg::new(
+        RefreshParams {
+            layout,
+            key: key(0, 0),
+        },
+        Id::Bivariate(0, 0),
+    )
+    .unwrap();
+    for out in machine.start() {
+        match out.msg {
+            RefreshMessage::Commitments(_) => assert_eq!(out.to.len(), 8),
+            RefreshMessage::Share(_) => assert_eq!(out.to.len(), 1),
+            RefreshMessage::Complaints(_) => panic!("complained before holding every dealing"),
+        }
+    }
+    assert!(machine
+        .handle_message(Id::Bivariate(1, 1), RefreshMessage::Share(vec![1, 2, 3]))
+        .is_err());
+    assert!(machine
+        .handle_message(Id::Bivariate(3, 0), RefreshMessage::Share(vec![0; 32]))
+        .is_err());
+
+    let msg = rand::random::<[u8; 32]>();
+    let mut group_signatures = BTreeMap::new();
+    let keys = run_in_memory(machines);
+    assert_eq!(keys.len(), 9);
+    for (id, refreshed) in keys {
+        let (coefficients, sk) = refreshed.unwrap();
+        if let Id::Bivariate(i, j) = id {
+            assert_eq!(coefficients.public_key(), key(i, j).0.public_key());
+            assert_ne!(sk, key(i, j).1);
+            group_signatures
+                .entry(i)
+                .or_insert_with(BTreeMap::new)
+                .insert(j, sign_message(&msg, &sk));
+        }
+    }
+    let group_signatures: BTreeMap<usize, _> = group_signatures
+        .into_iter()
+        .map(|(i, signatures)| (i, combine_signatures(&signatures, 2).unwrap()))
+        .collect();
+    let sig = combine_signatures(&group_signatures, 2).unwrap();
+    verify_combined_sig(&msg, sig, key(0, 0).0.public_key()).unwrap();
+}
+
+// Tests a share that doesn't match its coefficients is complained about and aborts the refresh at
+// every node, not only at the node that got it
+#[test]
+fn refresh_machine_aborts_everywhere_on_a_bad_share() {
+    use crate::{
+        machine::{node_ids, Params},
+        refresh::{RefreshDkg, RefreshParams},
+    };
+    use networking::DkgProtocol;
+    use std::collections::VecDeque;
+    use types::bivariate::RefreshMessage;
+
+    let layout = Params {
+        nodes: (2, 2),
+        threshold: (2, 2),
+    };
+    let dealings = vec![generate_shares(layout.nodes, layout.threshold)];
+    let mut machines: BTreeMap<Id, RefreshDkg> = node_ids(layout.nodes)
+        .into_iter()
+        .map(|id| match id {
+            Id::Bivariate(i, j) => {
+                let params = RefreshParams {
+                    layout,
+                    key: Dealing::combine((i, j), &dealings),
+                };
+                (id, RefreshDkg::new(params, id).unwrap())
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+
+    // (0, 0) sends (0, 1) a share of the wrong value
+    let (dealer, victim) = (Id::Bivariate(0, 0), Id::Bivariate(0, 1));
+    let mut queue = VecDeque::new();
+    for (id, machine) in machines.iter_mut() {
+        for out in machine.start() {
+            for to in out.to {
+                let msg = match out.msg {
+                    RefreshMessage::Share(_) if (*id, to) == (dealer, victim) => {
+                        RefreshMessage::Share(Scalar::one().to_bytes().to_vec())
+                    }
+                    _ => out.msg.clone(),
+                };
+                queue.push_back((*id, to, msg));
+            }
+        }
+    }
+    while let Some((from, to, msg)) = queue.pop_front() {
+        let machine = machines.get_mut(&to).unwrap();
+        for out in machine.handle_message(from, msg).unwrap() {
+            queue.extend(out.to.into_iter().map(|next| (to, next, out.msg.clone())));
+        }
+    }
+
+    for machine in machines.values_mut() {
+        let output = machine.poll_output().unwrap();
+        assert!(output.unwrap_err().contains(&format!("{:?}", dealer)));
+    }
+}
+
+// Tests a lost share is recovered from blinded contributions of its row and of its column
+#[test]
+fn recover_lost_share() {
+    let (nodes, threshold) = ((4, 5), (2, 3));
+    let dealings: Vec<Dealing> = (0..2).map(|_| generate_shares(nodes, threshold)).collect();
+    let lost = (1, 2);
+    let (coefficients, share) = Dealing::combine(lost, &dealings);
+
+    for recovery in [Recovery::Row, Recovery::Column] {
+        let helpers = recovery.helpers(lost, nodes, threshold);
+        assert!(!helpers.contains(&lost));
+        let indices: Vec<usize> = helpers.iter().map(|id| recovery.index(*id)).collect();
+
+        // masks[k][l] is sent by helper k to helper l
+        let masks: Vec<Vec<Scalar>> = helpers
+            .iter()
+            .map(|_| recovery_masks(helpers.len()))
+            .collect();
+        let contributions: Vec<Scalar> = helpers
+            .iter()
+            .enumerate()
+            .map(|(k, helper)| {
+                let sent: Vec<Scalar> = (0..helpers.len())
+                    .filter(|l| *l != k)
+                    .map(|l| masks[k][l])
+                    .collect();
+                let received: Vec<Scalar> = (0..helpers.len())
+                    .filter(|l| *l != k)
+                    .map(|l| masks[l][k])
+                    .collect();
+                let coefficient =
+                    lagrange_coefficient(&indices, recovery.index(*helper), recovery.index(lost));
+                blind_recovery_share(
+                    Dealing::combine(*helper, &dealings).1,
+                    coefficient,
+                    &sent,
+                    &received,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            recover_share(&contributions, &coefficients, lost),
+            Ok(share)
+        );
+        // one contribution short doesn't give the share
+        assert!(recover_share(&contributions[1..], &coefficients, lost).is_err());
+    }
+}
+
+// Tests helpers are rejected when outside the layout, listed twice, the lost node or too few
+#[test]
+fn recovery_checks_helpers() {
+    let (nodes, threshold) = ((4, 5), (2, 3));
+    let lost = (1, 2);
+    for recovery in [Recovery::Row, Recovery::Column] {
+        let helpers = recovery.helpers(lost, nodes, threshold);
+        assert_eq!(
+            recovery.check_helpers(lost, nodes, threshold, &helpers),
+            Ok(())
+        );
+        let check = |ks: &[usize]| {
+            let helpers: Vec<_> = ks.iter().map(|k| recovery.helper(lost, *k)).collect();
+            recovery.check_helpers(lost, nodes, threshold, &helpers)
+        };
+        assert!(check(&[0, 3, 9]).is_err());
+        assert!(check(&[0, 3, 3]).is_err());
+        assert!(check(&[0]).is_err());
+    }
+    assert!(Recovery::Row
+        .check_helpers(lost, nodes, threshold, &[(1, 0), (1, 2), (1, 3)])
+        .is_err());
+    assert!(Recovery::Row
+        .check_helpers((4, 0), nodes, threshold, &[(4, 1), (4, 2), (4, 3)])
+        .is_err());
+}
+
+// Tests a reshare to a layout with a retired, a moved and a new group keeps the public key
+#[test]
+fn reshare_keeps_public_key() {
+    let (nodes, threshold) = ((3, 4), (2, 3));
+    let dealings: Vec<Dealing> = (0..2).map(|_| generate_shares(nodes, threshold)).collect();
+    let old = Dealing::combine((0, 0), &dealings).0;
+
+    let membership: Membership = "# old group 1 retires\nthreshold 2,2\nsize 3\ngroups 0,2,new"
+        .parse()
+        .unwrap();
+    membership.validate(nodes).unwrap();
+    assert!("threshold 2,2\nsize 3\ngroups 0,0"
+        .parse::<Membership>()
+        .unwrap()
+        .validate(nodes)
+        .is_err());
+
+    // groups 0 and 2 keep three of four members, group 1 retires and a new group joins
+    let processes = membership.processes(nodes);
+    assert_eq!(processes.len(), 4 + 4 + 3 + 4);
+    assert!(processes.contains(&(Id::Bivariate(1, 2), Some((2, 2)), Some((1, 2)))));
+    assert!(processes.contains(&(Id::Bivariate(1, 3), Some((2, 3)), None)));
+    assert!(processes.contains(&(Id::Bivariate(2, 0), None, Some((2, 0)))));
+    assert!(processes.contains(&(Id::Bivariate(3, 1), Some((1, 1)), None)));
+
+    // t' members of t old groups deal, here of groups 1 and 2
+    let dealers = vec![(1, 0), (1, 2), (1, 3), (2, 0), (2, 1), (2, 2)];
+    let reshares: Vec<Dealing> = dealers
+        .iter()
+        .map(|dealer| {
+            let share = Dealing::combine(*dealer, &dealings).1;
+            generate_reshare(share, membership.nodes(), membership.threshold)
+        })
+        .collect();
+    for (dealer, dealing) in dealers.iter().zip(&reshares) {
+        verify_reshare(dealing, &old, *dealer).unwrap();
+    }
+    assert!(verify_reshare(&reshares[0], &old, (1, 1)).is_err());
+
+    let weighted: Vec<(Scalar, &Dealing)> = reshare_weights(&dealers)
+        .into_iter()
+        .zip(&reshares)
+        .collect();
+    let msg = rand::random::<[u8; 32]>();
+    let mut group_signatures = BTreeMap::new();
+    for i in [0, 2] {
+        let mut signatures = BTreeMap::new();
+        for j in 1..3 {
+            for (_, dealing) in &weighted {
+                verify_dealt_share(dealing, (i, j)).unwrap();
+            }
+            let (coefficients, sk) = combine_reshare((i, j), &weighted);
+            assert_eq!(coefficients.public_key(), old.public_key());
+            signatures.insert(j, sign_message(&msg, &sk));
+        }
+        group_signatures.insert(i, combine_signatures(&signatures, 2).unwrap());
+    }
+    let sig = combine_signatures(&group_signatures, 2).unwrap();
+    verify_combined_sig(&msg, sig, old.public_key()).unwrap();
+}
+
+// Tests a single group can be resized, and dealings shared to the largest group fit every group
+#[test]
+fn reshare_resizes_one_group() {
+    let (nodes, threshold) = ((3, 4), (2, 3));
+    let dealings: Vec<Dealing> = (0..2).map(|_| generate_shares(nodes, threshold)).collect();
+    let old = Dealing::combine((0, 0), &dealings).0;
+
+    let membership: Membership = "threshold 2,3\nsize 4\nsize 1 6\ngroups 0,1,2"
+        .parse()
+        .unwrap();
+    membership.validate(nodes).unwrap();
+    assert_eq!(membership.sizes, vec![4, 6, 4]);
+    assert_eq!(membership.nodes(), (3, 6));
+    assert!(membership.contains((1, 5)) && !membership.contains((0, 4)));
+    assert!("threshold 2,3\nsize 4\nsize 3 6\ngroups 0,1,2"
+        .parse::<Membership>()
+        .is_err());
+    assert!("threshold 2,3\nsize 4\nsize 1 2\ngroups 0,1,2"
+        .parse::<Membership>()
+        .unwrap()
+        .validate(nodes)
+        .is_err());
+
+    // only group 1 grows
+    let processes = membership.processes(nodes);
+    assert_eq!(processes.len(), 4 + 6 + 4);
+    assert!(processes.contains(&(Id::Bivariate(1, 5), None, Some((1, 5)))));
+    assert!(!processes.contains(&(Id::Bivariate(0, 4), None, Some((0, 4)))));
+
+    let dealers = vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)];
+    let reshares: Vec<Dealing> = dealers
+        .iter()
+        .map(|dealer| {
+            let share = Dealing::combine(*dealer, &dealings).1;
+            generate_reshare(share, membership.nodes(), membership.threshold)
+        })
+        .collect();
+    let weighted: Vec<(Scalar, &Dealing)> = reshare_weights(&dealers)
+        .into_iter()
+        .zip(&reshares)
+        .collect();
+    let msg = rand::random::<[u8; 32]>();
+    let mut group_signatures = BTreeMap::new();
+    for (i, members) in [(0, [0, 2, 3]), (1, [1, 4, 5])] {
+        let mut signatures = BTreeMap::new();
+        for j in members {
+            let (coefficients, sk) = combine_reshare((i, j), &weighted);
+            assert_eq!(coefficients.public_key(), old.public_key());
+            signatures.insert(j, sign_message(&msg, &sk));
+        }
+        group_signatures.insert(i, combine_signatures(&signatures, 3).unwrap());
+    }
+    let sig = combine_signatures(&group_signatures, 2).unwrap();
+    verify_combined_sig(&msg, sig, old.public_key()).unwrap();
+}
+
+// Tests new nodes pick the same dealers whatever order dealings arrive in, and wait while the
+// pick depends on a dealing that hasn't arrived
+#[test]
+fn reshare_picks_dealers_in_order() {
+    let (nodes, threshold) = ((3, 4), (2, 3));
+    let mut dealings: BTreeMap<(usize, usize), Option<()>> = BTreeMap::new();
+    assert_eq!(pick_dealers(nodes, threshold, &dealings), None);
+
+    // group 0 waits on member 3 once member 1 is left out
+    for (dealer, kept) in [
+        ((0, 0), true),
+        ((0, 1), false),
+        ((0, 2), true),
+        ((1, 0), true),
+    ] {
+        dealings.insert(dealer, Some(()).filter(|_| kept));
+    }
+    dealings.extend((1..3).map(|j| ((1, j), Some(()))));
+    assert_eq!(pick_dealers(nodes, threshold, &dealings), None);
+    dealings.insert((0, 3), Some(()));
+    assert_eq!(
+        pick_dealers(nodes, threshold, &dealings),
+        Some(Ok(vec![(0, 0), (0, 2), (0, 3), (1, 0), (1, 1), (1, 2)]))
+    );
+
+    // a group with two left out can't have t' = 3, so group 2 is needed
+    dealings.insert((1, 1), None);
+    dealings.insert((1, 3), None);
+    assert_eq!(pick_dealers(nodes, threshold, &dealings), None);
+    dealings.extend((0..4).map(|j| ((2, j), Some(()).filter(|_| j != 0))));
+    assert_eq!(
+        pick_dealers(nodes, threshold, &dealings),
+        Some(Ok(vec![(0, 0), (0, 2), (0, 3), (2, 1), (2, 2), (2, 3)]))
+    );
+    dealings.insert((0, 0), None);
+    assert!(matches!(
+        pick_dealers(nodes, threshold, &dealings),
+        Some(Err(_))
+    ));
+}
+
+// Tests dealings wait for a majority of the old nodes to agree on the old coefficients, and that a
+// dealer who sent other coefficients with the published public key is left out
+#[test]
+fn reshare_checks_old_coefficients_against_the_majority() {
+    let (nodes, threshold) = ((2, 3), (2, 2));
+    let dealings = vec![generate_shares(nodes, threshold)];
+    let old = Dealing::combine((0, 0), &dealings).0;
+    let mut forged = old.clone();
+    forged.coefficients[1][1] = forged.coefficients[0][1];
+    assert_eq!(forged.public_key(), old.public_key());
+
+    let reshare = |dealer: (usize, usize)| {
+        let share = Dealing::combine(dealer, &dealings).1;
+        generate_reshare(share, nodes, threshold)
+    };
+    let mut received = Received::new(old.public_key(), nodes);
+    received.insert((0, 0), reshare((0, 0)), forged);
+    for dealer in [(0, 1), (0, 2), (1, 0)] {
+        received.insert(dealer, reshare(dealer), old.clone());
+    }
+    // three of six old nodes aren't a majority
+    assert_eq!(received.pick(nodes, threshold), None);
+
+    received.insert((1, 1), reshare((1, 1)), old.clone());
+    assert_eq!(
+        received.pick(nodes, threshold),
+        Some(Ok(vec![(0, 1), (0, 2), (1, 0), (1, 1)]))
+    );
+}
+
+// Tests the dkg state machine run in memory gives every node a key of the same public key
+#[test]
+fn machine_runs_without_network() {
+    use crate::machine::{sharing_params, BivariateDkg, Params};
+    use networking::{DkgProtocol, Outgoing};
+
+    let params = Params {
+        nodes: (3, 3),
+        threshold: (2, 2),
+    };
+    let mut machines: BTreeMap<Id, BivariateDkg> = (0..3)
+        .flat_map(|i| (0..3).map(move |j| Id::Bivariate(i, j)))
+        .map(|id| (id, BivariateDkg::new(sharing_params(params), id).unwrap()))
+        .collect();
+    assert!(BivariateDkg::new(sharing_params(params), Id::Bivariate(3, 0)).is_err());
+
+    // deliver every message until no machine has anything left to send
+    let mut queue: Vec<(Id, Outgoing<Message>)> = machines
+        .iter_mut()
+        .flat_map(|(id, machine)| machine.start().into_iter().map(move |out| (*id, out)))
+        .collect();
+    while let Some((from, Outgoing { to, msg })) = queue.pop() {
+        for id in to {
+            let replies = machines
+                .get_mut(&id)
+                .unwrap()
+                .handle_message(from, msg.clone())
+                .unwrap();
+            queue.extend(replies.into_iter().map(|out| (id, out)));
+        }
+    }
+
+    // start only deals once, and a second message from a node, one from outside or one that
+    // doesn't fit the layout is rejected
+    let machine = machines.get_mut(&Id::Bivariate(0, 0)).unwrap();
+    let dealing = machine.start();
+    assert!(dealing.is_empty());
+    let garbage = Message::Shares(vec![1, 2, 3], vec![1, 2, 3]);
+    assert!(machine
+        .handle_message(Id::Bivariate(1, 1), garbage.clone())
+        .is_err());
+    assert!(machine
+        .handle_message(Id::Bivariate(5, 0), garbage)
+        .is_err());
+    assert_eq!(machine.dealing_count(), 9);
+
+    let msg = rand::random::<[u8; 32]>();
+    let mut public_key = None;
+    let mut group_signatures: BTreeMap<usize, BTreeMap<usize, _>> = BTreeMap::new();
+    for (id, machine) in machines.iter_mut() {
+        let (coefficients, sk) = machine.poll_output().unwrap();
+        assert!(machine.poll_output().is_none());
+        assert_eq!(
+            *public_key.get_or_insert(coefficients.public_key()),
+            coefficients.public_key()
+        );
+        if let Id::Bivariate(i, j) = id {
+            group_signatures
+                .entry(*i)
+                .or_default()
+                .insert(*j, sign_message(&msg, &sk));
+        }
+    }
+    let group_signatures: BTreeMap<usize, _> = group_signatures
+        .iter()
+        .map(|(i, signatures)| (*i, combine_signatures(signatures, 2).unwrap()))
+        .collect();
+    let sig = combine_signatures(&group_signatures, 2).unwrap();
+    verify_combined_sig(&msg, sig, public_key.unwrap()).unwrap();
+}
+
+// Tests request lines parse to their domain and message and malformed ones are rejected
+#[test]
+fn serve_parses_requests() {
+    use crate::serve::parse_request;
+
+    let signable = parse_request(&format!("{} 0a0b\n", hex::encode("my-app"))).unwrap();
+    assert_eq!(signable.domain, "my-app");
+    assert_eq!(signable.message, vec![10, 11]);
+
+    for line in ["", "0a0b", "zz 0a0b", "ff 0a0b", "6d79 0a0"] {
+        assert!(parse_request(line).is_err(), "{:?} parsed", line);
+    }
+}
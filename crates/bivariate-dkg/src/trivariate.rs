@@ -0,0 +1,112 @@
+use networking::{read_addresses, run_threshold_node, Combined, Flow, ThresholdScheme, Topology};
+use sign::{scheme::G1, Signable, SignatureGroup, Signing};
+use types::{
+    multivariate::{Dealing, Levels},
+    Id,
+};
+
+// The nested scheme over three levels, regions of groups of nodes (see types::multivariate)
+//
+// Node (r, g, k) is member k of group g in region r. t_3 members of a group sign for the group,
+// t_2 groups of a region for the region and t_1 regions for everyone. Nodes are numbered region
+// by region then group by group, so the addresses file and local ports follow the same order.
+// The sharing works for any depth, but ids have three coordinates so only three levels run here.
+
+// the ids of every node in row major order
+pub fn ids(levels: &Levels) -> Vec<Id> {
+    assert_eq!(
+        levels.depth(),
+        3,
+        "only three levels can run over the network"
+    );
+    levels
+        .nodes()
+        .iter()
+        .map(|index| Id::Trivariate(index[0], index[1], index[2]))
+        .collect()
+}
+
+// write a dealing over three levels for threshold signatures
+pub fn write_dealing_to_file(levels: &Levels) {
+    let dealing = Dealing::generate(levels);
+
+    std::fs::write(
+        "trivariate_shares",
+        bincode::serialize(&dealing.serialize()).unwrap(),
+    )
+    .unwrap();
+}
+
+// read the dealing written by write_dealing_to_file
+fn read_dealing(levels: &Levels) -> Dealing {
+    let dealing: (Vec<u8>, Vec<u8>) = bincode::deserialize(
+        &std::fs::read("trivariate_shares").expect("unable to read share file"),
+    )
+    .expect("unable to deserialize file");
+    Dealing::deserialize(&dealing.0, &dealing.1, levels).expect("share file doesn't fit the levels")
+}
+
+// run a node for nested threshold signatures over `signable` in three stages.
+// Shares go to the group, group signatures to the other groups of the region and region
+// signatures to the other regions. Fails if the run does
+pub async fn run_threshold_signature(
+    my_id: (usize, usize, usize),
+    levels: Levels,
+    aws: bool,
+    signable: Signable,
+) -> Result<(), String> {
+    let addresses = read_addresses(ids(&levels), aws);
+    let msg = signable.bytes();
+    let (r, g, k) = my_id;
+    let me = Id::Trivariate(r, g, k);
+
+    // ids of nodes in this group, in other groups of this region and in other regions
+    let others = addresses.keys().filter(|id| **id != me).copied();
+    let group_ids: Vec<Id> = others
+        .clone()
+        .filter(|id| matches!(id, Id::Trivariate(r_, g_, _) if (*r_, *g_) == (r, g)))
+        .collect();
+    let region_ids: Vec<Id> = others
+        .clone()
+        .filter(|id| matches!(id, Id::Trivariate(r_, g_, _) if *r_ == r && *g_ != g))
+        .collect();
+    let other_ids: Vec<Id> = others
+        .filter(|id| matches!(id, Id::Trivariate(r_, _, _) if *r_ != r))
+        .collect();
+
+    let dealing = read_dealing(&levels);
+    let sk = dealing.1[levels.position(&[r, g, k])];
+    let scheme = Signing::<G1, _>::new(&msg, &dealing.0);
+    let flow = Flow {
+        path: vec![r, g, k],
+        thresholds: levels.thresholds.clone(),
+        targets: vec![other_ids, region_ids, group_ids],
+        phases: vec!["top", "region", "group"],
+        leader: None,
+    };
+    let run = run_threshold_node(addresses, me, Topology::FullMesh, &scheme, flow, || {
+        G1::sign(&msg, &sk)
+    })
+    .await?;
+
+    let Combined {
+        combine_time,
+        verify_time,
+        ..
+    } = &run.combined;
+    let line = format!(
+        "{:?},{:?},{:?},{:?},{:?},{:?}",
+        run.total_time,
+        run.share_time,
+        combine_time[2],
+        combine_time[1],
+        combine_time[0],
+        verify_time[0],
+    );
+    run.append_results(
+        &format!("results/trivariate_threshold_signatures_{}", levels),
+        &line,
+        me,
+    );
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use clap::Parser;
+use main::client;
+use types::ClientArgs;
+
+// load test client for `main serve`
+#[tokio::main]
+async fn main() {
+    client(ClientArgs::parse()).await;
+}
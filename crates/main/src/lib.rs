@@ -1,71 +1,343 @@
+use std::{io::Read, time::Duration};
+
+use bivariate::machine::{node_ids, sharing_params, BivariateDkg};
 use bivariate_dkg as bivariate;
-use nidkg;
+use networking::{
+    read_addresses, run_dkg_node, DkgProtocol, NiSharing, SharingDkg, SharingParams,
+    ThresholdScheme,
+};
+use nidkg::machine::{ForwardSecure, NiDkg};
+use optimized_nidkg::machine::{BivariateElGamal, OptimizedNiDkg};
+use optimized_univar::machine::{ElGamal, OptimizedUnivarDkg};
+use sign::{
+    accountable::Accountability,
+    beacon::Chain,
+    decrypt,
+    scheme::{G1, G2},
+    signature_from_hex,
+    weighted::Weighted,
+    Coefficients, Level, Signable, SignatureFile, SignatureGroup, Signing,
+};
 use types::{
-    BivariateDKGArgs, BivariateNiDKGArgs, BivariateNiDKGKeyPairsArgs, BivariateShareFileArgs,
-    BivariateThresholdSignatureArgs, NiDKGKeyPairsArgs, UnivariateDKGArgs, UnivariateNiDKGArgs,
-    UnivariateShareFileArgs, UnivariateThresholdSignatureArgs,
+    bivariate::check_threshold,
+    g1,
+    heterogeneous::{self, Shape},
+    multivariate::{self, Levels},
+    univariate::Weights,
+    BeaconArgs, BivariateDKGArgs, BivariateNiDKGArgs, BivariateNiDKGKeyPairsArgs,
+    BivariateRecoveryArgs, BivariateRefreshArgs, BivariateReshareArgs, BivariateShareFileArgs,
+    BivariateThresholdDecryptionArgs, BivariateThresholdSignatureArgs, ClientArgs, EncryptArgs,
+    HeterogeneousDKGArgs, HeterogeneousShareFileArgs, HeterogeneousThresholdSignatureArgs, Id,
+    LevelArgs, MessageArgs, NiDKGKeyPairsArgs, ServeArgs, ShapeArgs, TrivariateDKGArgs,
+    TrivariateShareFileArgs, TrivariateThresholdSignatureArgs, UnivariateDKGArgs,
+    UnivariateNiDKGArgs, UnivariateShareFileArgs, UnivariateThresholdSignatureArgs, VerifyArgs,
+    VerifyBeaconArgs,
 };
+use univariate::machine::UnivariateDkg;
 use univariate_dkg as univariate;
 
+// run node `me` of any dkg, whose nodes are `ids` in the order of the addresses file, with the
+// machine set up from `params`. `check` signs with the new key. Exits if the node fails
+async fn run_dkg<P: DkgProtocol>(
+    ids: Vec<Id>,
+    me: Id,
+    params: P::Params,
+    aws: bool,
+    reliable: bool,
+    check: impl FnOnce(&P::Output),
+    filename: &str,
+) -> P::Output {
+    run_dkg_node::<P>(
+        read_addresses(ids, aws),
+        me,
+        params,
+        reliable,
+        check,
+        filename,
+    )
+    .await
+    .unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    })
+}
+
 pub async fn univariate_dkg(args: UnivariateDKGArgs) {
-    univariate::api::run_dkg(
-        args.node_index,
-        args.num_nodes as u32,
-        args.threshold,
+    let weights = read_weights(args.num_nodes, &args.weights, args.threshold);
+    let (my_id, t) = (args.node_index, args.threshold);
+    let ids: Vec<Id> = (0..args.num_nodes).map(Id::Univariate).collect();
+    let msg: [u8; 32] = [0; 32];
+    if args.g2_signatures {
+        if args.weights.is_some() {
+            panic!("--weights isn't supported with --g2-signatures");
+        }
+        let filename = format!("results/univariate_dkg_g2_{}_{}", args.num_nodes, t);
+        let params = SharingParams {
+            layout: bivariate::machine::Params {
+                nodes: (args.num_nodes as u32, 1),
+                threshold: (t, 1),
+            },
+            ids: ids.clone(),
+        };
+        // sign with the new key to check it
+        run_dkg::<SharingDkg<g1::Dealing>>(
+            ids,
+            Id::Univariate(my_id),
+            params,
+            args.aws,
+            args.reliable,
+            |(coefficients, sk)| {
+                let pk = coefficients.individual_public_key((my_id as u32, 0));
+                G2::verify(&msg, G2::sign(&msg, sk), pk).unwrap();
+            },
+            &filename,
+        )
+        .await;
+        return;
+    }
+
+    let filename = format!("results/univariate_dkg_{}_{}", weights, t);
+    let params = univariate::machine::Params {
+        weights: weights.clone(),
+        threshold: t,
+    };
+    // sign with the new shares to check them
+    run_dkg::<UnivariateDkg>(
+        ids,
+        Id::Univariate(my_id),
+        params,
         args.aws,
+        args.reliable,
+        |(coefficients, sks)| {
+            let scheme = Weighted {
+                signing: Signing::<G1, _>::new(&msg, coefficients),
+                weights: &weights,
+            };
+            let sigs: Vec<_> = sks.iter().map(|sk| G1::sign(&msg, sk)).collect();
+            scheme.verify(&[my_id], &sigs).unwrap();
+        },
+        &filename,
     )
     .await;
 }
 
 pub async fn bivariate_dkg(args: BivariateDKGArgs) {
-    bivariate::api::run_dkg(
+    let my_id = (args.node_index_i, args.node_index_j);
+    let nodes = (args.num_nodes_n as u32, args.num_nodes_m as u32);
+    let threshold = (args.threshold_t, args.threshold_t_prime);
+    let layout = bivariate::machine::Params { nodes, threshold };
+    let ids = node_ids(nodes);
+    let msg: [u8; 32] = [0; 32];
+    if args.g2_signatures {
+        let filename = format!(
+            "results/bivariate_dkg_g2_{},{}_{},{}",
+            nodes.0, nodes.1, threshold.0, threshold.1
+        );
+        let params = SharingParams {
+            layout,
+            ids: ids.clone(),
+        };
+        // sign with the new key to check it
+        run_dkg::<SharingDkg<g1::Dealing>>(
+            ids,
+            Id::Bivariate(my_id.0, my_id.1),
+            params,
+            args.aws,
+            args.reliable,
+            |(coefficients, sk)| {
+                let pk = coefficients.individual_public_key((my_id.0 as u32, my_id.1 as u32));
+                G2::verify(&msg, G2::sign(&msg, sk), pk).unwrap();
+            },
+            &filename,
+        )
+        .await;
+        return;
+    }
+
+    let filename = format!(
+        "results/bivariate_dkg_{},{}_{},{}",
+        nodes.0, nodes.1, threshold.0, threshold.1
+    );
+    // sign with the new key to check it
+    run_dkg::<BivariateDkg>(
+        ids,
+        Id::Bivariate(my_id.0, my_id.1),
+        sharing_params(layout),
+        args.aws,
+        args.reliable,
+        |(coefficients, sk)| {
+            Signing::<G1, _>::new(&msg, coefficients)
+                .verify(&[my_id.0, my_id.1], &G1::sign(&msg, sk))
+                .unwrap();
+        },
+        &filename,
+    )
+    .await;
+}
+
+pub async fn bivariate_refresh(args: BivariateRefreshArgs) {
+    let result = bivariate::refresh::run_refresh(
         (args.node_index_i, args.node_index_j),
         (args.num_nodes_n as u32, args.num_nodes_m as u32),
         (args.threshold_t, args.threshold_t_prime),
         args.aws,
+        args.reliable,
     )
     .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
 }
 
+pub async fn bivariate_recovery(args: BivariateRecoveryArgs) {
+    let nodes = (args.num_nodes_n as u32, args.num_nodes_m as u32);
+    let threshold = (args.threshold_t, args.threshold_t_prime);
+    let lost = match args.lost.split_once(',') {
+        Some((i, j)) => (
+            i.parse().expect("invalid lost node"),
+            j.parse().expect("invalid lost node"),
+        ),
+        None => panic!("invalid lost node"),
+    };
+    let recovery: bivariate::recover::Recovery = args.from.parse().expect("invalid recovery");
+    let helpers = match &args.helpers {
+        Some(helpers) => helpers
+            .split(',')
+            .map(|k| recovery.helper(lost, k.parse().expect("invalid helpers")))
+            .collect(),
+        None => recovery.helpers(lost, nodes, threshold),
+    };
+
+    let result = bivariate::recover::run_recovery(
+        (args.node_index_i, args.node_index_j),
+        lost,
+        nodes,
+        threshold,
+        args.aws,
+        recovery,
+        helpers,
+    )
+    .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+pub async fn bivariate_reshare(args: BivariateReshareArgs) {
+    let index = |index: &Option<String>| {
+        index.as_ref().map(|index| match index.split_once(',') {
+            Some((i, j)) => (
+                i.parse().expect("invalid node index"),
+                j.parse().expect("invalid node index"),
+            ),
+            None => panic!("invalid node index"),
+        })
+    };
+    let my_id = (index(&args.old), index(&args.new));
+    if my_id == (None, None) {
+        panic!("--old, --new or both are needed");
+    }
+
+    let result = bivariate::reshare::run_reshare(
+        my_id,
+        (args.num_nodes_n as u32, args.num_nodes_m as u32),
+        (args.threshold_t, args.threshold_t_prime),
+        bivariate::reshare::Membership::read(&args.membership).expect("invalid membership file"),
+        args.aws,
+        args.reliable,
+    )
+    .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// dealers are numbered after the receivers, and receivers check their key against the
+// transcript they got it from
 pub async fn univariate_nidkg(args: UnivariateNiDKGArgs) {
+    let (n, d, t) = (args.num_nodes, args.num_dealers, args.threshold);
+    let (my_id, receiver) = match args.is_dealer {
+        true => (Id::Univariate(n + args.node_index), None),
+        false => (Id::Univariate(args.node_index), Some(args.node_index)),
+    };
+    let name = if args.optimized {
+        "optimized_nidkg"
+    } else {
+        "nidkg"
+    };
+    let filename = match args.is_dealer {
+        true => format!("results/{}_dealer_{}_{}", name, n, t),
+        false => format!("results/{}_{}_{}", name, n, t),
+    };
+
     if args.optimized {
-        optimized_univar::run_dkg(
-            args.node_index,
-            args.num_nodes,
-            args.num_dealers,
-            args.threshold,
-            args.is_dealer,
+        let params = optimized_univar::read_params(n, d, t, receiver);
+        run_dkg::<OptimizedUnivarDkg>(
+            nidkg_ids::<ElGamal>(&params),
+            my_id,
+            params,
             args.aws,
+            args.reliable,
+            |_| (),
+            &filename,
         )
         .await;
     } else {
-        nidkg::run_dkg(
-            args.node_index,
-            args.num_nodes,
-            args.num_dealers,
-            args.threshold,
-            args.is_dealer,
+        let params = nidkg::read_params(n, d, t, receiver);
+        run_dkg::<NiDkg>(
+            nidkg_ids::<ForwardSecure>(&params),
+            my_id,
+            params,
             args.aws,
+            args.reliable,
+            |_| (),
+            &filename,
         )
         .await;
     }
 }
 
+// dealers are the nodes (n, j), and receivers check their key against the transcript they got it
+// from
 pub async fn bivariate_nidkg(args: BivariateNiDKGArgs) {
-    optimized_nidkg::run_dkg(
-        args.node_index_i,
-        args.node_index_j,
-        args.num_nodes_n,
-        args.num_nodes_m,
-        args.num_dealers,
-        args.threshold_t,
-        args.threshold_t_prime,
-        args.is_dealer,
+    let (n, m) = (args.num_nodes_n, args.num_nodes_m);
+    let (t, t_prime) = (args.threshold_t, args.threshold_t_prime);
+    let (my_id, receiver) = match args.is_dealer {
+        true => (Id::Bivariate(n, args.node_index_j), None),
+        false => {
+            let my_id = (args.node_index_i, args.node_index_j);
+            (Id::Bivariate(my_id.0, my_id.1), Some(my_id))
+        }
+    };
+    let filename = match args.is_dealer {
+        true => format!(
+            "results/optimized_nidkg_dealer_{},{}_{},{}",
+            n, m, t, t_prime
+        ),
+        false => format!("results/optimized_nidkg_{},{}_{},{}", n, m, t, t_prime),
+    };
+
+    let params = optimized_nidkg::read_params((n, m), args.num_dealers, (t, t_prime), receiver);
+    run_dkg::<OptimizedNiDkg>(
+        nidkg_ids::<BivariateElGamal>(&params),
+        my_id,
+        params,
         args.aws,
+        args.reliable,
+        |_| (),
+        &filename,
     )
     .await;
 }
 
+// the receivers then the dealers of a nidkg, the order of the addresses file
+fn nidkg_ids<S: NiSharing>(params: &S::Params) -> Vec<Id> {
+    [S::receivers(params), S::dealers(params)].concat()
+}
+
 pub fn generate_keypairs(args: NiDKGKeyPairsArgs) {
     if args.optimized {
         optimized_univar::generate_keypairs(args.num_nodes);
@@ -79,30 +351,425 @@ pub fn bivariate_generate_keypairs(args: BivariateNiDKGKeyPairsArgs) {
 }
 
 pub async fn univariate_threshold_signature(args: UnivariateThresholdSignatureArgs) {
-    univariate::api::run_threshold_signature(
-        args.node_index,
-        args.num_nodes_n as u32,
-        args.threshold,
+    let weights = read_weights(args.num_nodes_n, &args.weights, args.threshold);
+    let result = if args.g2_signatures {
+        if args.output.is_some() {
+            panic!("--output isn't supported with --g2-signatures");
+        }
+        univariate::g2::run_threshold_signature(
+            args.node_index,
+            weights,
+            args.threshold,
+            args.aws,
+            read_message(&args.message),
+        )
+        .await
+    } else {
+        univariate::api::run_threshold_signature(
+            args.node_index,
+            weights,
+            args.threshold,
+            args.aws,
+            read_message(&args.message),
+            args.output,
+        )
+        .await
+    };
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+pub async fn bivariate_threshold_signature(args: BivariateThresholdSignatureArgs) {
+    check_threshold(
+        (args.num_nodes_n, args.num_nodes_m),
+        (args.threshold_t, args.threshold_t_prime),
+    )
+    .expect("invalid threshold");
+    let result = if args.g2_signatures {
+        if args.output.is_some() {
+            panic!("--output isn't supported with --g2-signatures");
+        }
+        if args.accountability.is_some() {
+            panic!("--accountability isn't supported with --g2-signatures");
+        }
+        bivariate::g2::run_threshold_signature(
+            (args.node_index_i, args.node_index_j),
+            (args.num_nodes_n as u32, args.num_nodes_m as u32),
+            (args.threshold_t, args.threshold_t_prime),
+            args.aws,
+            args.topology.parse().expect("invalid topology"),
+            args.dissemination.parse().expect("invalid dissemination"),
+            args.aggregation.parse().expect("invalid aggregation"),
+            read_message(&args.message),
+        )
+        .await
+    } else {
+        bivariate::api::run_threshold_signature(
+            (args.node_index_i, args.node_index_j),
+            (args.num_nodes_n as u32, args.num_nodes_m as u32),
+            (args.threshold_t, args.threshold_t_prime),
+            args.aws,
+            args.topology.parse().expect("invalid topology"),
+            args.dissemination.parse().expect("invalid dissemination"),
+            args.aggregation.parse().expect("invalid aggregation"),
+            read_message(&args.message),
+            args.output,
+            args.accountability,
+        )
+        .await
+    };
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+pub async fn serve(args: ServeArgs) {
+    check_threshold(
+        (args.num_nodes_n, args.num_nodes_m),
+        (args.threshold_t, args.threshold_t_prime),
+    )
+    .expect("invalid threshold");
+    let socket = args.socket.unwrap_or(format!(
+        "node_{}_{}.sock",
+        args.node_index_i, args.node_index_j
+    ));
+    bivariate::serve::run_server(
+        (args.node_index_i, args.node_index_j),
+        (args.num_nodes_n as u32, args.num_nodes_m as u32),
+        (args.threshold_t, args.threshold_t_prime),
         args.aws,
+        args.topology.parse().expect("invalid topology"),
+        &socket,
+        args.g2_signatures,
     )
     .await;
 }
 
-pub async fn bivariate_threshold_signature(args: BivariateThresholdSignatureArgs) {
-    bivariate::api::run_threshold_signature(
+pub async fn beacon(args: BeaconArgs) {
+    check_threshold(
+        (args.num_nodes_n, args.num_nodes_m),
+        (args.threshold_t, args.threshold_t_prime),
+    )
+    .expect("invalid threshold");
+    let chain = args.chain.unwrap_or(format!(
+        "beacon_{}_{}",
+        args.node_index_i, args.node_index_j
+    ));
+    bivariate::beacon::run_beacon(
+        (args.node_index_i, args.node_index_j),
+        (args.num_nodes_n as u32, args.num_nodes_m as u32),
+        (args.threshold_t, args.threshold_t_prime),
+        args.aws,
+        args.topology.parse().expect("invalid topology"),
+        Duration::from_millis(args.period),
+        args.rounds,
+        &chain,
+    )
+    .await;
+}
+
+pub async fn client(args: ClientArgs) {
+    bivariate::serve::run_client(&args.socket, args.requests, args.concurrency, &args.domain).await;
+}
+
+// verify a signature against public coefficients, exits with 1 if it's invalid
+pub fn verify(args: VerifyArgs) {
+    let coefficients = read_coefficients(&args.coefficients);
+    let level: Level = args.level.parse().expect("invalid level");
+    let (signable, signature) = match (&args.signature, &args.signature_file) {
+        (Some(signature), None) => (
+            read_message(&args.message),
+            signature_from_hex(signature).expect("invalid signature"),
+        ),
+        (None, Some(path)) => {
+            if args.message.message_hex.is_some() || args.message.message_file.is_some() {
+                panic!("a signature file holds its own message");
+            }
+            let file = SignatureFile::read(path).expect("unable to read signature file");
+            (file.signable, file.signature)
+        }
+        _ => panic!("one of --signature and --signature-file is needed"),
+    };
+
+    // a signature file names its domain, it has to be the one we expect
+    let result = if signable.domain != args.message.domain {
+        Err(format!(
+            "signature is for domain {:?}, not {:?}",
+            signable.domain, args.message.domain
+        ))
+    } else {
+        coefficients.verify(&signable.bytes(), signature, level)
+    };
+    let result = result.and_then(|()| match (&args.accountability, &coefficients) {
+        (None, _) => Ok(()),
+        (Some(path), Coefficients::Bivariate(coefficients)) => {
+            let nodes = args
+                .nodes
+                .as_deref()
+                .ok_or("--accountability needs --nodes <n>,<m>")?;
+            let nodes = parse_pair(nodes).ok_or("invalid --nodes")?;
+            Accountability::read(path)?.verify(&signable.bytes(), coefficients, nodes)
+        }
+        (Some(_), Coefficients::Univariate(_)) => {
+            Err("accountability needs bivariate coefficients".to_string())
+        }
+    });
+
+    match result {
+        Ok(()) => println!("valid"),
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the coefficients in `path`, exits with 1 if they can't be read
+fn read_coefficients(path: &str) -> Coefficients {
+    Coefficients::read(path).unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    })
+}
+
+// verify a beacon chain, or one round of it, against the top level key
+pub fn verify_beacon(args: VerifyBeaconArgs) {
+    let coefficients = read_coefficients(&args.coefficients);
+    let public_key = coefficients
+        .public_key(Level::Top)
+        .expect("invalid coefficients");
+    let chain = Chain::read(&args.chain).expect("unable to read chain");
+    let result = match args.round {
+        Some(round) => chain.verify_round(round, public_key),
+        None => chain.verify(public_key),
+    };
+
+    match result {
+        Ok(()) => println!("valid"),
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// encrypt a message to the top level key of the coefficients
+pub fn encrypt(args: EncryptArgs) {
+    let coefficients = Coefficients::read(&args.coefficients).expect("unable to read coefficients");
+    let public_key = coefficients
+        .public_key(Level::Top)
+        .expect("invalid coefficients");
+    let message = read_bytes(&args.message_hex, &args.message_file);
+    decrypt::encrypt(&public_key, &message, &mut rand::thread_rng())
+        .write(&args.output)
+        .expect("unable to write ciphertext");
+}
+
+pub async fn bivariate_threshold_decryption(args: BivariateThresholdDecryptionArgs) {
+    check_threshold(
+        (args.num_nodes_n, args.num_nodes_m),
+        (args.threshold_t, args.threshold_t_prime),
+    )
+    .expect("invalid threshold");
+    let result = bivariate::decrypt::run_threshold_decryption(
         (args.node_index_i, args.node_index_j),
         (args.num_nodes_n as u32, args.num_nodes_m as u32),
         (args.threshold_t, args.threshold_t_prime),
         args.aws,
+        args.topology.parse().expect("invalid topology"),
+        args.dissemination.parse().expect("invalid dissemination"),
+        decrypt::Ciphertext::read(&args.ciphertext).expect("unable to read ciphertext"),
+        args.output,
+    )
+    .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+pub fn heterogeneous_share_file(args: HeterogeneousShareFileArgs) {
+    bivariate::heterogeneous::write_dealing_to_file(&read_shape(&args.shape));
+}
+
+pub async fn heterogeneous_dkg(args: HeterogeneousDKGArgs) {
+    let my_id = (args.node_index_i, args.node_index_j);
+    let shape = read_shape(&args.shape);
+    let ids: Vec<Id> = shape.nodes().map(|(i, j)| Id::Bivariate(i, j)).collect();
+    let filename = format!("results/heterogeneous_dkg_{}", shape);
+    let params = SharingParams {
+        layout: shape,
+        ids: ids.clone(),
+    };
+
+    // sign with the new key to check it
+    let msg: [u8; 32] = [0; 32];
+    run_dkg::<SharingDkg<heterogeneous::Dealing>>(
+        ids,
+        Id::Bivariate(my_id.0, my_id.1),
+        params,
+        args.aws,
+        args.reliable,
+        |(coefficients, sk)| {
+            Signing::<G1, _>::new(&msg, coefficients)
+                .verify(&[my_id.0, my_id.1], &G1::sign(&msg, sk))
+                .unwrap();
+        },
+        &filename,
+    )
+    .await;
+}
+
+pub async fn heterogeneous_threshold_signature(args: HeterogeneousThresholdSignatureArgs) {
+    let result = bivariate::heterogeneous::run_threshold_signature(
+        (args.node_index_i, args.node_index_j),
+        read_shape(&args.shape),
+        args.aws,
+        args.topology.parse().expect("invalid topology"),
+        read_message(&args.message),
+    )
+    .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// the sizes and thresholds of the groups from comma separated lists
+fn read_shape(args: &ShapeArgs) -> Shape {
+    let list = |list: &str| -> Vec<usize> {
+        list.split(',')
+            .map(|k| k.parse().expect("invalid group list"))
+            .collect()
+    };
+    Shape::new(list(&args.groups), list(&args.thresholds), args.threshold_t)
+        .expect("invalid groups")
+}
+
+pub fn trivariate_share_file(args: TrivariateShareFileArgs) {
+    bivariate::trivariate::write_dealing_to_file(&read_levels(&args.levels));
+}
+
+pub async fn trivariate_dkg(args: TrivariateDKGArgs) {
+    let my_id = (args.node_index_i, args.node_index_j, args.node_index_k);
+    let levels = read_levels(&args.levels);
+    let ids = bivariate::trivariate::ids(&levels);
+    let filename = format!("results/trivariate_dkg_{}", levels);
+    let params = SharingParams {
+        layout: levels,
+        ids: ids.clone(),
+    };
+
+    // sign with the new key to check it
+    let msg: [u8; 32] = [0; 32];
+    run_dkg::<SharingDkg<multivariate::Dealing>>(
+        ids,
+        Id::Trivariate(my_id.0, my_id.1, my_id.2),
+        params,
+        args.aws,
+        args.reliable,
+        |(coefficients, sk)| {
+            Signing::<G1, _>::new(&msg, coefficients)
+                .verify(&[my_id.0, my_id.1, my_id.2], &G1::sign(&msg, sk))
+                .unwrap();
+        },
+        &filename,
+    )
+    .await;
+}
+
+pub async fn trivariate_threshold_signature(args: TrivariateThresholdSignatureArgs) {
+    let result = bivariate::trivariate::run_threshold_signature(
+        (args.node_index_i, args.node_index_j, args.node_index_k),
+        read_levels(&args.levels),
+        args.aws,
+        read_message(&args.message),
     )
     .await;
+    if let Err(err) = result {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// the sizes and thresholds of the levels from comma separated lists
+fn read_levels(args: &LevelArgs) -> Levels {
+    let list = |list: &str| -> Vec<usize> {
+        list.split(',')
+            .map(|k| k.parse().expect("invalid level list"))
+            .collect()
+    };
+    Levels::new(list(&args.sizes), list(&args.thresholds)).expect("invalid levels")
+}
+
+// the message to sign from a hex argument, a file or stdin, bound to its domain
+fn read_message(args: &MessageArgs) -> Signable {
+    Signable::new(
+        &args.domain,
+        read_bytes(&args.message_hex, &args.message_file),
+    )
+}
+
+// bytes from a hex argument, a file or stdin, 32 zero bytes if neither is given
+fn read_bytes(message_hex: &Option<String>, message_file: &Option<String>) -> Vec<u8> {
+    match (message_hex, message_file) {
+        (Some(_), Some(_)) => panic!("--message-hex and --message-file can't be used together"),
+        (Some(message), None) => hex::decode(message).expect("invalid hex message"),
+        (None, Some(path)) if path == "-" => {
+            let mut message = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut message)
+                .expect("unable to read message from stdin");
+            message
+        }
+        (None, Some(path)) => std::fs::read(path).expect("unable to read message file"),
+        (None, None) => vec![0; 32],
+    }
 }
 
 pub fn univariate_share_file(args: UnivariateShareFileArgs) {
-    univariate::api::write_dealing_to_file(args.num_nodes as u32, args.threshold_t);
+    let weights = read_weights(args.num_nodes, &args.weights, args.threshold_t);
+    if args.g2_signatures {
+        univariate::g2::write_dealing_to_file(&weights, args.threshold_t);
+        return;
+    }
+    univariate::api::write_dealing_to_file(&weights, args.threshold_t);
+}
+
+// a pair of indices or sizes written `<a>,<b>`
+fn parse_pair(pair: &str) -> Option<(usize, usize)> {
+    let (a, b) = pair.split_once(',')?;
+    Some((a.parse().ok()?, b.parse().ok()?))
+}
+
+// the weight of each of n nodes, 1 for every node without --weights, for a threshold of t in
+// total weight
+fn read_weights(n: usize, weights: &Option<String>, t: usize) -> Weights {
+    let weights = match weights {
+        Some(weights) => {
+            let weights: Weights = weights.parse().expect("invalid weights");
+            if weights.len() != n {
+                panic!("one weight is needed per node");
+            }
+            weights
+        }
+        None => Weights::uniform(n),
+    };
+    weights.check_threshold(t).expect("invalid threshold");
+    weights
 }
 
 pub fn bivariate_share_file(args: BivariateShareFileArgs) {
+    if args.g2_signatures {
+        bivariate::g2::write_dealing_to_file(
+            (args.num_nodes_n as u32, args.num_nodes_m as u32),
+            (args.threshold_t, args.threshold_t_prime),
+        );
+        return;
+    }
     bivariate::api::write_dealing_to_file(
         (args.num_nodes_n as u32, args.num_nodes_m as u32),
         (args.threshold_t, args.threshold_t_prime),
@@ -8,6 +8,19 @@ async fn main() {
     match command.command {
         Command::UnivariateDKG(args) => univariate_dkg(args).await,
         Command::BivariateDKG(args) => bivariate_dkg(args).await,
+        Command::BivariateRefresh(args) => bivariate_refresh(args).await,
+        Command::BivariateRecovery(args) => bivariate_recovery(args.clone()).await,
+        Command::BivariateReshare(args) => bivariate_reshare(args.clone()).await,
+        Command::HeterogeneousShareFile(args) => heterogeneous_share_file(args.clone()),
+        Command::HeterogeneousDKG(args) => heterogeneous_dkg(args.clone()).await,
+        Command::HeterogeneousThresholdSignature(args) => {
+            heterogeneous_threshold_signature(args.clone()).await
+        }
+        Command::TrivariateShareFile(args) => trivariate_share_file(args.clone()),
+        Command::TrivariateDKG(args) => trivariate_dkg(args.clone()).await,
+        Command::TrivariateThresholdSignature(args) => {
+            trivariate_threshold_signature(args.clone()).await
+        }
         Command::UnivariateNiDKG(args) => univariate_nidkg(args.clone()).await,
         Command::BivariateNiDKG(args) => bivariate_nidkg(args.clone()).await,
         Command::NiDKGKeyPairs(args) => generate_keypairs(args.clone()),
@@ -20,5 +33,13 @@ async fn main() {
         }
         Command::UnivariateShareFile(args) => univariate_share_file(args.clone()),
         Command::BivariateShareFile(args) => bivariate_share_file(args.clone()),
+        Command::Verify(args) => verify(args.clone()),
+        Command::Serve(args) => serve(args.clone()).await,
+        Command::Beacon(args) => beacon(args.clone()).await,
+        Command::VerifyBeacon(args) => verify_beacon(args.clone()),
+        Command::Encrypt(args) => encrypt(args.clone()),
+        Command::BivariateThresholdDecryption(args) => {
+            bivariate_threshold_decryption(args.clone()).await
+        }
     }
 }
@@ -0,0 +1,69 @@
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::collections::BTreeMap;
+
+// Reed-Solomon coding of payloads for reliable broadcast
+// a payload is split into `data_shards` pieces and extended to `total_shards` fragments,
+// any `data_shards` of which are enough to recover it
+
+// galois_8 can't address more shards than this
+pub const MAX_SHARDS: usize = 256;
+
+// split a payload into `total_shards` fragments
+pub fn encode(payload: &[u8], data_shards: usize, total_shards: usize) -> Vec<Vec<u8>> {
+    // prefix the length so the padding can be removed when decoding
+    let mut padded = (payload.len() as u64).to_be_bytes().to_vec();
+    padded.extend_from_slice(payload);
+    let shard_len = (padded.len() + data_shards - 1) / data_shards;
+    padded.resize(shard_len * data_shards, 0);
+
+    let mut shards: Vec<Vec<u8>> = padded
+        .chunks_exact(shard_len)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    shards.resize(total_shards, vec![0; shard_len]);
+
+    if total_shards > data_shards {
+        ReedSolomon::new(data_shards, total_shards - data_shards)
+            .expect("invalid shard counts")
+            .encode(&mut shards)
+            .expect("shards are the same length");
+    }
+    shards
+}
+
+// recover a payload from at least `data_shards` fragments indexed by their position
+// returns None if there aren't enough fragments or they are malformed
+pub fn decode(
+    fragments: &BTreeMap<usize, Vec<u8>>,
+    data_shards: usize,
+    total_shards: usize,
+) -> Option<Vec<u8>> {
+    if fragments.len() < data_shards {
+        return None;
+    }
+
+    let mut shards: Vec<Option<Vec<u8>>> = (0..total_shards)
+        .map(|i| fragments.get(&i).cloned())
+        .collect();
+
+    if total_shards > data_shards {
+        ReedSolomon::new(data_shards, total_shards - data_shards)
+            .ok()?
+            .reconstruct_data(&mut shards)
+            .ok()?;
+    }
+
+    let mut padded = Vec::new();
+    for shard in shards.into_iter().take(data_shards) {
+        padded.extend(shard?);
+    }
+    if padded.len() < 8 {
+        return None;
+    }
+
+    let len = u64::from_be_bytes(padded[..8].try_into().unwrap()) as usize;
+    if len > padded.len() - 8 {
+        return None;
+    }
+    Some(padded[8..8 + len].to_vec())
+}
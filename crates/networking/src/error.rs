@@ -0,0 +1,52 @@
+use std::fmt;
+
+// errors surfaced on a node's receive stream instead of panicking the reader task, or returned
+// when sending
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    // the peer closed the connection, or we have no connection on the route to it
+    Disconnected,
+    // the peer announced a frame larger than MAX_FRAME_SIZE, or a message we send doesn't fit one
+    FrameTooLarge(u64),
+    // the session fell SESSION_CAPACITY frames behind and was closed, later frames are lost
+    Overflow,
+    // any other io error while reading from the socket
+    Io(std::io::ErrorKind),
+    // the receive stream ended, the node was shut down
+    Closed,
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe => NetworkError::Disconnected,
+            kind => NetworkError::Io(kind),
+        }
+    }
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Disconnected => write!(f, "peer disconnected"),
+            NetworkError::FrameTooLarge(size) => write!(
+                f,
+                "frame of {} bytes exceeds the maximum of {} bytes",
+                size,
+                crate::MAX_FRAME_SIZE
+            ),
+            NetworkError::Overflow => write!(
+                f,
+                "session fell more than {} frames behind",
+                crate::SESSION_CAPACITY
+            ),
+            NetworkError::Io(kind) => write!(f, "io error: {:?}", kind),
+            NetworkError::Closed => write!(f, "node was shut down"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
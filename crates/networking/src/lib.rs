@@ -1,2 +1,26 @@
+pub mod erasure;
+pub mod error;
 pub mod node;
+pub mod overlay;
+pub mod protocol;
+pub mod rbc;
+pub mod session;
+pub mod sharing;
+pub mod threshold;
+pub mod traffic;
+pub mod transcript;
+pub use error::*;
 pub use node::*;
+pub use overlay::{Overlay, Topology};
+pub use protocol::{run_dkg_node, run_in_memory, run_protocol, DkgProtocol, Outcome, Outgoing};
+pub use rbc::ReliableBroadcast;
+pub use session::{Session, SessionId, CLOSED_LINGER, DEFAULT_SESSION, SESSION_CAPACITY};
+pub use sharing::{SharingDkg, SharingParams};
+pub use threshold::{
+    child, run_threshold, run_threshold_node, Combined, Flow, Run, ThresholdCore, ThresholdScheme,
+    THRESHOLD_SESSION,
+};
+pub use traffic::{Counters, Traffic};
+pub use transcript::{NiSharing, TranscriptDkg, TranscriptMessage};
+#[cfg(test)]
+mod tests;
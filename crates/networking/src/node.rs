@@ -1,36 +1,216 @@
-use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    sync::{Arc, Mutex as StdMutex},
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufWriter},
     net::{
         tcp::{OwnedReadHalf, OwnedWriteHalf},
         TcpListener, TcpStream,
     },
     sync::{
-        mpsc::{unbounded_channel, UnboundedSender},
+        mpsc,
         watch::{channel, Receiver, Sender},
     },
 };
 use types::Id;
 
-use tokio_stream::{wrappers::UnboundedReceiverStream, StreamMap};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    session::Sessions, NetworkError, Overlay, Session, SessionId, Topology, Traffic,
+    DEFAULT_SESSION,
+};
+
+// largest frame we are willing to allocate for, anything bigger is treated as a hostile peer
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+// number of frames queued for a connection. Our own messages wait for room, frames we forward
+// for others are dropped once it's full, so a reader task never waits on another connection
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+// a received frame, or the reason the connection to the peer stopped
+pub type Received = Result<Vec<u8>, NetworkError>;
+
+// every frame carries its origin and destination so gateways can forward it,
+// and the session it belongs to so the receiver can tell protocol instances apart
+// bincode encodes a struct as its fields in order, so a frame is the header of its recipient
+// followed by the payload, which is only serialized once however many nodes it goes to
+#[derive(Serialize)]
+struct Header {
+    session: SessionId,
+    from: Id,
+    to: Id,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    session: SessionId,
+    from: Id,
+    to: Id,
+    #[serde(with = "serde_bytes")]
+    payload: Vec<u8>,
+}
+
+// a frame queued for a connection, written as its length, `header` and then `body`
+struct Frame {
+    header: Vec<u8>,
+    body: Arc<Vec<u8>>,
+}
+
+impl Frame {
+    fn len(&self) -> usize {
+        self.header.len() + self.body.len()
+    }
+}
+
+// state shared between the node, its sessions and the reader tasks that forward traffic
+pub(crate) struct Shared {
+    my_id: Id,
+    overlay: Overlay,
+    // frames for each neighbour, written by a task per connection so nothing waits on a socket
+    // while holding a lock
+    writers: BTreeMap<Id, mpsc::Sender<Frame>>,
+    traffic: StdMutex<Traffic>,
+    pub(crate) sessions: StdMutex<Sessions>,
+}
+
+impl Shared {
+    // attribute traffic from now on to `phase`, for every session
+    pub(crate) fn set_phase(&self, phase: &str) {
+        self.traffic.lock().unwrap().set_phase(phase);
+    }
+
+    // bytes a frame takes on the wire, including its length prefix
+    fn wire_size(frame_len: usize) -> usize {
+        frame_len + 8
+    }
+
+    // queue a frame for the neighbour on the route to `to`, waiting for room
+    async fn send(&self, to: &Id, frame: Frame) -> Result<(), NetworkError> {
+        let hop = self.overlay.next_hop(to);
+        let writer = self.writers.get(&hop).ok_or(NetworkError::Disconnected)?;
+        writer
+            .send(frame)
+            .await
+            .map_err(|_| NetworkError::Disconnected)
+    }
+
+    // queue a frame we are a gateway for without waiting, it's dropped if the next hop is
+    // CHANNEL_CAPACITY frames behind like frames for a session that is
+    fn forward(&self, to: &Id, frame: Frame) -> Result<(), NetworkError> {
+        let hop = self.overlay.next_hop(to);
+        let writer = self.writers.get(&hop).ok_or(NetworkError::Disconnected)?;
+        writer.try_send(frame).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => NetworkError::Overflow,
+            mpsc::error::TrySendError::Closed(_) => NetworkError::Disconnected,
+        })
+    }
+
+    // send a message of `session` to all nodes in `to`. A message too large for a frame isn't
+    // sent to anyone, nodes we can't reach are reported on the session and skipped
+    pub(crate) async fn broadcast(
+        &self,
+        session: SessionId,
+        msg: &[u8],
+        to: Vec<Id>,
+    ) -> Result<(), NetworkError> {
+        let body = Arc::new(
+            bincode::serialize(serde_bytes::Bytes::new(msg)).expect("failed to serialize message"),
+        );
+        let headers = to
+            .iter()
+            .map(|id| {
+                bincode::serialize(&Header {
+                    session,
+                    from: self.my_id,
+                    to: *id,
+                })
+                .expect("failed to serialize message")
+            })
+            .collect::<Vec<_>>();
+        if let Some(len) = headers.iter().map(|header| header.len() + body.len()).max() {
+            if len > MAX_FRAME_SIZE {
+                return Err(NetworkError::FrameTooLarge(len as u64));
+            }
+        }
+
+        for (id, header) in to.into_iter().zip(headers) {
+            let frame = Frame {
+                header,
+                body: body.clone(),
+            };
+            self.traffic
+                .lock()
+                .unwrap()
+                .sent(id, Shared::wire_size(frame.len()));
+            if let Err(err) = self.send(&id, frame).await {
+                self.sessions.lock().unwrap().fail(session, id, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+// addresses of `ids` in order, one line of the addresses file or one local port per node
+pub fn read_addresses(ids: impl IntoIterator<Item = Id>, aws: bool) -> BTreeMap<Id, String> {
+    let mut addresses = BTreeMap::new();
+    if aws {
+        let mut reader = BufReader::new(File::open("addresses").unwrap());
+
+        for id in ids {
+            let mut addr = String::new();
+            reader.read_line(&mut addr).unwrap();
+            addr.pop();
+            addresses.insert(id, addr);
+        }
+    } else {
+        let mut port = 30000;
+
+        for id in ids {
+            addresses.insert(id, format!("127.0.0.1:{}", port));
+            port += 1;
+        }
+    }
+    addresses
+}
 
 // In hindsight, this isn't a great way to do this.
 
 // struct to store readers/writers
 pub struct Node {
-    writers: BTreeMap<Id, OwnedWriteHalf>,
+    shared: Arc<Shared>,
     stop: Sender<String>,
-    pub recv: StreamMap<Id, UnboundedReceiverStream<Vec<u8>>>,
+    // messages from every node in the default session, keyed by the node that sent them
+    pub recv: ReceiverStream<(Id, Received)>,
 }
 
 impl Node {
     // open a connection to all nodes in addresses
     pub async fn new(addresses: BTreeMap<Id, String>, my_id: Id) -> Node {
+        Self::with_topology(addresses, my_id, Topology::FullMesh).await
+    }
+
+    // open connections to our neighbours in the overlay, other nodes are reached through them
+    pub async fn with_topology(
+        addresses: BTreeMap<Id, String>,
+        my_id: Id,
+        topology: Topology,
+    ) -> Node {
+        let overlay = Overlay::new(my_id, addresses.keys().copied(), topology);
+        let neighbours = overlay.neighbours(addresses.keys().copied());
+
         let mut connect_to = BTreeMap::new();
         let mut to_connect = BTreeSet::new();
         let my_addr = addresses.get(&my_id).unwrap().clone();
 
         for (id, addr) in addresses {
+            if !neighbours.contains(&id) {
+                continue;
+            }
             if my_id < id {
                 connect_to.insert(id, addr);
             } else if my_id > id {
@@ -41,81 +221,176 @@ impl Node {
         let incoming_streams = tokio::spawn(listen(my_addr, to_connect));
 
         tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-        let outgoing_streams = connect(my_id, connect_to).await;
-        let incoming_streams = incoming_streams.await.expect("failed to finish listening");
+        let mut streams = connect(my_id, connect_to).await;
+        streams.extend(incoming_streams.await.expect("failed to finish listening"));
 
-        let mut recv = StreamMap::new();
+        let mut readers = Vec::new();
         let mut writers = BTreeMap::new();
-        let (stop, rx) = channel("go".to_owned());
-        for (id, stream) in outgoing_streams {
+        for (id, stream) in streams {
             let (reader, writer) = stream.into_split();
-            let (sender, receiver) = unbounded_channel();
-            tokio::spawn(read_from_sock(reader, sender, rx.clone()));
-            recv.insert(id, UnboundedReceiverStream::new(receiver));
-            writers.insert(id, writer);
+            let (sender, frames) = mpsc::channel(CHANNEL_CAPACITY);
+            tokio::spawn(write_to_sock(writer, frames));
+            readers.push((id, reader));
+            writers.insert(id, sender);
         }
-        for (id, stream) in incoming_streams {
-            let (reader, writer) = stream.into_split();
-            let (sender, receiver) = unbounded_channel();
-            tokio::spawn(read_from_sock(reader, sender, rx.clone()));
-            recv.insert(id, UnboundedReceiverStream::new(receiver));
-            writers.insert(id, writer);
+
+        let mut sessions = Sessions::default();
+        let receiver = sessions
+            .open(DEFAULT_SESSION)
+            .expect("failed to open default session");
+        let shared = Arc::new(Shared {
+            my_id,
+            overlay,
+            writers,
+            traffic: StdMutex::new(Traffic::default()),
+            sessions: StdMutex::new(sessions),
+        });
+        let (stop, rx) = channel("go".to_owned());
+        for (id, reader) in readers {
+            tokio::spawn(read_from_sock(id, reader, shared.clone(), rx.clone()));
         }
 
         Self {
-            writers,
+            shared,
             stop,
-            recv,
+            recv: ReceiverStream::new(receiver),
         }
     }
 
-    // send a message to all nodes in `to`
-    pub async fn broadcast(&mut self, msg: &[u8], to: Vec<Id>) {
-        let mut to_send = msg.len().to_be_bytes().to_vec();
-        to_send.append(&mut msg.to_vec());
-        for id in to {
-            self.writers
-                .get_mut(&id)
-                .unwrap()
-                .write_all(&to_send)
-                .await
-                .expect("failed to write message");
-        }
+    // number of tcp connections this node holds
+    pub fn connection_count(&self) -> usize {
+        self.shared.writers.len()
+    }
+
+    // attribute traffic from now on to `phase`
+    pub fn set_phase(&self, phase: &str) {
+        self.shared.set_phase(phase);
+    }
+
+    // messages and bytes exchanged so far, per phase and peer
+    pub fn traffic(&self) -> Traffic {
+        self.shared.traffic.lock().unwrap().clone()
+    }
+
+    // start a session over the existing connections, peers open a session with the same id
+    // messages that arrive before we open it are kept until we do. An id can't be opened again
+    // until CLOSED_LINGER after its session closed
+    pub fn open_session(&self, id: SessionId) -> Result<Session, String> {
+        let receiver = self.shared.sessions.lock().unwrap().open(id)?;
+        Ok(Session::new(id, self.shared.clone(), receiver))
     }
 
-    // shutdown the node
+    // send a message to all nodes in `to` in the default session
+    pub async fn broadcast(&mut self, msg: &[u8], to: Vec<Id>) -> Result<(), NetworkError> {
+        self.shared.broadcast(DEFAULT_SESSION, msg, to).await
+    }
+
+    // shutdown the node, ending the receive stream of every session
     pub fn shutdown(&self) {
         self.stop
             .send("stop".to_owned())
             .expect("failed to shutdown network");
+        self.shared.sessions.lock().unwrap().close_all();
+    }
+}
+
+// read a single length prefixed frame, rejecting frames larger than MAX_FRAME_SIZE
+pub(crate) async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Received {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf).await?;
+
+    let msg_size = u64::from_be_bytes(buf);
+    if msg_size > MAX_FRAME_SIZE as u64 {
+        return Err(NetworkError::FrameTooLarge(msg_size));
     }
+
+    let mut read = vec![0; msg_size as usize];
+    reader.read_exact(&mut read).await?;
+    Ok(read)
 }
 
-// read messages and send them to the node's receiver
+// read messages and send them to their session, forwarding those meant for other nodes
+// the first error is forwarded to every open session and ends the task
 async fn read_from_sock(
+    peer: Id,
     mut reader: OwnedReadHalf,
-    sender: UnboundedSender<Vec<u8>>,
+    shared: Arc<Shared>,
     mut stop: Receiver<String>,
 ) {
-    let mut buf = [0; 8];
     loop {
         tokio::select! {
-            _ = stop.changed() => {
-                let s = stop.borrow_and_update();
-                if *s == "stop" {
+            changed = stop.changed() => {
+                if changed.is_err() || *stop.borrow_and_update() == "stop" {
                     break;
                 }
             }
-            Ok(_) = reader.read_exact(&mut buf) => {
-                let msg_size: usize = usize::from_be_bytes(buf);
-                let mut read = vec![0; msg_size];
-                reader.read_exact(&mut read).await.unwrap();
-                sender.send(read.to_vec()).unwrap();
+            frame = read_frame(&mut reader) => {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        shared.sessions.lock().unwrap().fail_all(peer, err);
+                        break;
+                    }
+                };
+
+                let envelope: Envelope = match bincode::deserialize(&frame) {
+                    Ok(envelope) => envelope,
+                    // garbage from a faulty peer
+                    Err(_) => continue,
+                };
+                // routes are fixed, so a frame from `from` only ever reaches us over one link
+                if shared.overlay.previous_hop(&envelope.from, &envelope.to) != Some(peer) {
+                    continue;
+                }
+
+                if envelope.to == shared.my_id {
+                    shared
+                        .traffic
+                        .lock()
+                        .unwrap()
+                        .received(envelope.from, Shared::wire_size(frame.len()));
+                    // never waits, a session that can't keep up fails on its own
+                    shared.sessions.lock().unwrap().deliver(
+                        envelope.session,
+                        envelope.from,
+                        envelope.payload,
+                    );
+                } else {
+                    // we are a gateway on the route, pass it along. Never waits, see CHANNEL_CAPACITY
+                    shared
+                        .traffic
+                        .lock()
+                        .unwrap()
+                        .forwarded(envelope.from, Shared::wire_size(frame.len()));
+                    let frame = Frame {
+                        header: Vec::new(),
+                        body: Arc::new(frame),
+                    };
+                    let _ = shared.forward(&envelope.to, frame);
+                }
             },
         }
     }
 }
 
+// write the frames queued for a neighbour until the node is dropped or the connection fails
+async fn write_to_sock(writer: OwnedWriteHalf, mut frames: mpsc::Receiver<Frame>) {
+    let mut writer = BufWriter::new(writer);
+    while let Some(frame) = frames.recv().await {
+        let written = async {
+            writer
+                .write_all(&(frame.len() as u64).to_be_bytes())
+                .await?;
+            writer.write_all(&frame.header).await?;
+            writer.write_all(&frame.body).await?;
+            writer.flush().await
+        };
+        if written.await.is_err() {
+            break;
+        }
+    }
+}
+
 // listen for connections
 async fn listen(my_addr: String, to_connect: BTreeSet<Id>) -> BTreeMap<Id, TcpStream> {
     let listener = TcpListener::bind(my_addr.clone())
@@ -129,16 +404,7 @@ async fn listen(my_addr: String, to_connect: BTreeSet<Id>) -> BTreeMap<Id, TcpSt
             .await
             .expect("failed to accept incoming connection");
 
-        let mut buf = [0; 8];
-
-        stream
-            .read_exact(&mut buf)
-            .await
-            .expect("failed to read id");
-
-        let msg_size: usize = usize::from_be_bytes(buf);
-        let mut read = vec![0; msg_size];
-        stream.read_exact(&mut read).await.unwrap();
+        let read = read_frame(&mut stream).await.expect("failed to read id");
 
         let id: Id = bincode::deserialize(&read).expect("failed to deserialize id");
         streams.insert(id, stream);
@@ -149,7 +415,7 @@ async fn listen(my_addr: String, to_connect: BTreeSet<Id>) -> BTreeMap<Id, TcpSt
 // connect to all nodes in `to`
 async fn connect(my_id: Id, connect_to: BTreeMap<Id, String>) -> BTreeMap<Id, TcpStream> {
     let mut id_buf = bincode::serialize(&my_id).expect("failed to serialize id");
-    let mut to_send = id_buf.len().to_be_bytes().to_vec();
+    let mut to_send = (id_buf.len() as u64).to_be_bytes().to_vec();
     to_send.append(&mut id_buf);
 
     let mut streams = BTreeMap::new();
@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+use types::Id;
+
+// How nodes are connected to each other. Only bivariate ids are grouped, nodes with univariate
+// or trivariate ids are always connected directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    // every node connects to every other node
+    FullMesh,
+    // nodes connect to their own group, the first `g` members of every group are gateways
+    // that also connect to the gateways of all other groups
+    Gateways(usize),
+    // nodes connect to their own group, the first member of every group is its gateway and
+    // gateways form a tree of the given arity over the groups
+    Tree(usize),
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    // parses "full", "gateways:<g>" or "tree:<arity>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |arg: &str| match arg.parse::<usize>() {
+            Ok(value) if value > 0 => Ok(value),
+            _ => Err(format!("invalid topology argument: {}", arg)),
+        };
+
+        match s.split_once(':') {
+            None if s == "full" => Ok(Topology::FullMesh),
+            Some(("gateways", g)) => Ok(Topology::Gateways(parse(g)?)),
+            Some(("tree", arity)) => Ok(Topology::Tree(parse(arity)?)),
+            _ => Err(format!("unknown topology: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Topology::FullMesh => write!(f, "full"),
+            Topology::Gateways(g) => write!(f, "gateways:{}", g),
+            Topology::Tree(arity) => write!(f, "tree:{}", arity),
+        }
+    }
+}
+
+// routing table of a single node
+pub struct Overlay {
+    my_id: Id,
+    topology: Topology,
+    // members of every group ordered by id, keyed by group index
+    groups: BTreeMap<usize, Vec<Id>>,
+    // group indices in order, a group's position is its place in the tree
+    order: Vec<usize>,
+}
+
+impl Overlay {
+    pub fn new(my_id: Id, ids: impl IntoIterator<Item = Id>, topology: Topology) -> Self {
+        let mut groups: BTreeMap<usize, Vec<Id>> = BTreeMap::new();
+        for id in ids {
+            if let Id::Bivariate(i, _) = id {
+                groups.entry(i).or_default().push(id);
+            }
+        }
+        for members in groups.values_mut() {
+            members.sort();
+        }
+        let order = groups.keys().copied().collect();
+
+        Self {
+            my_id,
+            topology,
+            groups,
+            order,
+        }
+    }
+
+    // the nodes we hold a direct connection to
+    pub fn neighbours(&self, ids: impl IntoIterator<Item = Id>) -> BTreeSet<Id> {
+        ids.into_iter()
+            .filter(|id| *id != self.my_id && self.is_direct(&self.my_id, id))
+            .collect()
+    }
+
+    // the neighbour a message for `to` has to be handed to
+    pub fn next_hop(&self, to: &Id) -> Id {
+        self.next_hop_from(&self.my_id, to)
+    }
+
+    // the neighbour a message from `from` to `to` reaches us through, or None if its route
+    // doesn't pass through us. Routes are fixed, so frames from anyone else are spoofed
+    pub fn previous_hop(&self, from: &Id, to: &Id) -> Option<Id> {
+        if !self.is_known(from) || !self.is_known(to) {
+            return None;
+        }
+        let mut at = *from;
+        // a route never visits a node twice
+        let max_hops = self.groups.values().map(Vec::len).sum::<usize>() + 1;
+        for _ in 0..max_hops {
+            if at == *to {
+                return None;
+            }
+            let next = self.next_hop_from(&at, to);
+            if next == self.my_id {
+                return Some(at);
+            }
+            at = next;
+        }
+        None
+    }
+
+    // the neighbour node `at` hands a message for `to` to
+    fn next_hop_from(&self, at: &Id, to: &Id) -> Id {
+        if self.is_direct(at, to) {
+            return *to;
+        }
+        let (at_group, to_group) = match (group(at), group(to)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return *to,
+        };
+
+        // leave the group through our gateway
+        let slot = self.gateway_slot(at);
+        let gateway = self.gateway(at_group, slot);
+        if gateway != *at {
+            return gateway;
+        }
+
+        match self.topology {
+            Topology::FullMesh => *to,
+            Topology::Gateways(_) => self.gateway(to_group, slot),
+            Topology::Tree(arity) => {
+                let next_group = self.step_towards(at_group, to_group, arity);
+                self.gateway(next_group, 0)
+            }
+        }
+    }
+
+    // whether a grouped node is one of the nodes the overlay was built from
+    fn is_known(&self, id: &Id) -> bool {
+        match group(id) {
+            Some(i) => self
+                .groups
+                .get(&i)
+                .map_or(false, |members| members.contains(id)),
+            None => true,
+        }
+    }
+
+    // whether two nodes are connected directly
+    fn is_direct(&self, a: &Id, b: &Id) -> bool {
+        let (group_a, group_b) = match (group(a), group(b)) {
+            (Some(group_a), Some(group_b)) => (group_a, group_b),
+            _ => return true,
+        };
+        if group_a == group_b {
+            return true;
+        }
+
+        match self.topology {
+            Topology::FullMesh => true,
+            Topology::Gateways(_) => self.is_gateway(a) && self.is_gateway(b),
+            Topology::Tree(arity) => {
+                let (pos_a, pos_b) = (self.position(group_a), self.position(group_b));
+                self.is_gateway(a)
+                    && self.is_gateway(b)
+                    && (parent(pos_a, arity) == Some(pos_b) || parent(pos_b, arity) == Some(pos_a))
+            }
+        }
+    }
+
+    fn gateways_per_group(&self) -> usize {
+        match self.topology {
+            Topology::FullMesh => usize::MAX,
+            Topology::Gateways(g) => g,
+            Topology::Tree(_) => 1,
+        }
+    }
+
+    fn is_gateway(&self, id: &Id) -> bool {
+        match group(id) {
+            Some(i) => self.groups[&i]
+                .iter()
+                .take(self.gateways_per_group())
+                .any(|gateway| gateway == id),
+            None => false,
+        }
+    }
+
+    // gateways are picked by a node's position in its group so traffic spreads across them
+    fn gateway_slot(&self, id: &Id) -> usize {
+        group(id)
+            .and_then(|i| self.groups.get(&i)?.iter().position(|member| member == id))
+            .unwrap_or(0)
+    }
+
+    fn gateway(&self, group: usize, slot: usize) -> Id {
+        let members = &self.groups[&group];
+        let gateways = members.len().min(self.gateways_per_group());
+        members[slot % gateways]
+    }
+
+    fn position(&self, group: usize) -> usize {
+        self.order
+            .iter()
+            .position(|i| *i == group)
+            .expect("unknown group")
+    }
+
+    // the next group on the tree path between two groups
+    fn step_towards(&self, from: usize, to: usize, arity: usize) -> usize {
+        let from = self.position(from);
+        let mut to = self.position(to);
+
+        // walk up from the destination until we hit a child of `from`, or the root
+        while to != 0 {
+            let up = parent(to, arity).unwrap();
+            if up == from {
+                return self.order[to];
+            }
+            to = up;
+        }
+
+        // the destination isn't below us, go towards the root
+        self.order[parent(from, arity).expect("the root is an ancestor of every group")]
+    }
+}
+
+fn group(id: &Id) -> Option<usize> {
+    match id {
+        Id::Bivariate(i, _) => Some(*i),
+        Id::Univariate(_) | Id::Trivariate(_, _, _) => None,
+    }
+}
+
+fn parent(position: usize, arity: usize) -> Option<usize> {
+    if position == 0 {
+        None
+    } else {
+        Some((position - 1) / arity)
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio_stream::StreamExt;
+use types::Id;
+
+use crate::{NetworkError, Node, ReliableBroadcast};
+
+// A common interface for the dkg variants
+//
+// Every dkg is written as a state machine without any IO: it is set up from its parameters, hands
+// back the messages to send and takes the messages it receives until it gives its output, the key
+// share and public coefficients of a node. run_protocol drives any of them over a Node, so a new
+// variant only has to implement DkgProtocol and every variant is timed the same way. run_in_memory
+// runs all nodes of a dkg without a network, for tests, and run_dkg_node a whole node, from
+// connecting to recording its results.
+
+// a message for the transport to send
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outgoing<M> {
+    pub to: Vec<Id>,
+    pub msg: M,
+}
+
+pub trait DkgProtocol: Sized {
+    // everything a node needs to know before the dkg starts
+    type Params;
+    // what nodes send each other, the driver serializes it with bincode
+    type Message: Serialize + DeserializeOwned;
+    // the key share and public coefficients of a node
+    type Output;
+    // why a node couldn't be set up or a message was rejected
+    type Error: Debug;
+
+    // set up node `my_id`
+    fn new(params: Self::Params, my_id: Id) -> Result<Self, Self::Error>;
+
+    // our network id
+    fn id(&self) -> Id;
+
+    // the nodes that run the echo and ready phases when messages go over reliable broadcast
+    fn participants(&self) -> Vec<Id>;
+
+    // the messages to send first, only returned once
+    fn start(&mut self) -> Vec<Outgoing<Self::Message>>;
+
+    // take a message from node `from`, returning the messages to send in reply.
+    // A rejected message leaves the machine as it was
+    fn handle_message(
+        &mut self,
+        from: Id,
+        msg: Self::Message,
+    ) -> Result<Vec<Outgoing<Self::Message>>, Self::Error>;
+
+    // our output the first time it's ready, None before that and after
+    fn poll_output(&mut self) -> Option<Self::Output>;
+
+    // the phase traffic is attributed to
+    fn phase(&self) -> &'static str {
+        "dealing"
+    }
+
+    // whether we can stop once we have our output. Over reliable broadcast a participant keeps
+    // delivering until it's true, so slower participants still get its echoes
+    fn finished(&self) -> bool {
+        true
+    }
+
+    // the times run_dkg_node records between the total time and the traffic, the setup, output
+    // and check times unless a machine keeps its own
+    fn columns(&self, setup: Duration, output: Duration, check: Duration) -> Vec<Duration> {
+        vec![setup, output, check]
+    }
+}
+
+// the output of a run and where the machine spent its time
+pub struct Outcome<O> {
+    pub output: O,
+    // time spent in handle_message
+    pub handle_time: Duration,
+    // time spent in the poll_output that gave the output
+    pub output_time: Duration,
+}
+
+// run `protocol` over `node` until it gives its output.
+// With `reliable` messages to every participant go over reliable broadcast, so every participant
+// sees the same message, and messages to some of them go to them alone. Messages that don't
+// deserialize or that the machine rejects are dropped. Fails if a message doesn't fit in a frame
+// or the node is shut down before the output
+pub async fn run_protocol<P: DkgProtocol>(
+    node: &mut Node,
+    protocol: &mut P,
+    reliable: bool,
+) -> Result<Outcome<P::Output>, NetworkError> {
+    let me = protocol.id();
+    let participants = protocol.participants();
+    let mut handle_time = Duration::ZERO;
+    let outgoing = protocol.start();
+
+    let (output, output_time) = if reliable && participants.contains(&me) {
+        // the broadcast holds on to the node, so the phase can only be set once
+        node.set_phase(protocol.phase());
+        let mut rbc = ReliableBroadcast::new(node, me, participants.clone());
+        send_reliable(&mut rbc, me, &participants, outgoing).await?;
+
+        let mut output = None;
+        loop {
+            if output.is_none() {
+                let t = Instant::now();
+                output = protocol.poll_output().map(|output| (output, t.elapsed()));
+            }
+            if protocol.finished() {
+                if let Some(output) = output.take() {
+                    break output;
+                }
+            }
+
+            // our own messages are delivered as well, but the machine already knows them
+            let (from, msg) = rbc.deliver().await?;
+            if from == me {
+                continue;
+            }
+            let msg = match bincode::deserialize(&msg) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            let t = Instant::now();
+            let replies = protocol.handle_message(from, msg);
+            handle_time += t.elapsed();
+            send_reliable(&mut rbc, me, &participants, replies.unwrap_or_default()).await?;
+        }
+    } else {
+        send(node, protocol, &participants, outgoing, reliable).await?;
+
+        loop {
+            let t = Instant::now();
+            if let Some(output) = protocol.poll_output() {
+                break (output, t.elapsed());
+            }
+
+            let (from, msg) = node.recv.next().await.ok_or(NetworkError::Closed)?;
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            let msg = match bincode::deserialize(&msg) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            let t = Instant::now();
+            let replies = protocol.handle_message(from, msg);
+            handle_time += t.elapsed();
+            if let Ok(replies) = replies {
+                send(node, protocol, &participants, replies, reliable).await?;
+            }
+        }
+    };
+
+    Ok(Outcome {
+        output,
+        handle_time,
+        output_time,
+    })
+}
+
+// run node `me` of a dkg over a Node connected to `addresses`: set the machine up from `params`,
+// run it and time `check` on the output, a signature with the new key. Appends the total time,
+// the columns of the machine and the traffic to `filename`. Fails like run_protocol
+pub async fn run_dkg_node<P: DkgProtocol>(
+    addresses: BTreeMap<Id, String>,
+    me: Id,
+    params: P::Params,
+    reliable: bool,
+    check: impl FnOnce(&P::Output),
+    filename: &str,
+) -> Result<P::Output, NetworkError> {
+    let mut node = Node::new(addresses, me).await;
+
+    let time = Instant::now();
+    let t = Instant::now();
+    let mut protocol = P::new(params, me).expect("invalid node index");
+    let setup_time = t.elapsed();
+
+    let Outcome {
+        output,
+        output_time,
+        ..
+    } = run_protocol(&mut node, &mut protocol, reliable).await?;
+    let t = Instant::now();
+    check(&output);
+    let check_time = t.elapsed();
+
+    // shutdown and record results
+    let total_time = time.elapsed();
+    std::thread::sleep(Duration::from_secs(1));
+    node.shutdown();
+    println!("total_time: {:?}", total_time);
+    let line = std::iter::once(total_time)
+        .chain(protocol.columns(setup_time, output_time, check_time))
+        .map(|time| format!("{:?}", time))
+        .collect::<Vec<_>>()
+        .join(",");
+    node.traffic().append_results(filename, &line, me);
+    Ok(output)
+}
+
+// whether a message to `to` goes to every participant other than `me`
+fn is_broadcast(me: Id, to: &[Id], participants: &[Id]) -> bool {
+    participants.iter().all(|id| *id == me || to.contains(id))
+}
+
+// send messages for a participant: messages to every other participant are reliably broadcast,
+// the others go to their recipients directly
+async fn send_reliable<M: Serialize>(
+    rbc: &mut ReliableBroadcast<'_>,
+    me: Id,
+    participants: &[Id],
+    outgoing: Vec<Outgoing<M>>,
+) -> Result<(), NetworkError> {
+    for Outgoing { to, msg } in outgoing {
+        let msg = bincode::serialize(&msg).unwrap();
+        if is_broadcast(me, &to, participants) {
+            rbc.broadcast(&msg).await?;
+        } else {
+            rbc.send_direct(&msg, to).await?;
+        }
+    }
+    Ok(())
+}
+
+// send messages for a node that doesn't run the echo and ready phases.
+// With `reliable` messages for all participants go out as fragments, to all participants
+async fn send<P: DkgProtocol>(
+    node: &mut Node,
+    protocol: &P,
+    participants: &[Id],
+    outgoing: Vec<Outgoing<P::Message>>,
+    reliable: bool,
+) -> Result<(), NetworkError> {
+    node.set_phase(protocol.phase());
+    for Outgoing { to, msg } in outgoing {
+        let msg = bincode::serialize(&msg).unwrap();
+        if reliable && is_broadcast(protocol.id(), &to, participants) {
+            ReliableBroadcast::new(node, protocol.id(), participants.to_vec())
+                .broadcast(&msg)
+                .await?;
+        } else {
+            node.broadcast(&msg, to).await?;
+        }
+    }
+    Ok(())
+}
+
+// run every node of a dkg in memory, delivering messages in the order they were sent until none
+// are left. Messages go through bincode like over a Node, and rejected ones are dropped.
+// Gives the output of every node that got one
+pub fn run_in_memory<P: DkgProtocol>(nodes: Vec<P>) -> BTreeMap<Id, P::Output> {
+    let mut nodes: BTreeMap<Id, P> = nodes.into_iter().map(|node| (node.id(), node)).collect();
+    let mut queue = VecDeque::new();
+    for (id, node) in nodes.iter_mut() {
+        for Outgoing { to, msg } in node.start() {
+            queue.push_back((*id, to, bincode::serialize(&msg).unwrap()));
+        }
+    }
+
+    while let Some((from, to, msg)) = queue.pop_front() {
+        for id in to {
+            let node = match nodes.get_mut(&id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let replies = match node.handle_message(from, bincode::deserialize(&msg).unwrap()) {
+                Ok(replies) => replies,
+                Err(_) => continue,
+            };
+            for Outgoing { to, msg } in replies {
+                queue.push_back((id, to, bincode::serialize(&msg).unwrap()));
+            }
+        }
+    }
+
+    nodes
+        .iter_mut()
+        .filter_map(|(id, node)| node.poll_output().map(|output| (*id, output)))
+        .collect()
+}
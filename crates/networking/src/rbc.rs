@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio_stream::StreamExt;
+use types::Id;
+
+use crate::{erasure, NetworkError, Node};
+
+// Bracha style reliable broadcast with erasure coded payloads (Cachin-Tessaro AVID)
+//
+// The sender splits its payload into one fragment per participant and sends each participant
+// its fragment together with the hashes of all fragments. Participants echo their fragment to
+// everyone, send ready once n - f echoes agree on the same hashes (or f + 1 readies were seen),
+// and deliver after 2f + 1 readies and enough fragments to decode. Every honest participant
+// delivers the same payload for a sender, or nothing at all if the sender was faulty.
+// A payload meant for one participant alone, like its share of a dealing, goes out directly instead
+// and is delivered as soon as it arrives.
+
+pub(crate) type Hash = [u8; 32];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum RbcMessage {
+    Value {
+        sender: Id,
+        checksums: Vec<Hash>,
+        #[serde(with = "serde_bytes")]
+        fragment: Vec<u8>,
+    },
+    Echo {
+        sender: Id,
+        checksums: Vec<Hash>,
+        #[serde(with = "serde_bytes")]
+        fragment: Vec<u8>,
+    },
+    Ready {
+        sender: Id,
+        root: Hash,
+    },
+    Direct {
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+    },
+}
+
+// state of the broadcast started by one sender
+#[derive(Default)]
+struct Instance {
+    echo_sent: bool,
+    ready_sent: bool,
+    delivered: bool,
+    echoed: BTreeSet<Id>,
+    readied: BTreeSet<Id>,
+    fragments: BTreeMap<Hash, BTreeMap<usize, Vec<u8>>>,
+    readies: BTreeMap<Hash, usize>,
+}
+
+// the echo and ready logic of one participant, without the network
+// every call hands back the messages to send and who to send them to
+pub(crate) struct Core {
+    my_id: Id,
+    // participants echo and ready, their position is the index of the fragment they hold
+    participants: Vec<Id>,
+    faulty: usize,
+    instances: BTreeMap<Id, Instance>,
+    delivered: VecDeque<(Id, Vec<u8>)>,
+}
+
+// a message and the nodes to send it to
+pub(crate) type Outbound = (RbcMessage, Vec<Id>);
+
+impl Core {
+    pub(crate) fn new(my_id: Id, mut participants: Vec<Id>) -> Self {
+        participants.sort();
+        participants.dedup();
+        assert!(
+            !participants.is_empty(),
+            "reliable broadcast needs at least one participant"
+        );
+        assert!(
+            participants.len() <= erasure::MAX_SHARDS,
+            "reliable broadcast supports at most {} participants",
+            erasure::MAX_SHARDS
+        );
+        let faulty = (participants.len() - 1) / 3;
+
+        Self {
+            my_id,
+            participants,
+            faulty,
+            instances: BTreeMap::new(),
+            delivered: VecDeque::new(),
+        }
+    }
+
+    // number of fragments needed to decode a payload
+    fn data_shards(&self) -> usize {
+        self.participants.len() - 2 * self.faulty
+    }
+
+    // a fragment of the payload for every participant
+    pub(crate) fn broadcast(&self, payload: &[u8]) -> Vec<Outbound> {
+        let fragments = erasure::encode(payload, self.data_shards(), self.participants.len());
+        let checksums: Vec<Hash> = fragments.iter().map(|fragment| hash(fragment)).collect();
+
+        self.participants
+            .iter()
+            .zip(fragments)
+            .map(|(id, fragment)| {
+                let msg = RbcMessage::Value {
+                    sender: self.my_id,
+                    checksums: checksums.clone(),
+                    fragment,
+                };
+                (msg, vec![*id])
+            })
+            .collect()
+    }
+
+    // the next payload that was delivered, with its sender
+    pub(crate) fn deliver(&mut self) -> Option<(Id, Vec<u8>)> {
+        self.delivered.pop_front()
+    }
+
+    pub(crate) fn handle(&mut self, from: Id, msg: RbcMessage) -> Vec<Outbound> {
+        match msg {
+            RbcMessage::Value {
+                sender,
+                checksums,
+                fragment,
+            } => {
+                let index = match self.index_of(&self.my_id) {
+                    Some(index) => index,
+                    None => return Vec::new(),
+                };
+                // only the sender may hand out its fragments, and only once
+                if from != sender || !self.is_valid(&checksums, index, &fragment) {
+                    return Vec::new();
+                }
+                let instance = self.instances.entry(sender).or_default();
+                if instance.echo_sent {
+                    return Vec::new();
+                }
+
+                instance.echo_sent = true;
+                let msg = RbcMessage::Echo {
+                    sender,
+                    checksums,
+                    fragment,
+                };
+                vec![(msg, self.participants.clone())]
+            }
+            RbcMessage::Echo {
+                sender,
+                checksums,
+                fragment,
+            } => {
+                let index = match self.index_of(&from) {
+                    Some(index) => index,
+                    None => return Vec::new(),
+                };
+                if !self.is_valid(&checksums, index, &fragment) {
+                    return Vec::new();
+                }
+
+                let root = hash(&checksums.concat());
+                let echo_threshold = self.participants.len() - self.faulty;
+                let instance = self.instances.entry(sender).or_default();
+                if !instance.echoed.insert(from) {
+                    return Vec::new();
+                }
+                let fragments = instance.fragments.entry(root).or_default();
+                fragments.insert(index, fragment);
+                let echoes = fragments.len();
+
+                let mut outgoing = Vec::new();
+                if echoes >= echo_threshold && !instance.ready_sent {
+                    instance.ready_sent = true;
+                    let msg = RbcMessage::Ready { sender, root };
+                    outgoing.push((msg, self.participants.clone()));
+                }
+                outgoing.extend(self.try_deliver(sender, root));
+                outgoing
+            }
+            RbcMessage::Ready { sender, root } => {
+                if self.index_of(&from).is_none() {
+                    return Vec::new();
+                }
+
+                let amplify_threshold = self.faulty + 1;
+                let instance = self.instances.entry(sender).or_default();
+                if !instance.readied.insert(from) {
+                    return Vec::new();
+                }
+                let readies = instance.readies.entry(root).or_default();
+                *readies += 1;
+
+                let mut outgoing = Vec::new();
+                if *readies >= amplify_threshold && !instance.ready_sent {
+                    instance.ready_sent = true;
+                    let msg = RbcMessage::Ready { sender, root };
+                    outgoing.push((msg, self.participants.clone()));
+                }
+                outgoing.extend(self.try_deliver(sender, root));
+                outgoing
+            }
+            // nobody else sees it, so there is nothing to agree on
+            RbcMessage::Direct { payload } => {
+                self.delivered.push_back((from, payload));
+                Vec::new()
+            }
+        }
+    }
+
+    // deliver once 2f + 1 readies and enough fragments agree on the same root
+    fn try_deliver(&mut self, sender: Id, root: Hash) -> Option<Outbound> {
+        let data_shards = self.data_shards();
+        let total_shards = self.participants.len();
+        let ready_threshold = 2 * self.faulty + 1;
+        let my_index = self.index_of(&self.my_id);
+
+        let instance = self.instances.entry(sender).or_default();
+        if instance.delivered || instance.readies.get(&root).copied().unwrap_or(0) < ready_threshold
+        {
+            return None;
+        }
+        let fragments = match instance.fragments.get(&root) {
+            Some(fragments) if fragments.len() >= data_shards => fragments,
+            _ => return None,
+        };
+        instance.delivered = true;
+
+        // re-encode to check the sender committed to a consistent encoding,
+        // if it didn't then no honest participant delivers anything for it
+        let payload = erasure::decode(fragments, data_shards, total_shards)?;
+        let encoded = erasure::encode(&payload, data_shards, total_shards);
+        let checksums: Vec<Hash> = encoded.iter().map(|fragment| hash(fragment)).collect();
+        if hash(&checksums.concat()) != root {
+            return None;
+        }
+        self.delivered.push_back((sender, payload));
+
+        // help participants that are still missing fragments if we never echoed our own
+        let index = my_index?;
+        if instance.echo_sent {
+            return None;
+        }
+        instance.echo_sent = true;
+        let msg = RbcMessage::Echo {
+            sender,
+            checksums,
+            fragment: encoded[index].clone(),
+        };
+        Some((msg, self.participants.clone()))
+    }
+
+    fn index_of(&self, id: &Id) -> Option<usize> {
+        self.participants.binary_search(id).ok()
+    }
+
+    fn is_valid(&self, checksums: &[Hash], index: usize, fragment: &[u8]) -> bool {
+        checksums.len() == self.participants.len() && checksums[index] == hash(fragment)
+    }
+}
+
+pub struct ReliableBroadcast<'a> {
+    node: &'a mut Node,
+    core: Core,
+    // messages we sent to ourselves
+    pending: VecDeque<(Id, RbcMessage)>,
+}
+
+impl<'a> ReliableBroadcast<'a> {
+    // `participants` is every node taking part in the echo/ready phases, which may include us
+    // senders don't have to be participants, for example dealers pushing a transcript to receivers
+    pub fn new(node: &'a mut Node, my_id: Id, participants: Vec<Id>) -> Self {
+        Self {
+            node,
+            core: Core::new(my_id, participants),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // reliably broadcast a payload to all participants
+    pub async fn broadcast(&mut self, payload: &[u8]) -> Result<(), NetworkError> {
+        for (msg, to) in self.core.broadcast(payload) {
+            self.send(&msg, to).await?;
+        }
+        Ok(())
+    }
+
+    // send a payload to `to` without the echo and ready phases, it's delivered to them alone
+    pub async fn send_direct(&mut self, payload: &[u8], to: Vec<Id>) -> Result<(), NetworkError> {
+        let msg = RbcMessage::Direct {
+            payload: payload.to_vec(),
+        };
+        self.send(&msg, to).await
+    }
+
+    // wait until the broadcast of some sender is delivered, fails once the node is shut down or
+    // an echo or ready message doesn't fit in a frame
+    pub async fn deliver(&mut self) -> Result<(Id, Vec<u8>), NetworkError> {
+        loop {
+            if let Some(delivered) = self.core.deliver() {
+                return Ok(delivered);
+            }
+
+            let (from, msg) = match self.pending.pop_front() {
+                Some(pending) => pending,
+                None => {
+                    let (from, msg) = self.node.recv.next().await.ok_or(NetworkError::Closed)?;
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(_) => continue,
+                    };
+                    match bincode::deserialize(&msg) {
+                        Ok(msg) => (from, msg),
+                        // garbage from a faulty peer
+                        Err(_) => continue,
+                    }
+                }
+            };
+
+            for (msg, to) in self.core.handle(from, msg) {
+                self.send(&msg, to).await?;
+            }
+        }
+    }
+
+    // send a message, looping it back locally if we are one of the recipients
+    async fn send(&mut self, msg: &RbcMessage, to: Vec<Id>) -> Result<(), NetworkError> {
+        let bytes = bincode::serialize(msg).expect("failed to serialize message");
+        let mut others = Vec::new();
+        for id in to {
+            if id == self.core.my_id {
+                self.pending.push_back((id, msg.clone()));
+            } else {
+                others.push(id);
+            }
+        }
+        self.node.broadcast(&bytes, others).await
+    }
+}
+
+pub(crate) fn hash(bytes: &[u8]) -> Hash {
+    Sha256::digest(bytes).into()
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use types::Id;
+
+use crate::{node::Shared, NetworkError, Received};
+
+// sessions let several protocol instances share the connections of one node
+pub type SessionId = u64;
+
+// the session `Node::broadcast` and `Node::recv` use
+pub const DEFAULT_SESSION: SessionId = 0;
+
+// frames a session holds that its consumer hasn't taken yet, including those that arrive before
+// it's opened. A session that falls further behind is closed with NetworkError::Overflow, so a
+// slow session never holds up the others
+pub const SESSION_CAPACITY: usize = 16 * 1024;
+
+// how long frames for a closed session are dropped for, after that its id can be opened again
+pub const CLOSED_LINGER: Duration = Duration::from_secs(60);
+
+// where the readers deliver frames to
+#[derive(Default)]
+pub(crate) struct Sessions {
+    open: BTreeMap<SessionId, mpsc::Sender<(Id, Received)>>,
+    // frames for sessions we haven't opened yet, a peer may start a session before we do
+    early: BTreeMap<SessionId, Vec<(Id, Vec<u8>)>>,
+    // sessions that got more early frames than they can hold and the peer whose frame didn't fit,
+    // they fail once opened
+    overflowed: BTreeMap<SessionId, Id>,
+    // when each session was closed, frames for them are dropped instead of buffered
+    closed: BTreeMap<SessionId, Instant>,
+}
+
+impl Sessions {
+    // register a session and hand it any frames that arrived before it was opened
+    pub(crate) fn open(
+        &mut self,
+        session: SessionId,
+    ) -> Result<mpsc::Receiver<(Id, Received)>, String> {
+        self.closed
+            .retain(|_, closed_at| closed_at.elapsed() < CLOSED_LINGER);
+        if self.open.contains_key(&session) || self.closed.contains_key(&session) {
+            return Err(format!("session {} was already opened", session));
+        }
+
+        // one more slot than the frames it holds, so there is always room to report an overflow
+        let (sender, receiver) = mpsc::channel(SESSION_CAPACITY + 1);
+        for (from, payload) in self.early.remove(&session).unwrap_or_default() {
+            // can't fail, we never buffer more than the channel holds
+            let _ = sender.try_send((from, Ok(payload)));
+        }
+        self.open.insert(session, sender);
+        if let Some(from) = self.overflowed.remove(&session) {
+            self.overflow(session, from);
+        }
+        Ok(receiver)
+    }
+
+    // stop delivering to a session, its receive stream ends once drained
+    pub(crate) fn close(&mut self, session: SessionId) {
+        self.open.remove(&session);
+        self.closed.insert(session, Instant::now());
+    }
+
+    // close every session, called when the node shuts down
+    pub(crate) fn close_all(&mut self) {
+        let sessions: Vec<SessionId> = self.open.keys().copied().collect();
+        for session in sessions {
+            self.close(session);
+        }
+    }
+
+    // hand a frame to its session without waiting, buffering it if the session isn't open yet.
+    // Frames for closed sessions are dropped
+    pub(crate) fn deliver(&mut self, session: SessionId, from: Id, payload: Vec<u8>) {
+        if let Some(sender) = self.open.get(&session) {
+            if sender.capacity() > 1 {
+                // only fails if the session was dropped, which closes it anyway
+                let _ = sender.try_send((from, Ok(payload)));
+            } else {
+                self.overflow(session, from);
+            }
+            return;
+        }
+        if self.closed.contains_key(&session) || self.overflowed.contains_key(&session) {
+            return;
+        }
+
+        let early = self.early.entry(session).or_default();
+        if early.len() < SESSION_CAPACITY {
+            early.push((from, payload));
+        } else {
+            self.overflowed.insert(session, from);
+        }
+    }
+
+    // report a dead connection to every open session
+    pub(crate) fn fail_all(&mut self, from: Id, err: NetworkError) {
+        let sessions: Vec<SessionId> = self.open.keys().copied().collect();
+        for session in sessions {
+            self.fail(session, from, err.clone());
+        }
+    }
+
+    // report to a session that `from` can't be reached, if it's open
+    pub(crate) fn fail(&mut self, session: SessionId, from: Id, err: NetworkError) {
+        if let Some(sender) = self.open.get(&session) {
+            if sender.capacity() > 1 {
+                let _ = sender.try_send((from, Err(err)));
+            } else {
+                self.overflow(session, from);
+            }
+        }
+    }
+
+    // tell an open session that a frame from `from` didn't fit and close it.
+    // The slot kept free for this always has room
+    fn overflow(&mut self, session: SessionId, from: Id) {
+        if let Some(sender) = self.open.get(&session) {
+            let _ = sender.try_send((from, Err(NetworkError::Overflow)));
+        }
+        self.close(session);
+    }
+}
+
+// one protocol instance running over a node's connections
+// messages sent through a session are only received by the session with the same id on the peer
+pub struct Session {
+    id: SessionId,
+    shared: Arc<Shared>,
+    // messages from every node in this session, keyed by the node that sent them
+    pub recv: ReceiverStream<(Id, Received)>,
+}
+
+impl Session {
+    pub(crate) fn new(
+        id: SessionId,
+        shared: Arc<Shared>,
+        receiver: mpsc::Receiver<(Id, Received)>,
+    ) -> Self {
+        Self {
+            id,
+            shared,
+            recv: ReceiverStream::new(receiver),
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    // attribute traffic from now on to `phase`. The phase is the node's, so sessions running at
+    // the same time shouldn't set it
+    pub fn set_phase(&self, phase: &str) {
+        self.shared.set_phase(phase);
+    }
+
+    // send a message to all nodes in `to`, nodes we can't reach are reported on `recv`
+    pub async fn broadcast(&mut self, msg: &[u8], to: Vec<Id>) -> Result<(), NetworkError> {
+        self.shared.broadcast(self.id, msg, to).await
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shared.sessions.lock().unwrap().close(self.id);
+    }
+}
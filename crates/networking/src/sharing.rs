@@ -0,0 +1,130 @@
+use std::collections::BTreeSet;
+
+use types::{bivariate::Message, Id, Sharing};
+
+use crate::{DkgProtocol, Outgoing};
+
+// A dkg on any sharing (see types::Sharing) as a state machine without any IO
+//
+// The machine deals when it is created and hands back the messages to send, takes the messages
+// it receives from any transport and gives its key once it holds a dealing from every node.
+// It never blocks or touches the network, so a test can drive any number of nodes in memory and
+// run_protocol drives it over a Node. Bivariate, heterogeneous, multivariate and G1 keyed
+// dealings all run on it.
+
+// the layout to deal to and the id of the node at each of its positions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharingParams<L> {
+    pub layout: L,
+    pub ids: Vec<Id>,
+}
+
+pub struct SharingDkg<S: Sharing> {
+    layout: S::Layout,
+    ids: Vec<Id>,
+    // our position in the layout
+    position: usize,
+    // ours comes first, and is serialized and sent on the first call to start
+    dealings: Vec<S>,
+    started: bool,
+    senders: BTreeSet<Id>,
+    // whether poll_output gave our key
+    done: bool,
+}
+
+impl<S: Sharing> SharingDkg<S> {
+    // the number of dealings we hold, ours included
+    pub fn dealing_count(&self) -> usize {
+        self.dealings.len()
+    }
+
+    fn insert(&mut self, from: Id, dealing: S) {
+        self.senders.insert(from);
+        self.dealings.push(dealing);
+    }
+}
+
+impl<S: Sharing> DkgProtocol for SharingDkg<S> {
+    type Params = SharingParams<S::Layout>;
+    type Message = Message;
+    type Output = S::Output;
+    type Error = String;
+
+    // generate our dealing for node `my_id`
+    fn new(params: Self::Params, my_id: Id) -> Result<Self, String> {
+        let SharingParams { layout, ids } = params;
+        if ids.len() != S::node_count(&layout) {
+            return Err("one id is needed per node of the layout".to_string());
+        }
+        let position = ids
+            .iter()
+            .position(|id| *id == my_id)
+            .ok_or(format!("{:?} isn't a node of the dkg", my_id))?;
+
+        let dealing = S::generate(&layout);
+
+        let mut dkg = SharingDkg {
+            layout,
+            ids,
+            position,
+            dealings: Vec::new(),
+            started: false,
+            senders: BTreeSet::new(),
+            done: false,
+        };
+        dkg.insert(my_id, dealing);
+        Ok(dkg)
+    }
+
+    fn id(&self) -> Id {
+        self.ids[self.position]
+    }
+
+    fn participants(&self) -> Vec<Id> {
+        self.ids.clone()
+    }
+
+    // our dealing to send to every other node, serialized here so that generating it in new can
+    // be timed alone
+    fn start(&mut self) -> Vec<Outgoing<Message>> {
+        if std::mem::replace(&mut self.started, true) {
+            return Vec::new();
+        }
+        let me = self.id();
+        let to: Vec<Id> = self.ids.iter().filter(|id| **id != me).copied().collect();
+        let (serialized_coefficients, serialized_shares) = self.dealings[0].serialize();
+        vec![Outgoing {
+            to,
+            msg: Message::Shares(serialized_coefficients, serialized_shares),
+        }]
+    }
+
+    // messages that don't fit the layout and second dealings from the same node are rejected
+    fn handle_message(&mut self, from: Id, msg: Message) -> Result<Vec<Outgoing<Message>>, String> {
+        if !self.ids.contains(&from) {
+            return Err(format!("{:?} isn't a node of the dkg", from));
+        }
+        if self.senders.contains(&from) {
+            return Err(format!("{:?} already sent a dealing", from));
+        }
+
+        match msg {
+            Message::Shares(serialized_coefficients, serialized_shares) => {
+                let dealing =
+                    S::deserialize(&self.layout, &serialized_coefficients, &serialized_shares)
+                        .map_err(|err| format!("dealing of {:?}: {}", from, err))?;
+                self.insert(from, dealing);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    // combined from the dealings once there is one from every node
+    fn poll_output(&mut self) -> Option<S::Output> {
+        if self.done || self.dealings.len() < self.ids.len() {
+            return None;
+        }
+        self.done = true;
+        Some(S::combine(&self.layout, self.position, &self.dealings))
+    }
+}
@@ -0,0 +1,611 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    erasure,
+    node::read_frame,
+    rbc::{hash, Core, Outbound, RbcMessage},
+    session::Sessions,
+    Flow, NetworkError, Overlay, ThresholdCore, ThresholdScheme, Topology, Traffic,
+    DEFAULT_SESSION, MAX_FRAME_SIZE, SESSION_CAPACITY,
+};
+use types::Id;
+
+// follow next hops from `from` to `to`, returning the number of hops taken
+fn route(ids: &[Id], topology: Topology, from: Id, to: Id) -> usize {
+    let mut hops = 0;
+    let mut at = from;
+    while at != to {
+        let overlay = Overlay::new(at, ids.iter().copied(), topology);
+        let next = overlay.next_hop(&to);
+        assert!(
+            overlay.neighbours(ids.iter().copied()).contains(&next),
+            "{:?} routed to {:?} which it isn't connected to",
+            at,
+            next
+        );
+        at = next;
+        hops += 1;
+        assert!(
+            hops <= ids.len(),
+            "routing loop from {:?} to {:?}",
+            from,
+            to
+        );
+    }
+    hops
+}
+
+// Tests every node can reach every other node over each topology
+#[test]
+fn overlay_routes_reach_every_node() {
+    let ids: Vec<Id> = (0..7)
+        .flat_map(|i| (0..5).map(move |j| Id::Bivariate(i, j)))
+        .collect();
+
+    for topology in [Topology::FullMesh, Topology::Gateways(2), Topology::Tree(2)] {
+        for from in &ids {
+            for to in &ids {
+                let hops = route(&ids, topology, *from, *to);
+                if topology == Topology::Gateways(2) {
+                    assert!(hops <= 3, "gateway route took {} hops", hops);
+                }
+            }
+        }
+    }
+}
+
+// Tests every node on a route knows which neighbour the route reaches it through, and nodes off
+// the route or frames from unknown nodes are rejected
+#[test]
+fn overlay_previous_hops_follow_routes() {
+    let ids: Vec<Id> = (0..5)
+        .flat_map(|i| (0..4).map(move |j| Id::Bivariate(i, j)))
+        .collect();
+
+    for topology in [Topology::FullMesh, Topology::Gateways(2), Topology::Tree(2)] {
+        let overlays: BTreeMap<Id, Overlay> = ids
+            .iter()
+            .map(|id| (*id, Overlay::new(*id, ids.iter().copied(), topology)))
+            .collect();
+        for from in &ids {
+            for to in &ids {
+                let mut on_route = vec![*from];
+                while on_route.last() != Some(to) {
+                    let at = *on_route.last().unwrap();
+                    let next = overlays[&at].next_hop(to);
+                    assert_eq!(overlays[&next].previous_hop(from, to), Some(at));
+                    on_route.push(next);
+                }
+                for id in ids.iter().filter(|id| !on_route.contains(id)) {
+                    assert_eq!(overlays[id].previous_hop(from, to), None);
+                }
+            }
+        }
+
+        let unknown = Id::Bivariate(9, 0);
+        let me = Id::Bivariate(0, 0);
+        assert_eq!(overlays[&me].previous_hop(&unknown, &me), None);
+        assert_eq!(overlays[&me].previous_hop(&Id::Bivariate(0, 9), &me), None);
+    }
+}
+
+// Tests gateways cut the number of connections a non gateway node holds down to its group
+#[test]
+fn overlay_connection_counts() {
+    let ids: Vec<Id> = (0..10)
+        .flat_map(|i| (0..10).map(move |j| Id::Bivariate(i, j)))
+        .collect();
+
+    let neighbours = |id: Id, topology: Topology| {
+        Overlay::new(id, ids.iter().copied(), topology)
+            .neighbours(ids.iter().copied())
+            .len()
+    };
+
+    assert_eq!(neighbours(Id::Bivariate(3, 4), Topology::FullMesh), 99);
+    assert_eq!(neighbours(Id::Bivariate(3, 4), Topology::Gateways(2)), 9);
+    assert_eq!(
+        neighbours(Id::Bivariate(3, 1), Topology::Gateways(2)),
+        9 + 9 * 2
+    );
+    // group 0 is the root of the tree and has two children
+    assert_eq!(neighbours(Id::Bivariate(0, 0), Topology::Tree(2)), 9 + 2);
+}
+
+// Tests frames for a session that isn't open yet are handed over when it opens
+#[test]
+fn sessions_buffer_early_frames() {
+    let mut sessions = Sessions::default();
+    let from = Id::Univariate(1);
+
+    sessions.deliver(7, from, vec![1]);
+    sessions.deliver(7, from, vec![2]);
+
+    let mut receiver = sessions.open(7).unwrap();
+    assert_eq!(receiver.try_recv().unwrap(), (from, Ok(vec![1])));
+    assert_eq!(receiver.try_recv().unwrap(), (from, Ok(vec![2])));
+    assert!(receiver.try_recv().is_err());
+
+    sessions.deliver(7, from, vec![3]);
+    assert_eq!(receiver.try_recv().unwrap(), (from, Ok(vec![3])));
+}
+
+// Tests closed sessions drop their frames and can't be opened again
+#[test]
+fn sessions_drop_frames_once_closed() {
+    let mut sessions = Sessions::default();
+    let mut receiver = sessions.open(DEFAULT_SESSION).unwrap();
+    assert!(sessions.open(DEFAULT_SESSION).is_err());
+
+    sessions.close(DEFAULT_SESSION);
+    sessions.deliver(DEFAULT_SESSION, Id::Univariate(1), vec![1]);
+    assert!(receiver.try_recv().is_err());
+    assert!(sessions.open(DEFAULT_SESSION).is_err());
+}
+
+// Tests a session that falls behind fails with an overflow while other sessions keep going
+#[test]
+fn sessions_overflow_on_their_own() {
+    let mut sessions = Sessions::default();
+    let from = Id::Univariate(1);
+    let mut slow = sessions.open(1).unwrap();
+    let mut fast = sessions.open(2).unwrap();
+
+    for i in 0..SESSION_CAPACITY + 5 {
+        sessions.deliver(1, from, i.to_be_bytes().to_vec());
+        sessions.deliver(2, from, vec![1]);
+        assert_eq!(fast.try_recv().unwrap(), (from, Ok(vec![1])));
+    }
+
+    for i in 0..SESSION_CAPACITY {
+        assert_eq!(
+            slow.try_recv().unwrap(),
+            (from, Ok(i.to_be_bytes().to_vec()))
+        );
+    }
+    assert_eq!(
+        slow.try_recv().unwrap(),
+        (from, Err(NetworkError::Overflow))
+    );
+    // the session was closed, so its stream ends
+    assert_eq!(
+        slow.try_recv(),
+        Err(tokio::sync::mpsc::error::TryRecvError::Disconnected)
+    );
+    assert!(sessions.open(1).is_err());
+}
+
+// Tests a session that got more early frames than it holds fails as soon as it's opened
+#[test]
+fn sessions_overflow_before_opening() {
+    let mut sessions = Sessions::default();
+    let from = Id::Univariate(1);
+    for _ in 0..SESSION_CAPACITY + 1 {
+        sessions.deliver(3, from, vec![1]);
+    }
+
+    let mut receiver = sessions.open(3).unwrap();
+    for _ in 0..SESSION_CAPACITY {
+        assert_eq!(receiver.try_recv().unwrap(), (from, Ok(vec![1])));
+    }
+    assert_eq!(
+        receiver.try_recv().unwrap(),
+        (from, Err(NetworkError::Overflow))
+    );
+}
+
+// Tests a dead connection is reported to every open session
+#[test]
+fn sessions_report_dead_connections() {
+    let mut sessions = Sessions::default();
+    let from = Id::Univariate(1);
+    let mut first = sessions.open(1).unwrap();
+    let mut second = sessions.open(2).unwrap();
+
+    sessions.fail_all(from, NetworkError::Disconnected);
+    assert_eq!(
+        first.try_recv().unwrap(),
+        (from, Err(NetworkError::Disconnected))
+    );
+    assert_eq!(
+        second.try_recv().unwrap(),
+        (from, Err(NetworkError::Disconnected))
+    );
+}
+
+// Tests a node we can't send to is reported to the session that sent alone
+#[test]
+fn sessions_report_unreachable_peers_to_the_sender() {
+    let mut sessions = Sessions::default();
+    let to = Id::Univariate(1);
+    let mut sender = sessions.open(1).unwrap();
+    let mut other = sessions.open(2).unwrap();
+
+    sessions.fail(1, to, NetworkError::Disconnected);
+    assert_eq!(
+        sender.try_recv().unwrap(),
+        (to, Err(NetworkError::Disconnected))
+    );
+    assert!(other.try_recv().is_err());
+    // the session stays open, frames from other nodes still arrive
+    sessions.deliver(1, Id::Univariate(2), vec![1]);
+    assert_eq!(sender.try_recv().unwrap(), (Id::Univariate(2), Ok(vec![1])));
+}
+
+// length prefix a frame like write_to_sock does
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = (payload.len() as u64).to_be_bytes().to_vec();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// Tests frames are read back one at a time, and the end of the stream is a disconnect
+#[tokio::test]
+async fn read_frame_round_trip() {
+    let mut wire = frame(b"first");
+    wire.extend(frame(b""));
+    wire.extend(frame(&[7; 1000]));
+
+    let mut reader = &wire[..];
+    assert_eq!(read_frame(&mut reader).await, Ok(b"first".to_vec()));
+    assert_eq!(read_frame(&mut reader).await, Ok(Vec::new()));
+    assert_eq!(read_frame(&mut reader).await, Ok(vec![7; 1000]));
+    assert_eq!(
+        read_frame(&mut reader).await,
+        Err(NetworkError::Disconnected)
+    );
+}
+
+// Tests a frame larger than MAX_FRAME_SIZE is rejected before anything is allocated for it
+#[tokio::test]
+async fn read_frame_rejects_oversized_frames() {
+    let size = MAX_FRAME_SIZE as u64 + 1;
+    let wire = size.to_be_bytes();
+    assert_eq!(
+        read_frame(&mut &wire[..]).await,
+        Err(NetworkError::FrameTooLarge(size))
+    );
+
+    let wire = u64::MAX.to_be_bytes();
+    assert_eq!(
+        read_frame(&mut &wire[..]).await,
+        Err(NetworkError::FrameTooLarge(u64::MAX))
+    );
+}
+
+// Tests a peer that disconnects in the middle of a frame or its length is reported
+#[tokio::test]
+async fn read_frame_reports_truncated_frames() {
+    let wire = frame(b"truncated");
+    assert_eq!(
+        read_frame(&mut &wire[..wire.len() - 1]).await,
+        Err(NetworkError::Disconnected)
+    );
+    assert_eq!(
+        read_frame(&mut &wire[..4]).await,
+        Err(NetworkError::Disconnected)
+    );
+}
+
+// deliver the messages of a reliable broadcast between `cores` until none are left,
+// messages to nodes without a core are lost. Gives what every core delivered
+fn run_rbc(
+    cores: &mut BTreeMap<Id, Core>,
+    sender: Id,
+    sent: Vec<Outbound>,
+) -> BTreeMap<Id, Vec<(Id, Vec<u8>)>> {
+    let mut queue: VecDeque<(Id, Outbound)> = sent.into_iter().map(|out| (sender, out)).collect();
+    while let Some((from, (msg, to))) = queue.pop_front() {
+        for id in to {
+            if let Some(core) = cores.get_mut(&id) {
+                for out in core.handle(from, msg.clone()) {
+                    queue.push_back((id, out));
+                }
+            }
+        }
+    }
+
+    cores
+        .iter_mut()
+        .map(|(id, core)| (*id, std::iter::from_fn(|| core.deliver()).collect()))
+        .collect()
+}
+
+// Tests every live participant delivers the payload when one of them crashed
+#[test]
+fn rbc_delivers_despite_a_crashed_participant() {
+    let participants: Vec<Id> = (0..4).map(Id::Univariate).collect();
+    // participant 3 is down, so it has no core
+    let mut cores: BTreeMap<Id, Core> = participants[..3]
+        .iter()
+        .map(|id| (*id, Core::new(*id, participants.clone())))
+        .collect();
+
+    let payload = vec![42; 1000];
+    let sender = Id::Univariate(0);
+    let sent = cores[&sender].broadcast(&payload);
+    let delivered = run_rbc(&mut cores, sender, sent);
+
+    for id in &participants[..3] {
+        assert_eq!(delivered[id], vec![(sender, payload.clone())]);
+    }
+}
+
+// Tests a sender that isn't a participant reaches every participant
+#[test]
+fn rbc_delivers_from_outside_the_participants() {
+    let participants: Vec<Id> = (0..7).map(Id::Univariate).collect();
+    let mut cores: BTreeMap<Id, Core> = participants
+        .iter()
+        .map(|id| (*id, Core::new(*id, participants.clone())))
+        .collect();
+
+    let payload = b"transcript".to_vec();
+    let sender = Id::Univariate(9);
+    let sent = Core::new(sender, participants.clone()).broadcast(&payload);
+    let delivered = run_rbc(&mut cores, sender, sent);
+
+    for id in &participants {
+        assert_eq!(delivered[id], vec![(sender, payload.clone())]);
+    }
+}
+
+// Tests fragments that don't come from one encoding are never delivered
+#[test]
+fn rbc_rejects_an_inconsistent_encoding() {
+    let participants: Vec<Id> = (0..4).map(Id::Univariate).collect();
+    let mut cores: BTreeMap<Id, Core> = participants
+        .iter()
+        .map(|id| (*id, Core::new(*id, participants.clone())))
+        .collect();
+
+    // two of the four fragments decode the payload, the last one is tampered with
+    let mut fragments = erasure::encode(&[1; 100], 2, 4);
+    fragments[3][0] ^= 1;
+    let checksums: Vec<_> = fragments.iter().map(|fragment| hash(fragment)).collect();
+
+    let sender = Id::Univariate(0);
+    let sent = participants
+        .iter()
+        .zip(fragments)
+        .map(|(id, fragment)| {
+            let msg = RbcMessage::Value {
+                sender,
+                checksums: checksums.clone(),
+                fragment,
+            };
+            (msg, vec![*id])
+        })
+        .collect();
+    let delivered = run_rbc(&mut cores, sender, sent);
+
+    assert!(delivered.values().all(|delivered| delivered.is_empty()));
+}
+
+// Tests a direct payload is delivered to its recipient alone, without echoes
+#[test]
+fn rbc_delivers_direct_payloads_to_their_recipient() {
+    let participants: Vec<Id> = (0..4).map(Id::Univariate).collect();
+    let mut cores: BTreeMap<Id, Core> = participants
+        .iter()
+        .map(|id| (*id, Core::new(*id, participants.clone())))
+        .collect();
+
+    let payload = b"share".to_vec();
+    let sender = Id::Univariate(0);
+    let msg = RbcMessage::Direct {
+        payload: payload.clone(),
+    };
+    let delivered = run_rbc(&mut cores, sender, vec![(msg, vec![Id::Univariate(2)])]);
+
+    for id in &participants {
+        let expected = if *id == Id::Univariate(2) {
+            vec![(sender, payload.clone())]
+        } else {
+            Vec::new()
+        };
+        assert_eq!(delivered[id], expected);
+    }
+}
+
+// Tests a broadcast without participants is rejected up front
+#[test]
+#[should_panic(expected = "at least one participant")]
+fn rbc_needs_participants() {
+    Core::new(Id::Univariate(0), Vec::new());
+}
+
+// Tests any `data_shards` fragments recover the payload, and fewer don't
+#[test]
+fn erasure_recovers_from_any_data_shards() {
+    for payload in [Vec::new(), vec![5], (0..=255).collect::<Vec<u8>>()] {
+        for (data_shards, total_shards) in [(1, 1), (1, 4), (3, 7), (4, 4)] {
+            let fragments = erasure::encode(&payload, data_shards, total_shards);
+            assert_eq!(fragments.len(), total_shards);
+
+            // the last `data_shards` fragments, so parity is used whenever there is any
+            let kept: BTreeMap<usize, Vec<u8>> = fragments
+                .iter()
+                .cloned()
+                .enumerate()
+                .skip(total_shards - data_shards)
+                .collect();
+            assert_eq!(
+                erasure::decode(&kept, data_shards, total_shards),
+                Some(payload.clone())
+            );
+
+            let mut too_few = kept;
+            too_few.remove(&(total_shards - 1));
+            assert_eq!(erasure::decode(&too_few, data_shards, total_shards), None);
+        }
+    }
+}
+
+// Tests frames are counted against the peer and the phase that was current
+#[test]
+fn traffic_counts_per_phase_and_peer() {
+    let (a, b) = (Id::Univariate(1), Id::Univariate(2));
+    let mut traffic = Traffic::default();
+    traffic.sent(a, 10);
+    traffic.set_phase("dealing");
+    traffic.sent(a, 100);
+    traffic.sent(b, 100);
+    traffic.received(b, 50);
+    traffic.forwarded(b, 7);
+    traffic.set_phase("signing");
+    traffic.received(a, 5);
+    // going back to a phase adds to it
+    traffic.set_phase("dealing");
+    traffic.received(b, 50);
+
+    let dealing = traffic.phase("dealing");
+    assert_eq!(dealing[&a].messages_sent, 1);
+    assert_eq!(dealing[&a].bytes_sent, 100);
+    assert_eq!(dealing[&b].messages_received, 2);
+    assert_eq!(dealing[&b].bytes_received, 100);
+    assert_eq!(dealing[&b].messages_forwarded, 1);
+    assert_eq!(dealing[&b].bytes_forwarded, 7);
+
+    let total = traffic.phase_total("dealing");
+    assert_eq!((total.messages_sent, total.bytes_sent), (2, 200));
+    assert_eq!((total.messages_received, total.bytes_received), (2, 100));
+    assert!(traffic.phase("verify").is_empty());
+
+    assert_eq!(
+        traffic.summary(),
+        "setup:1:10:0:0,dealing:2:200:2:100,signing:0:0:1:5"
+    );
+}
+
+// Tests the per peer lines appended to a file, and that phases without traffic are left out
+#[test]
+fn traffic_appends_per_peer_lines() {
+    let (a, b) = (Id::Univariate(1), Id::Bivariate(0, 1));
+    let mut traffic = Traffic::default();
+    traffic.set_phase("dealing");
+    traffic.sent(a, 10);
+    traffic.received(b, 20);
+    traffic.forwarded(b, 30);
+    traffic.set_phase("unused");
+    assert_eq!(traffic.summary(), "dealing:1:10:1:20");
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!("traffic_{}_{}", std::process::id(), nanos));
+    let filename = path.to_str().unwrap();
+    traffic.append_per_peer(filename, Id::Univariate(0));
+    traffic.append_per_peer(filename, Id::Univariate(0));
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let lines = "Univariate(0),dealing,Univariate(1),1,10,0,0,0,0\n\
+                 Univariate(0),dealing,Bivariate(0, 1),0,0,1,20,1,30\n";
+    assert_eq!(written, lines.repeat(2));
+}
+
+// a scheme where the share of a path is a number derived from it, shares only combine into the
+// share of their prefix if they're all valid
+struct Toy;
+
+impl Toy {
+    fn key(path: &[usize]) -> u64 {
+        path.iter().fold(7, |key, i| key * 31 + *i as u64 + 1)
+    }
+}
+
+impl ThresholdScheme for Toy {
+    type Share = u64;
+
+    fn verify(&self, prefix: &[usize], share: &u64) -> Result<(), String> {
+        match *share == Toy::key(prefix) {
+            true => Ok(()),
+            false => Err(format!("invalid share for {:?}", prefix)),
+        }
+    }
+
+    fn combine(
+        &self,
+        prefix: &[usize],
+        shares: &BTreeMap<usize, u64>,
+        t: usize,
+    ) -> Result<u64, String> {
+        let valid = shares
+            .iter()
+            .take(t)
+            .all(|(i, share)| *share == Toy::key(&crate::child(prefix, *i)));
+        Ok(Toy::key(prefix) + u64::from(!valid))
+    }
+
+    fn serialize(&self, share: &u64) -> Vec<u8> {
+        share.to_le_bytes().to_vec()
+    }
+
+    fn deserialize(&self, _path: &[usize], bytes: &[u8]) -> Option<u64> {
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+// Tests nested cores run in memory all combine the top level share, with the bad share of a
+// member left out of its group
+#[test]
+fn threshold_cores_leave_out_bad_shares() {
+    let ids: Vec<Id> = (0..2)
+        .flat_map(|i| (0..3).map(move |j| Id::Bivariate(i, j)))
+        .collect();
+    let flow = |i: usize, j: usize| Flow {
+        path: vec![i, j],
+        thresholds: vec![2, 2],
+        targets: vec![
+            ids.iter().copied().filter(|id| id.path()[0] != i).collect(),
+            ids.iter()
+                .copied()
+                .filter(|id| id.path()[0] == i && *id != Id::Bivariate(i, j))
+                .collect(),
+        ],
+        phases: Vec::new(),
+        leader: None,
+    };
+
+    let mut cores: BTreeMap<Id, ThresholdCore<Toy>> = ids
+        .iter()
+        .map(|id| {
+            let (i, j) = (id.path()[0], id.path()[1]);
+            let core = ThresholdCore::new(&Toy, flow(i, j), *id, Toy::key(&[i, j])).unwrap();
+            (*id, core)
+        })
+        .collect();
+
+    // node (0, 0) sends a bad share to its group, which is delivered first
+    let bad = Id::Bivariate(0, 0);
+    let mut queue = VecDeque::new();
+    for (id, core) in cores.iter_mut() {
+        for (_, out) in core.outgoing() {
+            let mut msg = out.msg;
+            if *id == bad && msg[0] == 2 {
+                msg[1] ^= 1;
+            }
+            queue.extend(out.to.into_iter().map(|to| (*id, to, msg.clone())));
+        }
+    }
+    while let Some((from, to, msg)) = queue.pop_front() {
+        let core = cores.get_mut(&to).unwrap();
+        core.handle(from, &msg).unwrap();
+        for (_, out) in core.outgoing() {
+            queue.extend(out.to.into_iter().map(|id| (to, id, out.msg.clone())));
+        }
+    }
+
+    // (0, 1) and (0, 2) take the bad share first and have to leave it out, and report its sender
+    for (id, core) in cores.iter_mut() {
+        let combined = core.output().unwrap();
+        assert_eq!(combined.share, Toy::key(&[]), "{:?}", id);
+        let faulty = match id {
+            Id::Bivariate(0, 1) | Id::Bivariate(0, 2) => vec![bad],
+            _ => Vec::new(),
+        };
+        assert_eq!(combined.faulty.into_iter().collect::<Vec<_>>(), faulty);
+    }
+}
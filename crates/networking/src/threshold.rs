@@ -0,0 +1,489 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use tokio_stream::StreamExt;
+use types::Id;
+
+use crate::{Node, Outgoing, Session, SessionId, Topology, Traffic};
+
+// Nested threshold protocols as a state machine without any IO
+//
+// Signatures, decryption and the other nested schemes all run the same way. A node holds a share
+// for its path, its indices from the top level down such as (group, member). Once it holds the
+// shares of enough children of the prefix path[..k], its own included, it combines them into the
+// share of that prefix and passes it on, until it has the share of the empty prefix, the result.
+// Shares are combined optimistically: the first children that reach the threshold are combined
+// and the result is checked against the key of the prefix. Only if that fails are the shares
+// checked, the invalid ones dropped and their senders ignored from then on.
+//
+// A ThresholdScheme says how shares are checked and combined and a Flow who gets each share, so
+// the same core runs every scheme over any layout. With a leader the other members of the bottom
+// level wait for the share of their prefix from it, and combine their own only if it doesn't
+// verify or doesn't come in time.
+
+// the session one-shot runs use, sessions for requests are tagged with a node index (see serve)
+pub const THRESHOLD_SESSION: SessionId = 1;
+
+pub trait ThresholdScheme {
+    type Share: Clone;
+
+    // check `share` against the key of `prefix`
+    fn verify(&self, prefix: &[usize], share: &Self::Share) -> Result<(), String>;
+
+    // the children of `prefix` whose shares are invalid, keyed by their last index.
+    // Checks them one by one unless the scheme can do better
+    fn find_invalid(&self, prefix: &[usize], shares: &BTreeMap<usize, Self::Share>) -> Vec<usize> {
+        shares
+            .iter()
+            .filter(|(i, share)| self.verify(&child(prefix, **i), share).is_err())
+            .map(|(i, _)| *i)
+            .collect()
+    }
+
+    // combine shares of children of `prefix` worth at least `t` into the share of `prefix`
+    fn combine(
+        &self,
+        prefix: &[usize],
+        shares: &BTreeMap<usize, Self::Share>,
+        t: usize,
+    ) -> Result<Self::Share, String>;
+
+    // how much the share of `path` counts towards the threshold of its parent
+    fn weight(&self, _path: &[usize]) -> usize {
+        1
+    }
+
+    fn serialize(&self, share: &Self::Share) -> Vec<u8>;
+
+    // None if the bytes aren't a share of `path`
+    fn deserialize(&self, path: &[usize], bytes: &[u8]) -> Option<Self::Share>;
+}
+
+// the path of child `index` of `prefix`
+pub fn child(prefix: &[usize], index: usize) -> Vec<usize> {
+    let mut path = prefix.to_vec();
+    path.push(index);
+    path
+}
+
+// where a node sends its shares
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Flow {
+    // our indices from the top level down
+    pub path: Vec<usize>,
+    // the weight of the children of path[..k] needed to combine its share
+    pub thresholds: Vec<usize>,
+    // the nodes that get our share of path[..k + 1]
+    pub targets: Vec<Vec<Id>>,
+    // the phase sending our share of path[..k + 1] is attributed to, none are set if empty
+    pub phases: Vec<&'static str>,
+    // the node that combines the share of path[..d - 1] for the others at the bottom level and
+    // how long they wait for it
+    pub leader: Option<(Id, Duration)>,
+}
+
+impl Flow {
+    // the phase of sending a share of a prefix of length `level`
+    pub fn phase(&self, level: usize) -> Option<&'static str> {
+        level
+            .checked_sub(1)
+            .and_then(|k| self.phases.get(k))
+            .copied()
+    }
+}
+
+// the result of a run and where the core spent its time
+pub struct Combined<Share> {
+    // the share of the empty prefix
+    pub share: Share,
+    // the top level shares it was combined from, keyed by their index
+    pub used: BTreeMap<usize, Share>,
+    // combining and checking the share of path[..k], including tries that failed
+    pub combine_time: Vec<Duration>,
+    pub verify_time: Vec<Duration>,
+    // looking for invalid shares after a combination failed
+    pub batch_time: Duration,
+    // the nodes that sent us an invalid share
+    pub faulty: BTreeSet<Id>,
+}
+
+pub struct ThresholdCore<'a, S: ThresholdScheme> {
+    scheme: &'a S,
+    flow: Flow,
+    me: Id,
+    // our share of path[..k], once we have it
+    own: Vec<Option<S::Share>>,
+    // the shares of children of path[..k] we hold, keyed by their last index
+    children: Vec<BTreeMap<usize, S::Share>>,
+    // children whose shares were found valid, and who sent each share
+    verified: Vec<BTreeSet<usize>>,
+    senders: Vec<BTreeMap<usize, Id>>,
+    // senders of an invalid share, nothing more is taken from them
+    rejected: BTreeSet<Id>,
+    // whether we still wait for the share of our leader
+    waiting: bool,
+    // shares to send, with the length of the prefix they're for
+    outgoing: Vec<(usize, Outgoing<Vec<u8>>)>,
+    used: BTreeMap<usize, S::Share>,
+    combine_time: Vec<Duration>,
+    verify_time: Vec<Duration>,
+    batch_time: Duration,
+    // whether output gave the result
+    done: bool,
+}
+
+impl<'a, S: ThresholdScheme> ThresholdCore<'a, S> {
+    // start node `me` from its own share, which has to be valid
+    pub fn new(scheme: &'a S, flow: Flow, me: Id, share: S::Share) -> Result<Self, String> {
+        let d = flow.path.len();
+        if d == 0 || flow.thresholds.len() != d || flow.targets.len() != d {
+            return Err("one threshold and one set of targets are needed per level".to_string());
+        }
+        let waiting = d > 1 && matches!(flow.leader, Some((leader, _)) if leader != me);
+
+        let mut core = Self {
+            scheme,
+            flow,
+            me,
+            own: vec![None; d + 1],
+            children: vec![BTreeMap::new(); d],
+            verified: vec![BTreeSet::new(); d],
+            senders: vec![BTreeMap::new(); d],
+            rejected: BTreeSet::new(),
+            waiting,
+            outgoing: Vec::new(),
+            used: BTreeMap::new(),
+            combine_time: vec![Duration::ZERO; d],
+            verify_time: vec![Duration::ZERO; d],
+            batch_time: Duration::ZERO,
+            done: false,
+        };
+        core.set_own(d, share);
+        core.progress()?;
+        Ok(core)
+    }
+
+    // whether we wait for the share of our leader
+    pub fn waiting(&self) -> bool {
+        self.waiting
+    }
+
+    // how long we wait for the share of our leader
+    pub fn leader_timeout(&self) -> Option<Duration> {
+        self.flow.leader.map(|(_, timeout)| timeout)
+    }
+
+    // give up on the leader and combine the share of our prefix ourselves
+    pub fn stop_waiting(&mut self) -> Result<(), String> {
+        if self.waiting {
+            self.waiting = false;
+            println!("no valid share from the leader, combining it ourselves");
+        }
+        self.progress()
+    }
+
+    // the shares to send since the last call, with the phase to attribute them to
+    pub fn outgoing(&mut self) -> Vec<(Option<&'static str>, Outgoing<Vec<u8>>)> {
+        let flow = &self.flow;
+        self.outgoing
+            .drain(..)
+            .map(|(level, out)| (flow.phase(level), out))
+            .collect()
+    }
+
+    // take a frame from node `from`: the length of the prefix of the share, then the share.
+    // Frames that don't fit where the sender is are dropped, an error means we can't finish
+    pub fn handle(&mut self, from: Id, msg: &[u8]) -> Result<(), String> {
+        let d = self.flow.path.len();
+        let (level, bytes) = match msg.split_first() {
+            Some((level, bytes)) if (1..=d).contains(&(*level as usize)) => {
+                (*level as usize, bytes)
+            }
+            _ => return Ok(()),
+        };
+        let path = from.path();
+        if self.rejected.contains(&from)
+            || path.len() != d
+            || path[..level - 1] != self.flow.path[..level - 1]
+        {
+            return Ok(());
+        }
+
+        // the share of our own prefix, only our leader sends those
+        if path[level - 1] == self.flow.path[level - 1] {
+            let from_leader = matches!(self.flow.leader, Some((leader, _)) if leader == from);
+            if from_leader && level == d - 1 && self.own[level].is_none() {
+                self.from_leader(from, &path[..level], bytes);
+                return self.progress();
+            }
+            return Ok(());
+        }
+
+        let k = level - 1;
+        let index = path[k];
+        if self.own[k].is_some() || self.children[k].contains_key(&index) {
+            return Ok(());
+        }
+        if let Some(share) = self.scheme.deserialize(&path[..level], bytes) {
+            self.children[k].insert(index, share);
+            self.senders[k].insert(index, from);
+        }
+        self.progress()
+    }
+
+    // the result the first time it's ready, None before that and after
+    pub fn output(&mut self) -> Option<Combined<S::Share>> {
+        if self.done {
+            return None;
+        }
+        let share = self.own[0].clone()?;
+        self.done = true;
+        Some(Combined {
+            share,
+            used: std::mem::take(&mut self.used),
+            combine_time: self.combine_time.clone(),
+            verify_time: self.verify_time.clone(),
+            batch_time: self.batch_time,
+            faulty: self.rejected.clone(),
+        })
+    }
+
+    // take the share of our prefix from the leader if it verifies
+    fn from_leader(&mut self, leader: Id, prefix: &[usize], bytes: &[u8]) {
+        let k = prefix.len();
+        let t = Instant::now();
+        let share = self
+            .scheme
+            .deserialize(prefix, bytes)
+            .filter(|share| self.scheme.verify(prefix, share).is_ok());
+        self.verify_time[k] += t.elapsed();
+
+        match share {
+            Some(share) => self.set_own(k, share),
+            None => {
+                println!("invalid share from leader {:?}", leader);
+                self.rejected.insert(leader);
+            }
+        }
+        self.waiting = false;
+    }
+
+    // keep our share of path[..k], count it towards the parent and send it on
+    fn set_own(&mut self, k: usize, share: S::Share) {
+        let d = self.flow.path.len();
+        let msg = [vec![k as u8], self.scheme.serialize(&share)].concat();
+        if k > 0 {
+            let mut to = self.flow.targets[k - 1].clone();
+            // a leader also hands the share of its prefix to the others at the bottom level
+            if k == d - 1 && matches!(self.flow.leader, Some((leader, _)) if leader == self.me) {
+                to.extend(self.flow.targets[d - 1].iter().copied());
+            }
+            self.outgoing.push((k, Outgoing { to, msg }));
+
+            let index = self.flow.path[k - 1];
+            self.children[k - 1].insert(index, share.clone());
+            self.verified[k - 1].insert(index);
+        }
+        self.own[k] = Some(share);
+    }
+
+    // combine every prefix we can, from the bottom up
+    fn progress(&mut self) -> Result<(), String> {
+        let d = self.flow.path.len();
+        for k in (0..d).rev() {
+            if self.own[k].is_some() {
+                continue;
+            }
+            // we only combine once we hold and have sent our own share of the child
+            if self.own[k + 1].is_none() || (k == d - 1 && self.waiting) {
+                break;
+            }
+            while self.own[k].is_none() && self.try_combine(k)? {}
+            if self.own[k].is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // try to combine the share of path[..k] from the first children that reach the threshold.
+    // Returns whether it's worth trying again, after invalid shares were dropped
+    fn try_combine(&mut self, k: usize) -> Result<bool, String> {
+        let prefix = self.flow.path[..k].to_vec();
+        let mut weight = 0;
+        let first: BTreeMap<usize, S::Share> = self.children[k]
+            .iter()
+            .take_while(|(i, _)| {
+                let enough = weight >= self.flow.thresholds[k];
+                weight += self.scheme.weight(&child(&prefix, **i));
+                !enough
+            })
+            .map(|(i, share)| (*i, share.clone()))
+            .collect();
+        if weight < self.flow.thresholds[k] {
+            return Ok(false);
+        }
+
+        let t = Instant::now();
+        let share = self
+            .scheme
+            .combine(&prefix, &first, self.flow.thresholds[k]);
+        self.combine_time[k] += t.elapsed();
+        let t = Instant::now();
+        let share = share.and_then(|share| self.scheme.verify(&prefix, &share).map(|()| share));
+        self.verify_time[k] += t.elapsed();
+        if let Ok(share) = share {
+            if k == 0 {
+                self.used = first;
+            }
+            self.set_own(k, share);
+            return Ok(true);
+        }
+
+        // find the invalid shares among those we haven't checked yet and leave them out
+        let t = Instant::now();
+        let unchecked: BTreeMap<usize, S::Share> = first
+            .into_iter()
+            .filter(|(i, _)| !self.verified[k].contains(i))
+            .collect();
+        let invalid = self.scheme.find_invalid(&prefix, &unchecked);
+        self.batch_time += t.elapsed();
+        if invalid.is_empty() {
+            return Err(format!("valid shares for {:?} didn't combine", prefix));
+        }
+        for i in unchecked.keys() {
+            if invalid.contains(i) {
+                self.children[k].remove(i);
+                if let Some(sender) = self.senders[k].remove(i) {
+                    println!(
+                        "invalid share for {:?} from {:?}",
+                        child(&prefix, *i),
+                        sender
+                    );
+                    self.rejected.insert(sender);
+                }
+            } else {
+                self.verified[k].insert(*i);
+            }
+        }
+        Ok(true)
+    }
+}
+
+// run `core` over `session` until it gives the result, waiting for the leader no longer than
+// its timeout. Frames from peers that drop out are skipped, we only need a threshold of shares
+pub async fn run_threshold<S: ThresholdScheme>(
+    session: &mut Session,
+    mut core: ThresholdCore<'_, S>,
+) -> Result<Combined<S::Share>, String> {
+    let deadline = core
+        .leader_timeout()
+        .map(|timeout| tokio::time::Instant::now() + timeout);
+
+    loop {
+        for (phase, Outgoing { to, msg }) in core.outgoing() {
+            if let Some(phase) = phase {
+                session.set_phase(phase);
+            }
+            session
+                .broadcast(&msg, to)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        if let Some(combined) = core.output() {
+            return Ok(combined);
+        }
+
+        let next = match deadline.filter(|_| core.waiting()) {
+            Some(deadline) => match tokio::time::timeout_at(deadline, session.recv.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    core.stop_waiting()?;
+                    continue;
+                }
+            },
+            None => session.recv.next().await,
+        };
+        match next.ok_or("session closed")? {
+            (from, Ok(msg)) => core.handle(from, &msg)?,
+            (_, Err(_)) => continue,
+        }
+    }
+}
+
+// a one-shot run of a node and where its time went
+pub struct Run<Share> {
+    pub combined: Combined<Share>,
+    // computing and checking our own share
+    pub share_time: Duration,
+    pub verify_time: Duration,
+    pub total_time: Duration,
+    pub traffic: Traffic,
+    pub connection_count: usize,
+}
+
+impl<Share> Run<Share> {
+    // append `line` and the traffic to `filename` like Traffic::append_results, and the nodes that
+    // sent us an invalid share to a sibling file with a `_faulty` suffix, one `node,faulty` line each
+    pub fn append_results(&self, filename: &str, line: &str, me: Id) {
+        self.traffic.append_results(filename, line, me);
+        if self.combined.faulty.is_empty() {
+            return;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(format!("{}_faulty", filename))
+            .unwrap();
+        for id in &self.combined.faulty {
+            file.write_all(format!("{:?},{:?}\n", me, id).as_bytes())
+                .unwrap();
+        }
+    }
+}
+
+// connect node `me` to `addresses`, compute and check our share with `share` and run the core
+// in THRESHOLD_SESSION until it gives the result, then shut down
+pub async fn run_threshold_node<S: ThresholdScheme>(
+    addresses: BTreeMap<Id, String>,
+    me: Id,
+    topology: Topology,
+    scheme: &S,
+    flow: Flow,
+    share: impl FnOnce() -> S::Share,
+) -> Result<Run<S::Share>, String> {
+    let node = Node::with_topology(addresses, me, topology).await;
+    let mut session = node.open_session(THRESHOLD_SESSION)?;
+
+    let time = Instant::now();
+    if let Some(phase) = flow.phase(flow.path.len()) {
+        node.set_phase(phase);
+    }
+    let t = Instant::now();
+    let share = share();
+    let share_time = t.elapsed();
+    let t = Instant::now();
+    scheme.verify(&flow.path, &share)?;
+    let verify_time = t.elapsed();
+
+    let combined = match ThresholdCore::new(scheme, flow, me, share) {
+        Ok(core) => run_threshold(&mut session, core).await,
+        Err(err) => Err(err),
+    };
+
+    let total_time = time.elapsed();
+    std::thread::sleep(Duration::from_secs(1));
+    node.shutdown();
+    println!("total_time: {:?}", total_time);
+    Ok(Run {
+        combined: combined?,
+        share_time,
+        verify_time,
+        total_time,
+        traffic: node.traffic(),
+        connection_count: node.connection_count(),
+    })
+}
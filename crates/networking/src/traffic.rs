@@ -0,0 +1,162 @@
+use std::{collections::BTreeMap, io::Write};
+use types::Id;
+
+// phase traffic is attributed to before the protocol tags one
+pub const DEFAULT_PHASE: &str = "setup";
+
+// messages and bytes exchanged with a single peer, bytes include framing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    // frames we relayed as a gateway on behalf of this peer
+    pub messages_forwarded: u64,
+    pub bytes_forwarded: u64,
+}
+
+impl Counters {
+    fn add(&mut self, other: &Counters) {
+        self.messages_sent += other.messages_sent;
+        self.bytes_sent += other.bytes_sent;
+        self.messages_received += other.messages_received;
+        self.bytes_received += other.bytes_received;
+        self.messages_forwarded += other.messages_forwarded;
+        self.bytes_forwarded += other.bytes_forwarded;
+    }
+}
+
+// traffic of a node broken down by protocol phase and peer
+// received frames are counted against the phase that is current when they come off the socket
+#[derive(Clone, Debug)]
+pub struct Traffic {
+    phase: String,
+    // phases in the order they were first tagged
+    phases: Vec<String>,
+    counters: BTreeMap<(String, Id), Counters>,
+}
+
+impl Default for Traffic {
+    fn default() -> Self {
+        Self {
+            phase: DEFAULT_PHASE.to_owned(),
+            phases: vec![DEFAULT_PHASE.to_owned()],
+            counters: BTreeMap::new(),
+        }
+    }
+}
+
+impl Traffic {
+    pub(crate) fn set_phase(&mut self, phase: &str) {
+        if !self.phases.iter().any(|p| p == phase) {
+            self.phases.push(phase.to_owned());
+        }
+        self.phase = phase.to_owned();
+    }
+
+    fn current(&mut self, peer: Id) -> &mut Counters {
+        self.counters.entry((self.phase.clone(), peer)).or_default()
+    }
+
+    pub(crate) fn sent(&mut self, to: Id, bytes: usize) {
+        let counters = self.current(to);
+        counters.messages_sent += 1;
+        counters.bytes_sent += bytes as u64;
+    }
+
+    pub(crate) fn received(&mut self, from: Id, bytes: usize) {
+        let counters = self.current(from);
+        counters.messages_received += 1;
+        counters.bytes_received += bytes as u64;
+    }
+
+    pub(crate) fn forwarded(&mut self, from: Id, bytes: usize) {
+        let counters = self.current(from);
+        counters.messages_forwarded += 1;
+        counters.bytes_forwarded += bytes as u64;
+    }
+
+    // counters for every peer in a phase
+    pub fn phase(&self, phase: &str) -> BTreeMap<Id, Counters> {
+        self.counters
+            .iter()
+            .filter(|((p, _), _)| p == phase)
+            .map(|((_, peer), counters)| (*peer, *counters))
+            .collect()
+    }
+
+    // counters summed over all peers in a phase
+    pub fn phase_total(&self, phase: &str) -> Counters {
+        self.phase(phase)
+            .values()
+            .fold(Counters::default(), |mut total, counters| {
+                total.add(counters);
+                total
+            })
+    }
+
+    // one `phase:messages_sent:bytes_sent:messages_received:bytes_received` column per phase,
+    // meant to be appended to a line of timings
+    pub fn summary(&self) -> String {
+        self.phases
+            .iter()
+            .filter(|phase| self.counters.keys().any(|(p, _)| p == *phase))
+            .map(|phase| {
+                let total = self.phase_total(phase);
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    phase,
+                    total.messages_sent,
+                    total.bytes_sent,
+                    total.messages_received,
+                    total.bytes_received
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    // append a line of timings followed by the summary to `filename`, and one line per phase and
+    // peer to `<filename>_traffic`
+    pub fn append_results(&self, filename: &str, line: &str, my_id: Id) {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .append(true)
+            .create(true)
+            .open(filename)
+            .unwrap();
+        file.write_all(format!("{},{}\n", line, self.summary()).as_bytes())
+            .unwrap();
+        self.append_per_peer(&format!("{}_traffic", filename), my_id);
+    }
+
+    // append one line per phase and peer to `filename`, tagged with our id
+    pub fn append_per_peer(&self, filename: &str, my_id: Id) {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .append(true)
+            .create(true)
+            .open(filename)
+            .unwrap();
+
+        for ((phase, peer), counters) in &self.counters {
+            file.write_all(
+                format!(
+                    "{:?},{},{:?},{},{},{},{},{},{}\n",
+                    my_id,
+                    phase,
+                    peer,
+                    counters.messages_sent,
+                    counters.bytes_sent,
+                    counters.messages_received,
+                    counters.bytes_received,
+                    counters.messages_forwarded,
+                    counters.bytes_forwarded
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use types::Id;
+
+use crate::{DkgProtocol, Outgoing};
+
+// A non-interactive dkg on any scheme (see NiSharing) as a state machine without any IO
+//
+// Receivers only hold a key pair to decrypt their shares with. Dealers send each other their
+// dealing, and a dealer holding a verified dealing from every dealer combines them into a
+// transcript for the receivers. A receiver decrypts its key from the first transcript it can
+// check, a transcript that gives it a key that doesn't fit the public coefficients is rejected and
+// it waits for the next one. The nidkg, optimized-univar and optimized-nidkg crates all run on it.
+
+// a non-interactive dkg scheme, what dealings and transcripts are and how they are checked
+pub trait NiSharing {
+    // the receivers and dealers, their encryption keys and the threshold
+    type Params;
+    // a dealing as dealers send it
+    type Dealing: Serialize + DeserializeOwned;
+    // a dealing that passed verification, transcripts are combined from them
+    type Verified;
+    // the combined dealings as receivers get them
+    type Transcript: Serialize + DeserializeOwned;
+    type PublicCoefficients;
+    type SecretKey;
+
+    // the receivers and dealers, in the order of the addresses file
+    fn receivers(params: &Self::Params) -> Vec<Id>;
+    fn dealers(params: &Self::Params) -> Vec<Id>;
+
+    // whether the parameters hold the decryption key a receiver needs
+    fn has_secret_key(params: &Self::Params) -> bool;
+
+    // the dealing of dealer `index`, as sent and as kept by the dealer
+    fn deal(params: &Self::Params, index: u32) -> Result<(Self::Dealing, Self::Verified), String>;
+
+    // an error if the dealing of dealer `index` is invalid
+    fn verify(
+        params: &Self::Params,
+        index: u32,
+        dealing: Self::Dealing,
+    ) -> Result<Self::Verified, String>;
+
+    // the transcript of a verified dealing from every dealer and its public coefficients
+    fn transcript(
+        params: &Self::Params,
+        dealings: &BTreeMap<u32, Self::Verified>,
+    ) -> Result<(Self::Transcript, Self::PublicCoefficients), String>;
+
+    // the public coefficients and the key of receiver `id`, an error if the key doesn't fit the
+    // public coefficients
+    fn decrypt(
+        params: &Self::Params,
+        id: Id,
+        transcript: &Self::Transcript,
+    ) -> Result<(Self::PublicCoefficients, Self::SecretKey), String>;
+}
+
+// dealings are much larger than transcripts, so they are boxed
+#[derive(Serialize, Deserialize)]
+pub enum TranscriptMessage<D, T> {
+    Dealing(Box<D>),
+    Transcript(T),
+}
+
+pub struct TranscriptDkg<S: NiSharing> {
+    params: S::Params,
+    me: Id,
+    receivers: Vec<Id>,
+    dealers: Vec<Id>,
+    // our dealing as a dealer, sent on the first call to start
+    dealing: Option<S::Dealing>,
+    // the transcript of a lone dealer, created in new and sent with its dealing
+    pending: Vec<Outgoing<TranscriptMessage<S::Dealing, S::Transcript>>>,
+    dealings: BTreeMap<u32, S::Verified>,
+    // the dealers whose transcript we got as a receiver
+    transcripts: BTreeSet<Id>,
+    // our output, held until poll_output gives it
+    output: Option<(S::PublicCoefficients, Option<S::SecretKey>)>,
+    // whether we have our output, the transcript as a dealer or our key as a receiver
+    done: bool,
+    // time spent verifying dealings and creating the transcript as a dealer
+    verify_time: Duration,
+    transcript_time: Duration,
+    // time spent decrypting transcripts as a receiver
+    decrypt_time: Duration,
+}
+
+impl<S: NiSharing> TranscriptDkg<S> {
+    // the index of `id` among the dealers, None for a receiver
+    fn dealer_index(&self, id: Id) -> Option<u32> {
+        self.dealers
+            .iter()
+            .position(|dealer| *dealer == id)
+            .map(|index| index as u32)
+    }
+
+    fn is_dealer(&self) -> bool {
+        self.dealer_index(self.me).is_some()
+    }
+
+    // combine the dealings into a transcript for the receivers once we hold one from every dealer
+    fn send_transcript(
+        &mut self,
+    ) -> Result<Vec<Outgoing<TranscriptMessage<S::Dealing, S::Transcript>>>, String> {
+        if self.done || self.dealings.len() < self.dealers.len() {
+            return Ok(Vec::new());
+        }
+        let t = Instant::now();
+        let transcript = S::transcript(&self.params, &self.dealings);
+        self.transcript_time = t.elapsed();
+        let (transcript, coefficients) =
+            transcript.map_err(|err| format!("unable to create the transcript: {}", err))?;
+        self.output = Some((coefficients, None));
+        self.done = true;
+
+        Ok(vec![Outgoing {
+            to: self.receivers.clone(),
+            msg: TranscriptMessage::Transcript(transcript),
+        }])
+    }
+}
+
+impl<S: NiSharing> DkgProtocol for TranscriptDkg<S> {
+    type Params = S::Params;
+    type Message = TranscriptMessage<S::Dealing, S::Transcript>;
+    // dealers don't get a signing key
+    type Output = (S::PublicCoefficients, Option<S::SecretKey>);
+    type Error = String;
+
+    // a dealer generates its dealing
+    fn new(params: S::Params, my_id: Id) -> Result<Self, String> {
+        let receivers = S::receivers(&params);
+        if receivers.contains(&my_id) && !S::has_secret_key(&params) {
+            return Err("receivers need their secret key".to_owned());
+        }
+        let mut dkg = TranscriptDkg {
+            params,
+            me: my_id,
+            receivers,
+            dealers: Vec::new(),
+            dealing: None,
+            pending: Vec::new(),
+            dealings: BTreeMap::new(),
+            transcripts: BTreeSet::new(),
+            output: None,
+            done: false,
+            verify_time: Duration::ZERO,
+            transcript_time: Duration::ZERO,
+            decrypt_time: Duration::ZERO,
+        };
+        dkg.dealers = S::dealers(&dkg.params);
+
+        if let Some(index) = dkg.dealer_index(my_id) {
+            let (dealing, verified) = S::deal(&dkg.params, index)?;
+            dkg.dealings.insert(index, verified);
+            dkg.dealing = Some(dealing);
+            dkg.pending = dkg.send_transcript()?;
+        } else if !dkg.receivers.contains(&my_id) {
+            return Err(format!("{:?} isn't a node of the dkg", my_id));
+        }
+        Ok(dkg)
+    }
+
+    fn id(&self) -> Id {
+        self.me
+    }
+
+    // dealers only hand out fragments of their transcript
+    fn participants(&self) -> Vec<Id> {
+        self.receivers.clone()
+    }
+
+    // a dealer sends its dealing to the other dealers
+    fn start(&mut self) -> Vec<Outgoing<Self::Message>> {
+        let dealing = match self.dealing.take() {
+            Some(dealing) => dealing,
+            None => return Vec::new(),
+        };
+        let me = self.me;
+        let to = self
+            .dealers
+            .iter()
+            .filter(|id| **id != me)
+            .copied()
+            .collect();
+
+        let mut outgoing = vec![Outgoing {
+            to,
+            msg: TranscriptMessage::Dealing(Box::new(dealing)),
+        }];
+        outgoing.append(&mut self.pending);
+        outgoing
+    }
+
+    // dealers take verified dealings and receivers transcripts, each at most once per dealer.
+    // A rejected transcript still counts as its dealer's, so finished doesn't wait for another
+    fn handle_message(
+        &mut self,
+        from: Id,
+        msg: Self::Message,
+    ) -> Result<Vec<Outgoing<Self::Message>>, String> {
+        let index = self
+            .dealer_index(from)
+            .ok_or_else(|| format!("{:?} isn't a dealer", from))?;
+
+        match msg {
+            TranscriptMessage::Dealing(dealing) if self.is_dealer() => {
+                if self.dealings.contains_key(&index) {
+                    return Err(format!("{:?} already sent a dealing", from));
+                }
+                let t = Instant::now();
+                let verified = S::verify(&self.params, index, *dealing);
+                self.verify_time += t.elapsed();
+                let verified =
+                    verified.map_err(|err| format!("invalid dealing from {:?}: {}", from, err))?;
+                self.dealings.insert(index, verified);
+                self.send_transcript()
+            }
+            TranscriptMessage::Transcript(transcript) if !self.is_dealer() => {
+                if !self.transcripts.insert(from) {
+                    return Err(format!("{:?} already sent a transcript", from));
+                }
+                if self.done {
+                    return Ok(Vec::new());
+                }
+                let t = Instant::now();
+                let key = S::decrypt(&self.params, self.me, &transcript);
+                self.decrypt_time += t.elapsed();
+                let (coefficients, key) =
+                    key.map_err(|err| format!("invalid transcript from {:?}: {}", from, err))?;
+                self.output = Some((coefficients, Some(key)));
+                self.done = true;
+                Ok(Vec::new())
+            }
+            _ => Err(format!("unexpected message from {:?}", from)),
+        }
+    }
+
+    // the transcript as a dealer, our key from the first valid transcript as a receiver
+    fn poll_output(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+
+    fn phase(&self) -> &'static str {
+        if self.is_dealer() && !self.done {
+            "dealing"
+        } else {
+            "transcript"
+        }
+    }
+
+    // a receiver keeps echoing until it has the transcript of every dealer
+    fn finished(&self) -> bool {
+        self.is_dealer() || self.transcripts.len() == self.dealers.len()
+    }
+
+    // a dealer records the time to deal, verify the other dealings and create the transcript, a
+    // receiver the time to decrypt its key
+    fn columns(&self, setup: Duration, _output: Duration, _check: Duration) -> Vec<Duration> {
+        if self.is_dealer() {
+            vec![setup, self.verify_time, self.transcript_time]
+        } else {
+            vec![self.decrypt_time]
+        }
+    }
+}
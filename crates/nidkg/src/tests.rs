@@ -0,0 +1,119 @@
+use crate::machine::{NiDkg, Params};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    api::{
+        combine_signatures, combined_public_key, individual_public_key, sign_message,
+        verify_combined_signature, verify_individual_signature,
+    },
+    ni_dkg::groth20_bls12_381::{create_forward_secure_key_pair, types::FsEncryptionKeySetWithPop},
+};
+use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::ni_dkg_groth20_bls12_381::Transcript;
+use ic_types::{NumberOfNodes, Randomness};
+use networking::{run_in_memory, DkgProtocol, Outgoing, TranscriptMessage};
+use types::Id;
+
+// n key pairs and the parameters of a dkg of n receivers and d dealers with threshold t, without
+// a secret key
+fn setup(n: usize, d: usize, t: usize) -> (Vec<FsEncryptionKeySetWithPop>, Params) {
+    let keypairs: Vec<_> = (0..n)
+        .map(|_| {
+            create_forward_secure_key_pair(
+                Randomness::from(rand::random::<[u8; 32]>()),
+                &[2, 8, 1, 2],
+            )
+        })
+        .collect();
+    let params = Params {
+        receivers: n,
+        dealers: d,
+        threshold: t,
+        receiver_keys: keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, keypair)| (i as u32, keypair.public_key))
+            .collect(),
+        secret_key: None,
+    };
+    (keypairs, params)
+}
+
+// the machine of node i, receivers get their secret key
+fn machine(keypairs: &[FsEncryptionKeySetWithPop], params: &Params, i: usize) -> NiDkg {
+    let params = Params {
+        secret_key: keypairs.get(i).map(|keypair| keypair.secret_key.clone()),
+        ..params.clone()
+    };
+    NiDkg::new(params, Id::Univariate(i)).unwrap()
+}
+
+// the transcript a single dealer sends when it starts
+fn transcript(mut dealer: NiDkg) -> Transcript {
+    dealer
+        .start()
+        .into_iter()
+        .find_map(|Outgoing { msg, .. }| match msg {
+            TranscriptMessage::Transcript(transcript) => Some(transcript),
+            _ => None,
+        })
+        .unwrap()
+}
+
+// Tests the nidkg machine run in memory gives every receiver the key share of its index in the
+// same coefficients, and the shares combine to the public key
+#[test]
+fn machine_runs_in_memory() {
+    let (keypairs, params) = setup(4, 2, 2);
+    assert!(NiDkg::new(params.clone(), Id::Univariate(0)).is_err());
+    assert!(NiDkg::new(params.clone(), Id::Univariate(6)).is_err());
+
+    let machines = (0..6).map(|i| machine(&keypairs, &params, i)).collect();
+    let outputs = run_in_memory(machines);
+    assert_eq!(outputs.len(), 6);
+
+    let msg: [u8; 32] = [0; 32];
+    let coefficients = &outputs[&Id::Univariate(4)].0;
+    let mut signatures = Vec::new();
+    for (id, (public_coefficients, signing_key)) in &outputs {
+        assert_eq!(public_coefficients, coefficients);
+        match (id, signing_key) {
+            (Id::Univariate(i), Some(signing_key)) if *i < 4 => {
+                let public_key = individual_public_key(coefficients, *i as u32).unwrap();
+                let signature = sign_message(&msg, signing_key).unwrap();
+                verify_individual_signature(&msg, signature, public_key).unwrap();
+                signatures.push(Some(signature));
+            }
+            (Id::Univariate(_), None) => {}
+            _ => panic!("{:?} got the wrong output", id),
+        }
+    }
+    assert_eq!(signatures.len(), 4);
+    let signature = combine_signatures(&signatures, NumberOfNodes::new(2)).unwrap();
+    let public_key = combined_public_key(coefficients).unwrap();
+    verify_combined_signature(&msg, signature, public_key).unwrap();
+}
+
+// Tests a receiver rejects a transcript whose key doesn't fit its coefficients and takes the next
+#[test]
+fn machine_skips_invalid_transcripts() {
+    let (keypairs, params) = setup(3, 1, 2);
+    let valid = transcript(machine(&keypairs, &params, 3));
+    let forged = Transcript {
+        public_coefficients: transcript(machine(&keypairs, &params, 3)).public_coefficients,
+        ..valid.clone()
+    };
+
+    let params = Params {
+        dealers: 2,
+        ..params
+    };
+    let mut receiver = machine(&keypairs, &params, 0);
+    let forged = TranscriptMessage::Transcript(forged);
+    assert!(receiver.handle_message(Id::Univariate(3), forged).is_err());
+    assert!(receiver.poll_output().is_none());
+
+    let message = TranscriptMessage::Transcript(valid.clone());
+    receiver.handle_message(Id::Univariate(4), message).unwrap();
+    let (coefficients, signing_key) = receiver.poll_output().unwrap();
+    assert_eq!(coefficients, valid.public_coefficients);
+    assert!(signing_key.is_some());
+    assert!(receiver.finished());
+}
@@ -0,0 +1,230 @@
+use ic_crypto_internal_bls12381_serde_miracl::{miracl_g1_from_bytes, miracl_g2_from_bytes};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    api::{sign_message, verify_individual_signature},
+    ni_dkg::groth20_bls12_381::{
+        compute_threshold_signing_key_el_gamal, create_dealing_el_gamal,
+        create_transcript_el_gamal, verify_dealing_el_gamal,
+    },
+    types::SecretKeyBytes,
+};
+use ic_crypto_internal_types::{
+    encrypt::forward_secure::groth20_bls12_381::{FsEncryptionCiphertext, FsEncryptionPublicKey},
+    sign::threshold_sig::{
+        ni_dkg::{
+            ni_dkg_groth20_bls12_381::{ZKProofDec, ZKProofShare},
+            Epoch,
+        },
+        public_coefficients::bls12_381::PublicCoefficientsBytes,
+        public_key::bls12_381::PublicKeyBytes,
+    },
+    NodeIndex,
+};
+use ic_types::{NumberOfNodes, Randomness};
+use miracl_core::bls12381::big::BIG;
+use networking::{NiSharing, TranscriptDkg};
+use rand::Rng;
+use std::collections::BTreeMap;
+use types::{bivariate::PublicCoefficients, Id};
+
+// The bivariate nidkg as a state machine without any IO
+//
+// Receivers are Id::Bivariate(i, j) for i < n and j < m, and dealers Id::Bivariate(n, 0..d).
+// It's networking::TranscriptDkg with bivariate dealings and shares encrypted with el gamal.
+
+// the receivers and dealers of the dkg
+#[derive(Clone)]
+pub struct Params {
+    pub nodes: (usize, usize),
+    pub dealers: usize,
+    pub threshold: (usize, usize),
+    pub receiver_keys: BTreeMap<(u32, u32), FsEncryptionPublicKey>,
+    // only receivers need their decryption key
+    pub secret_key: Option<BIG>,
+}
+
+// the public coefficients of a dealing serialized, its encrypted shares and proofs
+type SerializedDealing = (
+    Vec<u8>,
+    FsEncryptionCiphertext,
+    ZKProofDec,
+    Vec<ZKProofShare>,
+    Vec<PublicCoefficientsBytes>,
+);
+
+// the combined public coefficients serialized and the shares for every receiver, encrypted
+type SerializedTranscript = (Vec<u8>, BTreeMap<NodeIndex, FsEncryptionCiphertext>);
+
+// bivariate dealings with shares under el gamal encryption
+pub struct BivariateElGamal;
+
+pub type OptimizedNiDkg = TranscriptDkg<BivariateElGamal>;
+
+// the thresholds as the dfinity functions take them
+fn threshold(params: &Params) -> (NumberOfNodes, NumberOfNodes) {
+    (
+        NumberOfNodes::new(params.threshold.0 as u32),
+        NumberOfNodes::new(params.threshold.1 as u32),
+    )
+}
+
+// crates/dfinity verifies and decrypts el gamal dealings in groups of 11 receivers
+const GROUP_SIZE: usize = 11;
+
+fn check_group_size(params: &Params) -> Result<(), String> {
+    if params.nodes.1 != GROUP_SIZE {
+        return Err(format!(
+            "groups need {} members, not {}",
+            GROUP_SIZE, params.nodes.1
+        ));
+    }
+    Ok(())
+}
+
+// the chunks of receiver (i, j) are at i * m + j, they and every point of each ciphertext are
+// checked before compute_threshold_signing_key_el_gamal, which unwraps them
+fn check_ciphertexts(
+    params: &Params,
+    ciphertexts: &BTreeMap<NodeIndex, FsEncryptionCiphertext>,
+    (i, j): (u32, u32),
+) -> Result<(), String> {
+    let position = i as usize * params.nodes.1 + j as usize;
+    for (dealer, ciphertext) in ciphertexts {
+        let mut g1 = ciphertext
+            .rand_r
+            .iter()
+            .chain(&ciphertext.rand_s)
+            .chain(ciphertext.ciphertext_chunks.iter().flatten());
+        if ciphertext.ciphertext_chunks.len() <= position
+            || g1.any(|point| miracl_g1_from_bytes(&point.0).is_err())
+            || ciphertext
+                .rand_z
+                .iter()
+                .any(|point| miracl_g2_from_bytes(&point.0).is_err())
+        {
+            return Err(format!("malformed ciphertext from dealer {}", dealer));
+        }
+    }
+    Ok(())
+}
+
+impl NiSharing for BivariateElGamal {
+    type Params = Params;
+    type Dealing = SerializedDealing;
+    type Verified = (PublicCoefficients, FsEncryptionCiphertext);
+    type Transcript = SerializedTranscript;
+    type PublicCoefficients = PublicCoefficients;
+    type SecretKey = SecretKeyBytes;
+
+    fn receivers(params: &Params) -> Vec<Id> {
+        let (n, m) = params.nodes;
+        (0..n)
+            .flat_map(|i| (0..m).map(move |j| Id::Bivariate(i, j)))
+            .collect()
+    }
+
+    fn dealers(params: &Params) -> Vec<Id> {
+        (0..params.dealers)
+            .map(|j| Id::Bivariate(params.nodes.0, j))
+            .collect()
+    }
+
+    fn has_secret_key(params: &Params) -> bool {
+        params.secret_key.is_some()
+    }
+
+    fn deal(params: &Params, index: u32) -> Result<(SerializedDealing, Self::Verified), String> {
+        check_group_size(params)?;
+        let dealing = create_dealing_el_gamal(
+            Randomness::from(rand::thread_rng().gen::<[u8; 32]>()),
+            Randomness::from(rand::thread_rng().gen::<[u8; 32]>()),
+            threshold(params),
+            params.nodes,
+            &params.receiver_keys,
+            Epoch::from(1),
+            index,
+            None,
+        )
+        .map_err(|e| format!("unable to create dealing: {:?}", e))?;
+        let verified = (dealing.0.clone(), dealing.1.clone());
+        Ok((
+            (
+                dealing.0.serialize(),
+                dealing.1,
+                dealing.2,
+                dealing.3,
+                dealing.4,
+            ),
+            verified,
+        ))
+    }
+
+    fn verify(
+        params: &Params,
+        index: u32,
+        dealing: SerializedDealing,
+    ) -> Result<Self::Verified, String> {
+        let (coefficients, ciphertext, proof_dec, proof_shares, coefficients_bytes) = dealing;
+        let coefficients = PublicCoefficients::from_bytes(&coefficients, params.threshold)
+            .ok_or("the public coefficients don't fit the threshold")?;
+        let dealing = (
+            coefficients,
+            ciphertext,
+            proof_dec,
+            proof_shares,
+            coefficients_bytes,
+        );
+        verify_dealing_el_gamal(
+            index,
+            threshold(params),
+            Epoch::from(1),
+            &params.receiver_keys,
+            &dealing,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        Ok((dealing.0, dealing.1))
+    }
+
+    fn transcript(
+        params: &Params,
+        dealings: &BTreeMap<u32, Self::Verified>,
+    ) -> Result<(SerializedTranscript, PublicCoefficients), String> {
+        let (n, m) = params.nodes;
+        let (coefficients, ciphertexts) = create_transcript_el_gamal(
+            threshold(params),
+            (NumberOfNodes::new(n as u32), NumberOfNodes::new(m as u32)),
+            dealings,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(((coefficients.serialize(), ciphertexts), coefficients))
+    }
+
+    // decrypt our signing key and sign with it to check it
+    fn decrypt(
+        params: &Params,
+        id: Id,
+        (coefficients, ciphertexts): &SerializedTranscript,
+    ) -> Result<(PublicCoefficients, SecretKeyBytes), String> {
+        let index = match id {
+            Id::Bivariate(i, j) => (i as u32, j as u32),
+            _ => return Err(format!("{:?} isn't a receiver", id)),
+        };
+        let coefficients = PublicCoefficients::from_bytes(coefficients, params.threshold)
+            .ok_or("the public coefficients don't fit the threshold")?;
+        let sk = params.secret_key.as_ref().ok_or("no secret key")?;
+        check_group_size(params)?;
+        check_ciphertexts(params, ciphertexts, index)?;
+        let signing_key =
+            compute_threshold_signing_key_el_gamal(ciphertexts.clone(), index, params.nodes, sk)
+                .map_err(|e| format!("unable to decrypt the signing key: {:?}", e))?;
+
+        let msg: [u8; 32] = [0; 32];
+        let signature = sign_message(&msg, &signing_key).map_err(|e| format!("{:?}", e))?;
+        verify_individual_signature(
+            &msg,
+            signature,
+            PublicKeyBytes::from(coefficients.individual_public_key(index)),
+        )
+        .map_err(|_| "the signing key doesn't fit the public coefficients".to_string())?;
+        Ok((coefficients, signing_key))
+    }
+}
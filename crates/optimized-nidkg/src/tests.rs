@@ -0,0 +1,73 @@
+use crate::machine::{OptimizedNiDkg, Params};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    api::{sign_message, verify_individual_signature},
+    ni_dkg::groth20_bls12_381::create_forward_secure_key_pair_el_gamal,
+};
+use ic_crypto_internal_types::sign::threshold_sig::public_key::bls12_381::PublicKeyBytes;
+use ic_types::Randomness;
+use networking::{run_in_memory, DkgProtocol};
+use std::collections::BTreeMap;
+use types::Id;
+
+// Tests the el gamal bivariate machine run in memory gives every receiver the key share of its
+// index in the same coefficients
+#[test]
+fn machine_runs_in_memory() {
+    let keypairs: BTreeMap<_, _> = (0..2)
+        .flat_map(|i| (0..11).map(move |j| (i, j)))
+        .map(|(i, j)| {
+            let keypair = create_forward_secure_key_pair_el_gamal(
+                Randomness::from(rand::random::<[u8; 32]>()),
+                &[2, 8, 1, 2],
+            );
+            ((i, j), keypair)
+        })
+        .collect();
+    let params = Params {
+        nodes: (2, 11),
+        dealers: 2,
+        threshold: (2, 2),
+        receiver_keys: keypairs
+            .iter()
+            .map(|((i, j), (pk, _))| ((*i as u32, *j as u32), *pk))
+            .collect(),
+        secret_key: None,
+    };
+    assert!(OptimizedNiDkg::new(params.clone(), Id::Bivariate(0, 0)).is_err());
+    assert!(OptimizedNiDkg::new(params.clone(), Id::Bivariate(2, 2)).is_err());
+    let small = Params {
+        nodes: (2, 2),
+        ..params.clone()
+    };
+    assert!(OptimizedNiDkg::new(small, Id::Bivariate(2, 0)).is_err());
+
+    let machines = (0..3)
+        .flat_map(|i| (0..11).map(move |j| (i, j)))
+        .filter(|(i, j)| *i < 2 || *j < 2)
+        .map(|(i, j)| {
+            let params = Params {
+                secret_key: keypairs.get(&(i, j)).map(|(_, sk)| *sk),
+                ..params.clone()
+            };
+            OptimizedNiDkg::new(params, Id::Bivariate(i, j)).unwrap()
+        })
+        .collect();
+    let outputs = run_in_memory(machines);
+    assert_eq!(outputs.len(), 24);
+
+    let msg: [u8; 32] = [0; 32];
+    let coefficients = &outputs[&Id::Bivariate(2, 0)].0;
+    for (id, (public_coefficients, signing_key)) in &outputs {
+        assert_eq!(public_coefficients, coefficients);
+        match (id, signing_key) {
+            (Id::Bivariate(i, j), Some(signing_key)) if *i < 2 => {
+                let public_key = coefficients.individual_public_key((*i as u32, *j as u32));
+                let signature = sign_message(&msg, signing_key).unwrap();
+                verify_individual_signature(&msg, signature, PublicKeyBytes::from(public_key))
+                    .unwrap();
+            }
+            (Id::Bivariate(2, _), None) => {}
+            _ => panic!("{:?} got the wrong output", id),
+        }
+    }
+}
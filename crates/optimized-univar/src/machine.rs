@@ -0,0 +1,180 @@
+use ic_crypto_internal_bls12381_serde_miracl::{miracl_g1_from_bytes, miracl_g2_from_bytes};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    api::{individual_public_key, sign_message, verify_individual_signature},
+    ni_dkg::groth20_bls12_381::{
+        compute_threshold_signing_key_univar, create_dealing, create_transcript, verify_dealing,
+    },
+    types::SecretKeyBytes,
+};
+use ic_crypto_internal_types::{
+    encrypt::forward_secure::groth20_bls12_381::{FsEncryptionCiphertext, FsEncryptionPublicKey},
+    sign::threshold_sig::{
+        ni_dkg::{
+            ni_dkg_groth20_bls12_381::{Dealing, Transcript},
+            Epoch,
+        },
+        public_coefficients::bls12_381::PublicCoefficientsBytes,
+    },
+};
+use ic_types::{
+    crypto::threshold_sig::ni_dkg::{NiDkgId, NiDkgTag, NiDkgTargetId, NiDkgTargetSubnet},
+    Height, NumberOfNodes, PrincipalId, Randomness, SubnetId,
+};
+use miracl_core::bls12381::big::BIG;
+use networking::{NiSharing, TranscriptDkg};
+use rand::Rng;
+use std::collections::BTreeMap;
+use types::Id;
+
+// The optimized univariate nidkg as a state machine without any IO
+//
+// It runs like the machine in the nidkg crate, receivers are Id::Univariate(0..n) and dealers
+// Id::Univariate(n..n + d), but shares are encrypted with el gamal instead of forward secure
+// encryption.
+
+// the receivers and dealers of the dkg
+#[derive(Clone)]
+pub struct Params {
+    pub receivers: usize,
+    pub dealers: usize,
+    pub threshold: usize,
+    pub receiver_keys: BTreeMap<u32, FsEncryptionPublicKey>,
+    // only receivers need their decryption key
+    pub secret_key: Option<BIG>,
+}
+
+// dealings with shares under el gamal encryption
+pub struct ElGamal;
+
+pub type OptimizedUnivarDkg = TranscriptDkg<ElGamal>;
+
+// compute_threshold_signing_key_univar unwraps every ciphertext of the transcript, so a receiver
+// checks their points and that there are chunks at its index before decrypting
+fn check_ciphertexts(
+    ciphertexts: &BTreeMap<u32, FsEncryptionCiphertext>,
+    index: usize,
+) -> Result<(), String> {
+    for (dealer, ciphertext) in ciphertexts {
+        let mut g1 = ciphertext
+            .rand_r
+            .iter()
+            .chain(&ciphertext.rand_s)
+            .chain(ciphertext.ciphertext_chunks.iter().flatten());
+        if ciphertext.ciphertext_chunks.len() <= index
+            || g1.any(|point| miracl_g1_from_bytes(&point.0).is_err())
+            || ciphertext
+                .rand_z
+                .iter()
+                .any(|point| miracl_g2_from_bytes(&point.0).is_err())
+        {
+            return Err(format!("malformed ciphertext from dealer {}", dealer));
+        }
+    }
+    Ok(())
+}
+
+// this is required to verify dealings, but it is not used for any computation, so it's set to
+// default values define by dfinity
+fn nidkg_id() -> NiDkgId {
+    NiDkgId {
+        start_block_height: Height::new(3),
+        dealer_subnet: SubnetId::new(PrincipalId::new(
+            10,
+            [
+                1, 0, 0, 0, 0, 0, 0, 0, 0xfc, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0,
+            ],
+        )),
+        dkg_tag: NiDkgTag::HighThreshold,
+        target_subnet: NiDkgTargetSubnet::Remote(NiDkgTargetId::new([42; 32])),
+    }
+}
+
+impl NiSharing for ElGamal {
+    type Params = Params;
+    type Dealing = Dealing;
+    type Verified = Dealing;
+    type Transcript = Transcript;
+    type PublicCoefficients = PublicCoefficientsBytes;
+    type SecretKey = SecretKeyBytes;
+
+    fn receivers(params: &Params) -> Vec<Id> {
+        (0..params.receivers).map(Id::Univariate).collect()
+    }
+
+    fn dealers(params: &Params) -> Vec<Id> {
+        (params.receivers..params.receivers + params.dealers)
+            .map(Id::Univariate)
+            .collect()
+    }
+
+    fn has_secret_key(params: &Params) -> bool {
+        params.secret_key.is_some()
+    }
+
+    fn deal(params: &Params, index: u32) -> Result<(Dealing, Dealing), String> {
+        let dealing = create_dealing(
+            Randomness::from(rand::thread_rng().gen::<[u8; 32]>()),
+            Randomness::from(rand::thread_rng().gen::<[u8; 32]>()),
+            NumberOfNodes::new(params.threshold as u32),
+            &params.receiver_keys,
+            Epoch::from(1),
+            index,
+            None,
+        )
+        .map_err(|e| format!("unable to create dealing: {:?}", e))?;
+        Ok((dealing.clone(), dealing))
+    }
+
+    fn verify(params: &Params, index: u32, dealing: Dealing) -> Result<Dealing, String> {
+        verify_dealing(
+            nidkg_id(),
+            index,
+            NumberOfNodes::new(params.threshold as u32),
+            Epoch::from(1),
+            &params.receiver_keys,
+            &dealing,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(dealing)
+    }
+
+    fn transcript(
+        params: &Params,
+        dealings: &BTreeMap<u32, Dealing>,
+    ) -> Result<(Transcript, PublicCoefficientsBytes), String> {
+        let transcript = create_transcript(
+            NumberOfNodes::new(params.threshold as u32),
+            NumberOfNodes::new(params.receivers as u32),
+            dealings,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        let coefficients = transcript.public_coefficients.clone();
+        Ok((transcript, coefficients))
+    }
+
+    // decrypt our signing key and sign with it to check it
+    fn decrypt(
+        params: &Params,
+        id: Id,
+        transcript: &Transcript,
+    ) -> Result<(PublicCoefficientsBytes, SecretKeyBytes), String> {
+        let index = match id {
+            Id::Univariate(i) => i as u32,
+            _ => return Err(format!("{:?} isn't a receiver", id)),
+        };
+        let sk = params.secret_key.as_ref().ok_or("no secret key")?;
+        check_ciphertexts(&transcript.receiver_data, index as usize)?;
+        let signing_key =
+            compute_threshold_signing_key_univar(transcript.receiver_data.clone(), index, sk)
+                .map_err(|e| format!("unable to decrypt the signing key: {:?}", e))?;
+
+        let msg: [u8; 32] = [0; 32];
+        let public_key = individual_public_key(&transcript.public_coefficients, index)
+            .map_err(|e| format!("{:?}", e))?;
+        let signature = sign_message(&msg, &signing_key).map_err(|e| format!("{:?}", e))?;
+        verify_individual_signature(&msg, signature, public_key)
+            .map_err(|_| "the signing key doesn't fit the public coefficients".to_string())?;
+        Ok((transcript.public_coefficients.clone(), signing_key))
+    }
+}
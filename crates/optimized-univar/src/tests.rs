@@ -0,0 +1,71 @@
+use crate::machine::{OptimizedUnivarDkg, Params};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    api::{
+        combine_signatures, combined_public_key, individual_public_key, sign_message,
+        verify_combined_signature, verify_individual_signature,
+    },
+    ni_dkg::groth20_bls12_381::create_forward_secure_key_pair_el_gamal,
+};
+use ic_types::{NumberOfNodes, Randomness};
+use networking::{run_in_memory, DkgProtocol};
+use types::Id;
+
+// Tests the optimized univariate machine run in memory gives every receiver the key share of its
+// index in the same coefficients, and the shares combine to the public key
+#[test]
+fn machine_runs_in_memory() {
+    let keypairs: Vec<_> = (0..4)
+        .map(|_| {
+            create_forward_secure_key_pair_el_gamal(
+                Randomness::from(rand::random::<[u8; 32]>()),
+                &[2, 8, 1, 2],
+            )
+        })
+        .collect();
+    let params = Params {
+        receivers: 4,
+        dealers: 2,
+        threshold: 2,
+        receiver_keys: keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, (pk, _))| (i as u32, *pk))
+            .collect(),
+        secret_key: None,
+    };
+    assert!(OptimizedUnivarDkg::new(params.clone(), Id::Univariate(0)).is_err());
+    assert!(OptimizedUnivarDkg::new(params.clone(), Id::Univariate(6)).is_err());
+
+    let machines = (0..6)
+        .map(|i| {
+            let params = Params {
+                secret_key: keypairs.get(i).map(|(_, sk)| *sk),
+                ..params.clone()
+            };
+            OptimizedUnivarDkg::new(params, Id::Univariate(i)).unwrap()
+        })
+        .collect();
+    let outputs = run_in_memory(machines);
+    assert_eq!(outputs.len(), 6);
+
+    let msg: [u8; 32] = [0; 32];
+    let coefficients = &outputs[&Id::Univariate(4)].0;
+    let mut signatures = Vec::new();
+    for (id, (public_coefficients, signing_key)) in &outputs {
+        assert_eq!(public_coefficients, coefficients);
+        match (id, signing_key) {
+            (Id::Univariate(i), Some(signing_key)) if *i < 4 => {
+                let public_key = individual_public_key(coefficients, *i as u32).unwrap();
+                let signature = sign_message(&msg, signing_key).unwrap();
+                verify_individual_signature(&msg, signature, public_key).unwrap();
+                signatures.push(Some(signature));
+            }
+            (Id::Univariate(_), None) => {}
+            _ => panic!("{:?} got the wrong output", id),
+        }
+    }
+    assert_eq!(signatures.len(), 4);
+    let signature = combine_signatures(&signatures, NumberOfNodes::new(2)).unwrap();
+    let public_key = combined_public_key(coefficients).unwrap();
+    verify_combined_signature(&msg, signature, public_key).unwrap();
+}
@@ -0,0 +1,339 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use bls12_381::{G1Projective, G2Projective};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::verify_combined_sig, types::PublicKey};
+use networking::ThresholdScheme;
+use types::bivariate::PublicCoefficients;
+
+use crate::{
+    deserialize_signature,
+    message::{signature_from_bytes, signature_from_hex, signature_to_hex},
+    scheme::G1,
+    serialize_signature, Signing,
+};
+
+// Accountability for nested signatures
+//
+// Alongside the final signature a node can record which groups, and which members of each group,
+// it combined. A group that sends its group signature also sends a contribution: the bitmap of
+// the members whose shares it combined and the sum of those shares. Summed over the contributing
+// groups this gives a single aggregate, and the record is checked with one pairing,
+//     e(aggregate, g2) == e(H(m), sum of the public keys of the listed members)
+// using the individual keys derived from the public coefficients.
+//
+// Threshold shares aren't independent: t' members of a group can compute the share of any other
+// member. So a record shows which shares were combined, not that a listed member took part
+// against the will of the others.
+//
+// Accountable runs signatures this way: a group signature that arrives without a valid
+// contribution is dropped like any other frame that doesn't fit.
+
+// a set of indices, bit i is bit i % 8 of byte i / 8
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitmap(pub Vec<u8>);
+
+impl Bitmap {
+    pub fn set(&mut self, i: usize) {
+        if self.0.len() <= i / 8 {
+            self.0.resize(i / 8 + 1, 0);
+        }
+        self.0[i / 8] |= 1 << (i % 8);
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.0
+            .get(i / 8)
+            .map_or(false, |byte| byte & (1 << (i % 8)) != 0)
+    }
+
+    // the indices in the set in increasing order
+    pub fn indices(&self) -> Vec<usize> {
+        (0..self.0.len() * 8)
+            .filter(|i| self.contains(*i))
+            .collect()
+    }
+}
+
+impl FromIterator<usize> for Bitmap {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut bitmap = Bitmap::default();
+        for i in iter {
+            bitmap.set(i);
+        }
+        bitmap
+    }
+}
+
+// the members of one group whose shares were combined, and the sum of their shares
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contribution {
+    pub members: Bitmap,
+    pub aggregate: G1Projective,
+}
+
+impl Contribution {
+    // the contribution of the shares combined into a group signature, keyed by member
+    pub fn new(shares: &BTreeMap<usize, G1Projective>) -> Self {
+        Self {
+            members: shares.keys().copied().collect(),
+            aggregate: shares.values().sum(),
+        }
+    }
+
+    // check the aggregate against the listed members of `group`, in a layout of n groups of m
+    pub fn verify(
+        &self,
+        msg: &[u8],
+        coefficients: &PublicCoefficients,
+        (n, m): (usize, usize),
+        group: usize,
+    ) -> Result<(), String> {
+        let t_prime = coefficients.coefficients[0].len();
+        if group >= n {
+            return Err(format!("group {} is outside the layout", group));
+        }
+        if self.members.indices().iter().any(|j| *j >= m) {
+            return Err(format!(
+                "members outside the layout listed for group {}",
+                group
+            ));
+        }
+        if self.members.indices().len() < t_prime {
+            return Err(format!("too few members listed for group {}", group));
+        }
+        let public_key = self
+            .members
+            .indices()
+            .iter()
+            .map(|j| {
+                coefficients
+                    .individual_public_key((group as u32, *j as u32))
+                    .0
+            })
+            .sum::<G2Projective>();
+        verify_combined_sig(msg, self.aggregate, PublicKey(public_key))
+            .map_err(|_| format!("invalid contribution for group {}", group))
+    }
+
+    // the aggregate compressed, then the bitmap
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.aggregate.to_affine().to_compressed().to_vec();
+        bytes.extend_from_slice(&self.members.0);
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 48 {
+            return None;
+        }
+        Some(Self {
+            aggregate: signature_from_bytes(bytes[..48].to_vec())?,
+            members: Bitmap(bytes[48..].to_vec()),
+        })
+    }
+}
+
+// a group signature followed by the contribution of the group, if there is one
+pub fn serialize_group_signature(
+    group_sig: &G1Projective,
+    contribution: Option<&Contribution>,
+) -> Vec<u8> {
+    let mut bytes = serialize_signature(group_sig).to_vec();
+    if let Some(contribution) = contribution {
+        bytes.extend(contribution.serialize());
+    }
+    bytes
+}
+
+// deserialize a frame from serialize_group_signature, None if it doesn't hold a signature.
+// Signature shares are plain signatures and come out without a contribution
+pub fn deserialize_group_signature(bytes: &[u8]) -> Option<(G1Projective, Option<Contribution>)> {
+    if bytes.len() <= 96 {
+        return Some((deserialize_signature(bytes)?, None));
+    }
+    Some((
+        deserialize_signature(&bytes[..96])?,
+        Some(Contribution::deserialize(&bytes[96..])?),
+    ))
+}
+
+// nested signatures in G1 that carry the contribution of each group signature
+pub struct Accountable<'a> {
+    pub signing: Signing<'a, G1, PublicCoefficients>,
+    // n groups of m members
+    pub nodes: (usize, usize),
+}
+
+impl ThresholdScheme for Accountable<'_> {
+    // group signatures come with their contribution, other signatures without
+    type Share = (G1Projective, Option<Contribution>);
+
+    fn verify(&self, prefix: &[usize], share: &Self::Share) -> Result<(), String> {
+        self.signing.verify(prefix, &share.0)?;
+        match (prefix, &share.1) {
+            ([group], Some(contribution)) => {
+                contribution.verify(self.signing.msg, self.signing.keys, self.nodes, *group)
+            }
+            ([_], None) => Err(format!("missing contribution for {:?}", prefix)),
+            _ => Ok(()),
+        }
+    }
+
+    // contributions were checked on arrival, only the signatures are left to check
+    fn find_invalid(&self, prefix: &[usize], shares: &BTreeMap<usize, Self::Share>) -> Vec<usize> {
+        let signatures = shares.iter().map(|(i, (sig, _))| (*i, *sig)).collect();
+        self.signing.find_invalid(prefix, &signatures)
+    }
+
+    fn combine(
+        &self,
+        prefix: &[usize],
+        shares: &BTreeMap<usize, Self::Share>,
+        t: usize,
+    ) -> Result<Self::Share, String> {
+        let signatures: BTreeMap<usize, G1Projective> =
+            shares.iter().map(|(i, (sig, _))| (*i, *sig)).collect();
+        let signature = self.signing.combine(prefix, &signatures, t)?;
+        let contribution = (prefix.len() == 1).then(|| Contribution::new(&signatures));
+        Ok((signature, contribution))
+    }
+
+    fn serialize(&self, share: &Self::Share) -> Vec<u8> {
+        serialize_group_signature(&share.0, share.1.as_ref())
+    }
+
+    // a group signature without a contribution that verifies is dropped
+    fn deserialize(&self, path: &[usize], bytes: &[u8]) -> Option<Self::Share> {
+        let (signature, contribution) = deserialize_group_signature(bytes)?;
+        match (path, &contribution) {
+            ([group], Some(contribution)) => contribution
+                .verify(self.signing.msg, self.signing.keys, self.nodes, *group)
+                .ok()?,
+            ([_], None) | (_, Some(_)) => return None,
+            _ => (),
+        }
+        Some((signature, contribution))
+    }
+}
+
+// which groups and members a final signature was combined from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accountability {
+    pub groups: Bitmap,
+    // the members of each listed group, in the order of the groups
+    pub members: Vec<Bitmap>,
+    // the sum of the shares of every listed member
+    pub aggregate: G1Projective,
+}
+
+impl Accountability {
+    // the record of the contributions of the groups in a final signature, keyed by group
+    pub fn new(contributions: &BTreeMap<usize, Contribution>) -> Self {
+        Self {
+            groups: contributions.keys().copied().collect(),
+            members: contributions
+                .values()
+                .map(|contribution| contribution.members.clone())
+                .collect(),
+            aggregate: contributions
+                .values()
+                .map(|contribution| contribution.aggregate)
+                .sum(),
+        }
+    }
+
+    // check at least t groups of t' members of a layout of n groups of m are listed and the
+    // aggregate matches their keys
+    pub fn verify(
+        &self,
+        msg: &[u8],
+        coefficients: &PublicCoefficients,
+        (n, m): (usize, usize),
+    ) -> Result<(), String> {
+        let (t, t_prime) = (
+            coefficients.coefficients.len(),
+            coefficients.coefficients[0].len(),
+        );
+        let groups = self.groups.indices();
+        if groups.len() != self.members.len() {
+            return Err("one member bitmap is needed per group".to_string());
+        }
+        if groups.len() < t {
+            return Err("too few groups listed".to_string());
+        }
+        if groups.iter().any(|i| *i >= n) {
+            return Err("groups outside the layout listed".to_string());
+        }
+
+        let mut public_key = G2Projective::identity();
+        for (i, members) in groups.iter().zip(&self.members) {
+            let members = members.indices();
+            if members.iter().any(|j| *j >= m) {
+                return Err(format!("members outside the layout listed for group {}", i));
+            }
+            if members.len() < t_prime {
+                return Err(format!("too few members listed for group {}", i));
+            }
+            for j in members {
+                public_key += coefficients.individual_public_key((*i as u32, j as u32)).0;
+            }
+        }
+        verify_combined_sig(msg, self.aggregate, PublicKey(public_key))
+            .map_err(|_| "aggregate doesn't match the listed members".to_string())
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_string()).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path, err))?
+            .parse()
+    }
+}
+
+// Accountability files are text, one `<field> <value>` line per field:
+//     groups <hex of the group bitmap>
+//     members <hex of the member bitmap of each group, comma separated>
+//     aggregate <hex of the 48 byte compressed aggregate>
+impl fmt::Display for Accountability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "groups {}", hex::encode(&self.groups.0))?;
+        let members: Vec<String> = self
+            .members
+            .iter()
+            .map(|members| hex::encode(&members.0))
+            .collect();
+        writeln!(f, "members {}", members.join(","))?;
+        writeln!(f, "aggregate {}", signature_to_hex(&self.aggregate))
+    }
+}
+
+impl FromStr for Accountability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let mut field = |name: &str| -> Result<String, String> {
+            let line = lines.next().ok_or(format!("missing {}", name))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value.to_string()),
+                _ => Err(format!("expected {}", name)),
+            }
+        };
+
+        let groups = hex::decode(field("groups")?).map_err(|_| "invalid hex for groups")?;
+        let members = field("members")?
+            .split(',')
+            .map(|members| hex::decode(members).map(Bitmap))
+            .collect::<Result<Vec<Bitmap>, _>>()
+            .map_err(|_| "invalid hex for members")?;
+        Ok(Self {
+            groups: Bitmap(groups),
+            members,
+            aggregate: signature_from_hex(&field("aggregate")?)?,
+        })
+    }
+}
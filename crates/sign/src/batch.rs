@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use bls12_381::{
+    multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Gt,
+};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::hash_message_to_g1, types::PublicKey};
+use rand::Rng;
+
+// Batch verification of signature shares
+//
+// A share is valid if e(sig, g2) == e(H(m), pk). Instead of checking every share with two
+// pairings we pick random r_i and check
+//     e(sum r_i sig_i, -g2) * prod_m e(H(m), sum_{i: m_i = m} r_i pk_i) == 1
+// with a single multi-pairing. A batch holding an invalid share only passes with probability
+// 2^-128. Shares that sign the same message share a Miller loop, so for threshold signatures
+// the cost is about two pairings no matter how many shares are checked. Signatures in G2 (see g2)
+// are checked the same way with the groups swapped,
+//     e(-g1, sum r_i sig_i) * prod_m e(sum_{i: m_i = m} r_i pk_i, H(m)) == 1
+
+// a signature share together with the message it signs and the key it should verify under
+pub type Share<'a> = (&'a [u8], G1Projective, PublicKey);
+
+// the same for signatures in G2, with keys in G1
+pub type G2Share<'a> = (&'a [u8], G2Projective, G1Projective);
+
+// check all shares at once, true iff every share is valid
+pub fn batch_verify(shares: &[Share]) -> bool {
+    if shares.is_empty() {
+        return true;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut combined_sig = G1Projective::identity();
+    // combined public keys, keyed by message
+    let mut combined_pks: BTreeMap<&[u8], G2Projective> = BTreeMap::new();
+
+    for (message, signature, public_key) in shares {
+        // shares are usually deserialized unchecked, and points outside the subgroup
+        // could cancel each other out in the linear combination
+        if !bool::from(G1Affine::from(signature).is_torsion_free()) {
+            return false;
+        }
+
+        let r = random_scalar(&mut rng);
+        combined_sig += signature * r;
+        *combined_pks
+            .entry(*message)
+            .or_insert_with(G2Projective::identity) += public_key.0 * r;
+    }
+
+    let neg_g2 = G2Prepared::from(-G2Affine::generator());
+    let combined_sig = G1Affine::from(combined_sig);
+    let terms: Vec<(G1Affine, G2Prepared)> = combined_pks
+        .into_iter()
+        .map(|(message, pk)| {
+            (
+                G1Affine::from(hash_message_to_g1(message)),
+                G2Prepared::from(G2Affine::from(pk)),
+            )
+        })
+        .collect();
+
+    let mut pairs = vec![(&combined_sig, &neg_g2)];
+    pairs.extend(terms.iter().map(|(point, pk)| (point, pk)));
+
+    multi_miller_loop(&pairs).final_exponentiation() == Gt::identity()
+}
+
+// check all shares in G2 at once, true iff every share is valid
+pub fn batch_verify_g2(shares: &[G2Share]) -> bool {
+    if shares.is_empty() {
+        return true;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut combined_sig = G2Projective::identity();
+    // combined public keys, keyed by message
+    let mut combined_pks: BTreeMap<&[u8], G1Projective> = BTreeMap::new();
+
+    for (message, signature, public_key) in shares {
+        if !bool::from(signature.to_affine().is_torsion_free()) {
+            return false;
+        }
+
+        let r = random_scalar(&mut rng);
+        combined_sig += signature * r;
+        *combined_pks
+            .entry(*message)
+            .or_insert_with(G1Projective::identity) += public_key * r;
+    }
+
+    let neg_g1 = -G1Affine::generator();
+    let combined_sig = G2Prepared::from(combined_sig.to_affine());
+    let terms: Vec<(G1Affine, G2Prepared)> = combined_pks
+        .into_iter()
+        .map(|(message, pk)| {
+            (
+                pk.to_affine(),
+                G2Prepared::from(crate::g2::hash_message_to_g2(message).to_affine()),
+            )
+        })
+        .collect();
+
+    let mut pairs = vec![(&neg_g1, &combined_sig)];
+    pairs.extend(terms.iter().map(|(pk, point)| (pk, point)));
+
+    multi_miller_loop(&pairs).final_exponentiation() == Gt::identity()
+}
+
+// indices of the invalid shares, found by splitting failed batches in half
+// costs one batch when all shares are valid, and O(k log n) batches for k invalid shares
+pub fn find_invalid(shares: &[Share]) -> Vec<usize> {
+    let mut invalid = Vec::new();
+    bisect(shares, 0, &batch_verify, &mut invalid);
+    invalid
+}
+
+// the same for shares in G2
+pub fn find_invalid_g2(shares: &[G2Share]) -> Vec<usize> {
+    let mut invalid = Vec::new();
+    bisect(shares, 0, &batch_verify_g2, &mut invalid);
+    invalid
+}
+
+fn bisect<T>(
+    shares: &[T],
+    offset: usize,
+    batch_verify: &impl Fn(&[T]) -> bool,
+    invalid: &mut Vec<usize>,
+) {
+    if batch_verify(shares) {
+        return;
+    }
+    if shares.len() == 1 {
+        invalid.push(offset);
+        return;
+    }
+
+    let mid = shares.len() / 2;
+    bisect(&shares[..mid], offset, batch_verify, invalid);
+    bisect(&shares[mid..], offset + mid, batch_verify, invalid);
+}
+
+// a random 128 bit scalar, enough to make forging a passing batch as hard as breaking the curve
+fn random_scalar<R: Rng>(rng: &mut R) -> bls12_381::Scalar {
+    let r: u128 = rng.gen();
+    bls12_381::Scalar::from_raw([r as u64, (r >> 64) as u64, 0, 0])
+}
@@ -0,0 +1,177 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use bls12_381::G1Projective;
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::verify_combined_sig, types::PublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::message::{signature_from_hex, signature_to_hex, Signable};
+
+// A threshold BLS random beacon on the top level key
+//
+// Round r's output is SHA-256 of the compressed top level signature on round r - 1's output,
+// signed under the `nested-dkg-beacon` domain. BLS signatures are unique, so the output doesn't
+// depend on which groups signed, and nobody knows it before t groups have signed. Round 0 has no
+// signature, its output is SHA-256 of the compressed public key.
+//
+// Chains are text, one `<round> <output hex> <signature hex>` line per round starting at round 1,
+// so any round can be checked against the output of the line before it.
+
+pub const BEACON_DOMAIN: &str = "nested-dkg-beacon";
+
+// one round of the beacon
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Round {
+    pub round: u64,
+    pub output: [u8; 32],
+    pub signature: G1Projective,
+}
+
+// the rounds of a beacon in order, starting at round 1
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chain {
+    pub rounds: Vec<Round>,
+}
+
+// the output of round 0
+pub fn genesis(public_key: &PublicKey) -> [u8; 32] {
+    hash(&public_key.0.to_affine().to_compressed())
+}
+
+// what is signed in the round after the one with output `previous`
+pub fn beacon_message(previous: &[u8; 32]) -> Signable {
+    Signable::new(BEACON_DOMAIN, previous.to_vec())
+}
+
+fn hash(bytes: &[u8]) -> [u8; 32] {
+    let mut output = [0; 32];
+    output.copy_from_slice(&Sha256::digest(bytes));
+    output
+}
+
+impl Round {
+    pub fn new(round: u64, signature: G1Projective) -> Self {
+        Self {
+            round,
+            output: hash(&signature.to_affine().to_compressed()),
+            signature,
+        }
+    }
+
+    // check the round follows the round with output `previous`
+    pub fn verify(&self, previous: &[u8; 32], public_key: PublicKey) -> Result<(), String> {
+        if self.output != hash(&self.signature.to_affine().to_compressed()) {
+            return Err(format!(
+                "round {}: output doesn't match signature",
+                self.round
+            ));
+        }
+        verify_combined_sig(
+            &beacon_message(previous).bytes(),
+            self.signature,
+            public_key,
+        )
+        .map_err(|_| format!("round {}: invalid signature", self.round))
+    }
+}
+
+impl Chain {
+    // read a chain, a missing file is an empty chain
+    pub fn read(path: &str) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("{}: {}", path, err)),
+        };
+        let rounds = text
+            .lines()
+            .map(|line| line.parse())
+            .collect::<Result<Vec<Round>, String>>()?;
+        for (k, round) in rounds.iter().enumerate() {
+            if round.round != k as u64 + 1 {
+                return Err(format!("expected round {}, got {}", k + 1, round.round));
+            }
+        }
+        Ok(Self { rounds })
+    }
+
+    // append a round to the chain file at `path`
+    pub fn append(path: &str, round: &Round) -> Result<(), String> {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(format!("{}\n", round).as_bytes()))
+            .map_err(|err| format!("{}: {}", path, err))
+    }
+
+    // the output of the last round, or of round 0 for an empty chain
+    pub fn last_output(&self, public_key: &PublicKey) -> [u8; 32] {
+        match self.rounds.last() {
+            Some(round) => round.output,
+            None => genesis(public_key),
+        }
+    }
+
+    // check round `r` against the round before it
+    pub fn verify_round(&self, r: u64, public_key: PublicKey) -> Result<(), String> {
+        let round = match r {
+            0 => return Err("round 0 has no signature".to_string()),
+            r => self
+                .rounds
+                .get(r as usize - 1)
+                .ok_or(format!("round {} isn't in the chain", r))?,
+        };
+        let previous = match r {
+            1 => genesis(&public_key),
+            r => self.rounds[r as usize - 2].output,
+        };
+        round.verify(&previous, public_key)
+    }
+
+    // check every round of the chain
+    pub fn verify(&self, public_key: PublicKey) -> Result<(), String> {
+        let mut previous = genesis(&public_key);
+        for round in &self.rounds {
+            round.verify(&previous, public_key)?;
+            previous = round.output;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Round {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.round,
+            hex::encode(self.output),
+            signature_to_hex(&self.signature)
+        )
+    }
+}
+
+impl FromStr for Round {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let (round, output, signature) = match fields[..] {
+            [round, output, signature] => (round, output, signature),
+            _ => return Err(format!("invalid beacon round: {}", s)),
+        };
+        let round = round
+            .parse::<u64>()
+            .map_err(|_| format!("invalid round number: {}", round))?;
+        let output = hex::decode(output)
+            .ok()
+            .and_then(|output| output.try_into().ok())
+            .ok_or(format!("invalid output in round {}", round))?;
+        Ok(Self {
+            round,
+            output,
+            signature: signature_from_hex(signature)?,
+        })
+    }
+}
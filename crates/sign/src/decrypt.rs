@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_bls12381_common::random_bls12_381_scalar;
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::x_for_index,
+    types::{PublicCoefficients as IcPublicCoefficients, PublicKey},
+};
+use networking::ThresholdScheme;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use types::bivariate::PublicCoefficients;
+
+use crate::PrefixKeys;
+
+// Nested threshold decryption with bivariate keys
+//
+// Hashed ElGamal to the top level key pk = g2^s. A ciphertext is (g1^r, g2^r, V, tag) where the
+// key K = pk^r encrypts V with a SHA-256 keystream and authenticates it with the tag. Node (i, j)
+// answers with the decryption share (g2^r)^f(i, j). t' shares of a group interpolate into the
+// share of the group (g2^r)^f(i, 0), and t of those into K, just like nested signatures.
+//
+// g1^r makes every share checkable with a pairing, the same way a signature share is checked:
+// D is a valid share for the key g2^x if e(g1, D) == e(g1^r, g2^x). This works for member, group
+// and top level shares alike, so bad shares are rejected without separate proofs. Nodes check
+// e(g1^r, g2) == e(g1, g2^r) before answering, so both halves use the same r. Decryption runs
+// the shares over networking::ThresholdCore like any other nested scheme.
+
+const KEY_TAG: &[u8] = b"nested-dkg-decrypt-key";
+const MAC_TAG: &[u8] = b"nested-dkg-decrypt-mac";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ciphertext {
+    pub u1: G1Projective,
+    pub u2: G2Projective,
+    pub v: Vec<u8>,
+    pub tag: [u8; 32],
+}
+
+// encrypt `msg` to the public key of everyone
+pub fn encrypt<R: RngCore>(public_key: &PublicKey, msg: &[u8], rng: &mut R) -> Ciphertext {
+    let r = random_bls12_381_scalar(rng);
+    let key = public_key.0 * r;
+    let v = xor_keystream(&key, msg);
+    Ciphertext {
+        u1: G1Projective::generator() * r,
+        u2: G2Projective::generator() * r,
+        tag: mac(&key, &v),
+        v,
+    }
+}
+
+impl Ciphertext {
+    // check both halves of the ciphertext use the same randomness
+    pub fn check(&self) -> Result<(), String> {
+        let (u1, u2) = (self.u1.to_affine(), self.u2.to_affine());
+        if !bool::from(u1.is_torsion_free()) || !bool::from(u2.is_torsion_free()) {
+            return Err("invalid ciphertext".to_string());
+        }
+        if pairing(&u1, &G2Affine::generator()) != pairing(&G1Affine::generator(), &u2) {
+            return Err("invalid ciphertext".to_string());
+        }
+        Ok(())
+    }
+
+    // u1 and u2 uncompressed, then the tag and V
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = self.u1.to_affine().to_uncompressed().to_vec();
+        bytes.extend_from_slice(&self.u2.to_affine().to_uncompressed());
+        bytes.extend_from_slice(&self.tag);
+        bytes.extend_from_slice(&self.v);
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 96 + 192 + 32 {
+            return Err("ciphertext too short".to_string());
+        }
+        let u1: [u8; 96] = bytes[..96].try_into().unwrap();
+        let u2: [u8; 192] = bytes[96..288].try_into().unwrap();
+        let u1 = Option::<G1Affine>::from(G1Affine::from_uncompressed(&u1))
+            .ok_or("invalid ciphertext")?;
+        let u2 = Option::<G2Affine>::from(G2Affine::from_uncompressed(&u2))
+            .ok_or("invalid ciphertext")?;
+        Ok(Self {
+            u1: u1.into(),
+            u2: u2.into(),
+            tag: bytes[288..320].try_into().unwrap(),
+            v: bytes[320..].to_vec(),
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.serialize()).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        Self::deserialize(&std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?)
+    }
+}
+
+// the decryption share of a single node
+pub fn decryption_share(ciphertext: &Ciphertext, sk: &Scalar) -> G2Projective {
+    ciphertext.u2 * sk
+}
+
+// check a share, of a member, a group or everyone, against the matching public key
+pub fn verify_decryption_share(
+    ciphertext: &Ciphertext,
+    share: G2Projective,
+    public_key: PublicKey,
+) -> Result<(), String> {
+    let share = share.to_affine();
+    if !bool::from(share.is_torsion_free()) {
+        return Err("invalid decryption share".to_string());
+    }
+    if pairing(&G1Affine::generator(), &share)
+        == pairing(&ciphertext.u1.to_affine(), &public_key.0.to_affine())
+    {
+        Ok(())
+    } else {
+        Err("invalid decryption share".to_string())
+    }
+}
+
+// verify the decryption share of node `index` = (group, member)
+pub fn verify_member_share(
+    ciphertext: &Ciphertext,
+    share: G2Projective,
+    coefficients: &PublicCoefficients,
+    index: (usize, usize),
+) -> Result<(), String> {
+    let public_key = coefficients.individual_public_key((index.0 as u32, index.1 as u32));
+    verify_decryption_share(ciphertext, share, public_key)
+        .map_err(|_| format!("invalid decryption share from {:?}", index))
+}
+
+// verify the decryption share of `group`
+pub fn verify_group_share(
+    ciphertext: &Ciphertext,
+    share: G2Projective,
+    coefficients: &PublicCoefficients,
+    group: usize,
+) -> Result<(), String> {
+    verify_decryption_share(
+        ciphertext,
+        share,
+        coefficients.group_public_key(group as u32),
+    )
+    .map_err(|_| format!("invalid decryption share for group {}", group))
+}
+
+// combine decryption shares with interpolation, shares are keyed by the index of their sender.
+// t' member shares give the share of the group, t group shares give the key
+pub fn combine_decryption_shares(
+    shares: &BTreeMap<usize, G2Projective>,
+    t: usize,
+) -> Result<G2Projective, String> {
+    if shares.len() < t {
+        return Err("Invalid Threshold".to_string());
+    }
+
+    let shares: Vec<(Scalar, G2Projective)> = shares
+        .iter()
+        .map(|(k, v)| (x_for_index(*k as u32), *v))
+        .collect();
+    IcPublicCoefficients::interpolate_g2(&shares)
+        .map_err(|_| "Failed to interpolate decryption shares".to_string())
+}
+
+// decrypt with the combined key, an error if the tag doesn't match
+pub fn decrypt(ciphertext: &Ciphertext, key: G2Projective) -> Result<Vec<u8>, String> {
+    if mac(&key, &ciphertext.v) != ciphertext.tag {
+        return Err("decryption failed".to_string());
+    }
+    Ok(xor_keystream(&key, &ciphertext.v))
+}
+
+// xor `data` with SHA-256(KEY_TAG || K || counter) blocks
+fn xor_keystream(key: &G2Projective, data: &[u8]) -> Vec<u8> {
+    let key = key.to_affine().to_compressed();
+    data.chunks(32)
+        .enumerate()
+        .flat_map(|(counter, chunk)| {
+            let block = Sha256::new()
+                .chain(KEY_TAG)
+                .chain(key)
+                .chain((counter as u64).to_be_bytes())
+                .finalize();
+            chunk
+                .iter()
+                .zip(block.iter())
+                .map(|(a, b)| a ^ b)
+                .collect::<Vec<u8>>()
+        })
+        .collect()
+}
+
+fn mac(key: &G2Projective, v: &[u8]) -> [u8; 32] {
+    let mut tag = [0; 32];
+    tag.copy_from_slice(
+        &Sha256::new()
+            .chain(MAC_TAG)
+            .chain(key.to_affine().to_compressed())
+            .chain(v)
+            .finalize(),
+    );
+    tag
+}
+
+// shares are sent uncompressed like signatures in G2
+pub fn serialize_share(share: &G2Projective) -> [u8; 192] {
+    crate::g2::serialize_signature(share)
+}
+
+// deserialize a share from serialize_share, None if the bytes aren't a point
+pub fn deserialize_share(bytes: &[u8]) -> Option<G2Projective> {
+    crate::g2::deserialize_signature(bytes)
+}
+
+// decryption shares of `ciphertext` as a networking::ThresholdScheme over the keys of a layout
+pub struct Decryption<'a, K> {
+    pub ciphertext: &'a Ciphertext,
+    pub keys: &'a K,
+}
+
+impl<K: PrefixKeys<Key = PublicKey>> ThresholdScheme for Decryption<'_, K> {
+    type Share = G2Projective;
+
+    fn verify(&self, prefix: &[usize], share: &G2Projective) -> Result<(), String> {
+        verify_decryption_share(self.ciphertext, *share, self.keys.key(prefix))
+            .map_err(|_| format!("invalid decryption share for {:?}", prefix))
+    }
+
+    fn combine(
+        &self,
+        _prefix: &[usize],
+        shares: &BTreeMap<usize, G2Projective>,
+        t: usize,
+    ) -> Result<G2Projective, String> {
+        combine_decryption_shares(shares, t)
+    }
+
+    fn serialize(&self, share: &G2Projective) -> Vec<u8> {
+        serialize_share(share).to_vec()
+    }
+
+    fn deserialize(&self, _path: &[usize], bytes: &[u8]) -> Option<G2Projective> {
+        deserialize_share(bytes)
+    }
+}
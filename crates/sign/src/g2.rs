@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar,
+};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::x_for_index, types::PublicCoefficients as IcPublicCoefficients,
+};
+
+// Threshold signatures in G2 with keys in G1 (see types::g1)
+//
+// Everything else about the schemes stays the same, shares combine with Lagrange interpolation
+// just like signatures in G1. Signatures are twice the size, but public coefficients, which
+// grow with t·t', are half the size.
+
+// hash to curve suite of the basic scheme with signatures in G2
+const DOMAIN_HASH_MSG_TO_G2: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_NUL_";
+
+pub fn hash_message_to_g2(msg: &[u8]) -> G2Projective {
+    <G2Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(
+        msg,
+        DOMAIN_HASH_MSG_TO_G2,
+    )
+}
+
+// sign `msg` with a secret share
+pub fn sign_share(msg: &[u8], sk: &Scalar) -> G2Projective {
+    hash_message_to_g2(msg) * sk
+}
+
+// verify `signature` against a public key, checking e(g1, signature) == e(key, H(msg))
+pub fn verify(msg: &[u8], signature: G2Projective, public_key: G1Projective) -> Result<(), String> {
+    let signature = signature.to_affine();
+    if !bool::from(signature.is_torsion_free()) {
+        return Err("invalid signature".to_string());
+    }
+    let hash = hash_message_to_g2(msg).to_affine();
+    if pairing(&G1Affine::generator(), &signature) == pairing(&public_key.to_affine(), &hash) {
+        Ok(())
+    } else {
+        Err("invalid signature".to_string())
+    }
+}
+
+// combine signatures with interpolation, signatures are keyed by the index of their signer
+pub fn combine_signatures(
+    signatures: &BTreeMap<usize, G2Projective>,
+    t: usize,
+) -> Result<G2Projective, String> {
+    if signatures.len() < t {
+        return Err("Invalid Threshold".to_string());
+    }
+
+    let signatures: Vec<(Scalar, G2Projective)> = signatures
+        .iter()
+        .map(|(k, v)| (x_for_index(*k as u32), *v))
+        .collect();
+    IcPublicCoefficients::interpolate_g2(&signatures)
+        .map_err(|_| "Failed to interpolate signatures".to_string())
+}
+
+// signatures are sent uncompressed like signatures in G1
+pub fn serialize_signature(signature: &G2Projective) -> [u8; 192] {
+    signature.to_affine().to_uncompressed()
+}
+
+// deserialize a signature from serialize_signature, None if the bytes aren't a point
+pub fn deserialize_signature(bytes: &[u8]) -> Option<G2Projective> {
+    let bytes: [u8; 192] = bytes.try_into().ok()?;
+    Option::<G2Affine>::from(G2Affine::from_uncompressed_unchecked(&bytes)).map(G2Projective::from)
+}
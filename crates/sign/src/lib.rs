@@ -0,0 +1,106 @@
+pub mod accountable;
+pub mod batch;
+pub mod beacon;
+pub mod decrypt;
+pub mod g2;
+pub mod message;
+pub mod multivariate;
+pub mod scheme;
+pub mod verify;
+pub mod weighted;
+pub use message::{signature_from_hex, signature_to_hex, Signable, SignatureFile};
+pub use scheme::{PrefixKeys, SignatureGroup, Signing};
+pub use verify::{Coefficients, Level};
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::{verify_combined_sig, x_for_index},
+    types::PublicKey,
+};
+use types::bivariate::PublicCoefficients;
+
+use crate::batch::{find_invalid, Share};
+
+// combine signatures with interpolation, signatures are keyed by the index of their signer
+pub fn combine_signatures(
+    signatures: &BTreeMap<usize, G1Projective>,
+    t: usize,
+) -> Result<G1Projective, String> {
+    if signatures.len() < t {
+        return Err("Invalid Threshold".to_string());
+    }
+
+    let signatures: Vec<(Scalar, G1Projective)> = signatures
+        .iter()
+        .map(|(k, v)| (x_for_index(*k as u32), *v))
+        .collect();
+    PublicCoefficients::interpolate_g1(&signatures)
+}
+
+// Combine shares optimistically: interpolate the first t and check the result against
+// `public_key`. If it doesn't verify, the shares are batch verified against `share_key` and the
+// faulty ones are left out of a second attempt. Returns the signature, or an error if fewer than
+// t valid shares remain, along with the indices of the faulty contributors.
+pub fn combine_signatures_robust(
+    msg: &[u8],
+    signatures: &BTreeMap<usize, G1Projective>,
+    t: usize,
+    public_key: PublicKey,
+    share_key: impl Fn(usize) -> PublicKey,
+) -> (Result<G1Projective, String>, Vec<usize>) {
+    let first: BTreeMap<usize, G1Projective> = signatures
+        .iter()
+        .take(t)
+        .map(|(i, sig)| (*i, *sig))
+        .collect();
+    if let Ok(signature) = combine_signatures(&first, t) {
+        if verify_combined_sig(msg, signature, public_key).is_ok() {
+            return (Ok(signature), Vec::new());
+        }
+    }
+
+    let faulty = find_faulty(msg, signatures, share_key);
+    let valid: BTreeMap<usize, G1Projective> = signatures
+        .iter()
+        .filter(|(i, _)| !faulty.contains(i))
+        .take(t)
+        .map(|(i, sig)| (*i, *sig))
+        .collect();
+
+    (combine_signatures(&valid, t), faulty)
+}
+
+// the keys of the signatures that don't verify under `share_key`, found with batch verification
+pub fn find_faulty(
+    msg: &[u8],
+    signatures: &BTreeMap<usize, G1Projective>,
+    share_key: impl Fn(usize) -> PublicKey,
+) -> Vec<usize> {
+    let shares: Vec<Share> = signatures
+        .iter()
+        .map(|(i, sig)| (msg, *sig, share_key(*i)))
+        .collect();
+    let invalid = find_invalid(&shares);
+    signatures
+        .keys()
+        .enumerate()
+        .filter(|(k, _)| invalid.contains(k))
+        .map(|(_, i)| *i)
+        .collect()
+}
+
+// we send signatures uncompressed, the compressed encoding is really slow to decode
+pub fn serialize_signature(signature: &G1Projective) -> [u8; 96] {
+    signature.to_affine().to_uncompressed()
+}
+
+// deserialize a signature from serialize_signature, None if the bytes aren't a point
+pub fn deserialize_signature(bytes: &[u8]) -> Option<G1Projective> {
+    let bytes: [u8; 96] = bytes.try_into().ok()?;
+    Option::<G1Affine>::from(G1Affine::from_uncompressed_unchecked(&bytes)).map(G1Projective::from)
+}
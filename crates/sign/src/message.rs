@@ -0,0 +1,141 @@
+use std::{fmt, str::FromStr};
+
+use bls12_381::{G1Affine, G1Projective, G2Affine};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::verify_combined_sig, types::PublicKey};
+
+// Messages are never signed as is. The signed bytes are
+//     "nested-dkg-v1" || len(domain) as 8 byte big endian || domain || message
+// so a signature made for one application (domain) can't be passed off as one for another.
+//
+// Signature files are text, one `<field> <value>` line per field in this order:
+//     nested-dkg-signature v1
+//     domain <hex of the utf-8 domain>
+//     message <hex of the message>
+//     public_key <hex of the 96 byte compressed G2 public key>
+//     signature <hex of the 48 byte compressed G1 signature>
+// Points use the zcash compressed encoding. A third party checks the file by hashing the signed
+// bytes to G1 with the BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_ ciphersuite and checking
+// e(signature, g2) == e(H(signed bytes), public_key).
+
+const TAG: &[u8] = b"nested-dkg-v1";
+const HEADER: &str = "nested-dkg-signature v1";
+
+// a message together with the application it is signed for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signable {
+    pub domain: String,
+    pub message: Vec<u8>,
+}
+
+impl Signable {
+    pub fn new(domain: &str, message: Vec<u8>) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            message,
+        }
+    }
+
+    // the bytes that actually get signed
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = TAG.to_vec();
+        bytes.extend_from_slice(&(self.domain.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(self.domain.as_bytes());
+        bytes.extend_from_slice(&self.message);
+        bytes
+    }
+}
+
+// a signature with everything needed to check it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureFile {
+    pub signable: Signable,
+    pub public_key: PublicKey,
+    pub signature: G1Projective,
+}
+
+impl SignatureFile {
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_string()).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path, err))?
+            .parse()
+    }
+
+    // check the signature against the public key in the file
+    pub fn verify(&self) -> Result<(), String> {
+        verify_combined_sig(&self.signable.bytes(), self.signature, self.public_key)
+            .map_err(|_| "invalid signature".to_string())
+    }
+}
+
+// a signature as compressed hex, as in signature files
+pub fn signature_to_hex(signature: &G1Projective) -> String {
+    hex::encode(signature.to_affine().to_compressed())
+}
+
+// parse a compressed signature in hex, as in signature files
+pub fn signature_from_hex(signature: &str) -> Result<G1Projective, String> {
+    hex::decode(signature)
+        .ok()
+        .and_then(signature_from_bytes)
+        .ok_or_else(|| "invalid signature".to_string())
+}
+
+pub(crate) fn signature_from_bytes(bytes: Vec<u8>) -> Option<G1Projective> {
+    let bytes: [u8; 48] = bytes.try_into().ok()?;
+    Option::<G1Affine>::from(G1Affine::from_compressed(&bytes)).map(G1Projective::from)
+}
+
+impl fmt::Display for SignatureFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "domain {}", hex::encode(&self.signable.domain))?;
+        writeln!(f, "message {}", hex::encode(&self.signable.message))?;
+        writeln!(
+            f,
+            "public_key {}",
+            hex::encode(self.public_key.0.to_affine().to_compressed())
+        )?;
+        writeln!(f, "signature {}", signature_to_hex(&self.signature))
+    }
+}
+
+impl FromStr for SignatureFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a signature file".to_string());
+        }
+        let mut field = |name: &str| -> Result<Vec<u8>, String> {
+            let line = lines.next().ok_or(format!("missing {}", name))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => {
+                    hex::decode(value).map_err(|_| format!("invalid hex for {}", name))
+                }
+                _ => Err(format!("expected {}", name)),
+            }
+        };
+
+        let domain = String::from_utf8(field("domain")?).map_err(|_| "invalid domain")?;
+        let message = field("message")?;
+        let public_key = field("public_key")?
+            .try_into()
+            .ok()
+            .and_then(|bytes: [u8; 96]| Option::from(G2Affine::from_compressed(&bytes)))
+            .map(|key: G2Affine| PublicKey(key.into()))
+            .ok_or("invalid public key")?;
+        let signature = signature_from_bytes(field("signature")?).ok_or("invalid signature")?;
+
+        Ok(Self {
+            signable: Signable { domain, message },
+            public_key,
+            signature,
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::BTreeMap;
+
+use bls12_381::G1Projective;
+use ic_crypto_internal_threshold_sig_bls12381::crypto::verify_combined_sig;
+use networking::ThresholdScheme;
+use types::multivariate::PublicCoefficients;
+
+use crate::{scheme::G1, Signing};
+
+// Nested threshold signatures over any number of levels (see types::multivariate)
+//
+// A node signs with its share. t_d signatures of nodes that share an index prefix combine into a
+// signature under the key of that prefix, t_{d-1} of those into one under the key of the shorter
+// prefix, and so on until the empty prefix, the key of everyone. Signatures combine like any
+// others, with combine_signatures.
+
+// verify the signature of the node or set of nodes with the given index prefix
+pub fn verify_level(
+    msg: &[u8],
+    signature: G1Projective,
+    coefficients: &PublicCoefficients,
+    prefix: &[usize],
+) -> Result<(), String> {
+    verify_combined_sig(msg, signature, coefficients.key(prefix))
+        .map_err(|_| format!("invalid signature for {:?}", prefix))
+}
+
+// check the signatures of the members of a prefix at once with batch verification, keyed by the
+// last index of each member. Gives the last indices of the invalid ones
+pub fn find_invalid_level(
+    msg: &[u8],
+    signatures: &BTreeMap<usize, G1Projective>,
+    coefficients: &PublicCoefficients,
+    prefix: &[usize],
+) -> Vec<usize> {
+    Signing::<G1, _>::new(msg, coefficients).find_invalid(prefix, signatures)
+}
@@ -0,0 +1,259 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use bls12_381::{G1Projective, G2Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::{sign_message, verify_combined_sig, x_for_index},
+    types::{PublicCoefficients as IcPublicCoefficients, PublicKey},
+};
+use networking::ThresholdScheme;
+use types::{bivariate, bivariate::NestedKeys, g1, heterogeneous, multivariate};
+
+use crate::{
+    batch::{find_invalid, find_invalid_g2},
+    combine_signatures, deserialize_signature, g2, serialize_signature, signature_to_hex,
+};
+
+// Threshold signatures in either group as a networking::ThresholdScheme
+//
+// A SignatureGroup says how to sign, check and combine signatures in G1 or G2 (see g2), and
+// PrefixKeys gives the key of any prefix of a path from the public coefficients of a layout. Put
+// together in Signing they run over the same core for every layout: univariate, bivariate,
+// heterogeneous and multivariate keys, with signatures in either group.
+
+// groups are shared between the tasks of the signing daemon, so everything in them is Send
+pub trait SignatureGroup: Send + Sync + 'static {
+    type Signature: Copy + Send + Sync;
+    type PublicKey: Copy + Send + Sync;
+
+    // the size of a serialized signature
+    const SIZE: usize;
+
+    fn sign(msg: &[u8], sk: &Scalar) -> Self::Signature;
+
+    fn verify(msg: &[u8], signature: Self::Signature, key: Self::PublicKey) -> Result<(), String>;
+
+    // indices of the invalid shares, found with batch verification
+    fn find_invalid(msg: &[u8], shares: &[(Self::Signature, Self::PublicKey)]) -> Vec<usize>;
+
+    // combine signatures with interpolation, signatures are keyed by the index of their signer
+    fn combine(
+        signatures: &BTreeMap<usize, Self::Signature>,
+        t: usize,
+    ) -> Result<Self::Signature, String>;
+
+    fn serialize(signature: &Self::Signature) -> Vec<u8>;
+
+    // None if the bytes aren't a point
+    fn deserialize(bytes: &[u8]) -> Option<Self::Signature>;
+
+    // the compressed signature in hex
+    fn to_hex(signature: &Self::Signature) -> String;
+}
+
+// signatures in G1 with keys in G2
+pub struct G1;
+
+// signatures in G2 with keys in G1
+pub struct G2;
+
+impl SignatureGroup for G1 {
+    type Signature = G1Projective;
+    type PublicKey = PublicKey;
+
+    const SIZE: usize = 96;
+
+    fn sign(msg: &[u8], sk: &Scalar) -> G1Projective {
+        sign_message(msg, sk)
+    }
+
+    fn verify(msg: &[u8], signature: G1Projective, key: PublicKey) -> Result<(), String> {
+        verify_combined_sig(msg, signature, key).map_err(|_| "invalid signature".to_string())
+    }
+
+    fn find_invalid(msg: &[u8], shares: &[(G1Projective, PublicKey)]) -> Vec<usize> {
+        let shares: Vec<_> = shares.iter().map(|(sig, key)| (msg, *sig, *key)).collect();
+        find_invalid(&shares)
+    }
+
+    fn combine(
+        signatures: &BTreeMap<usize, G1Projective>,
+        t: usize,
+    ) -> Result<G1Projective, String> {
+        combine_signatures(signatures, t)
+    }
+
+    fn serialize(signature: &G1Projective) -> Vec<u8> {
+        serialize_signature(signature).to_vec()
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<G1Projective> {
+        deserialize_signature(bytes)
+    }
+
+    fn to_hex(signature: &G1Projective) -> String {
+        signature_to_hex(signature)
+    }
+}
+
+impl SignatureGroup for G2 {
+    type Signature = G2Projective;
+    type PublicKey = G1Projective;
+
+    const SIZE: usize = 192;
+
+    fn sign(msg: &[u8], sk: &Scalar) -> G2Projective {
+        g2::sign_share(msg, sk)
+    }
+
+    fn verify(msg: &[u8], signature: G2Projective, key: G1Projective) -> Result<(), String> {
+        g2::verify(msg, signature, key)
+    }
+
+    fn find_invalid(msg: &[u8], shares: &[(G2Projective, G1Projective)]) -> Vec<usize> {
+        let shares: Vec<_> = shares.iter().map(|(sig, key)| (msg, *sig, *key)).collect();
+        find_invalid_g2(&shares)
+    }
+
+    fn combine(
+        signatures: &BTreeMap<usize, G2Projective>,
+        t: usize,
+    ) -> Result<G2Projective, String> {
+        g2::combine_signatures(signatures, t)
+    }
+
+    fn serialize(signature: &G2Projective) -> Vec<u8> {
+        g2::serialize_signature(signature).to_vec()
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<G2Projective> {
+        g2::deserialize_signature(bytes)
+    }
+
+    fn to_hex(signature: &G2Projective) -> String {
+        hex::encode(signature.to_affine().to_compressed())
+    }
+}
+
+// the key of every prefix of a path, the empty prefix is the key of everyone
+pub trait PrefixKeys {
+    type Key: Copy;
+
+    fn key(&self, prefix: &[usize]) -> Self::Key;
+}
+
+// (group, member) paths, a group prefix gives the key of the group
+fn nested_key(keys: &impl NestedKeys, prefix: &[usize]) -> PublicKey {
+    match prefix {
+        [] => keys.public_key(),
+        [i] => keys.group_public_key(*i as u32),
+        [i, j, ..] => keys.individual_public_key((*i as u32, *j as u32)),
+    }
+}
+
+impl PrefixKeys for bivariate::PublicCoefficients {
+    type Key = PublicKey;
+
+    fn key(&self, prefix: &[usize]) -> PublicKey {
+        nested_key(self, prefix)
+    }
+}
+
+impl PrefixKeys for heterogeneous::PublicCoefficients {
+    type Key = PublicKey;
+
+    fn key(&self, prefix: &[usize]) -> PublicKey {
+        nested_key(self, prefix)
+    }
+}
+
+impl PrefixKeys for multivariate::PublicCoefficients {
+    type Key = PublicKey;
+
+    fn key(&self, prefix: &[usize]) -> PublicKey {
+        multivariate::PublicCoefficients::key(self, prefix)
+    }
+}
+
+// keys in G1, univariate dealings are the ones with t' = 1 where [i] is the key of node i
+impl PrefixKeys for g1::PublicCoefficients {
+    type Key = G1Projective;
+
+    fn key(&self, prefix: &[usize]) -> G1Projective {
+        match prefix {
+            [] => self.public_key(),
+            [i] => self.group_public_key(*i as u32),
+            [i, j, ..] => self.individual_public_key((*i as u32, *j as u32)),
+        }
+    }
+}
+
+// univariate keys, [p] is the key of point p
+impl PrefixKeys for IcPublicCoefficients {
+    type Key = PublicKey;
+
+    fn key(&self, prefix: &[usize]) -> PublicKey {
+        match prefix.first() {
+            None => PublicKey(self.evaluate_at(&Scalar::zero())),
+            Some(p) => PublicKey(self.evaluate_at(&x_for_index(*p as u32))),
+        }
+    }
+}
+
+// signatures on `msg` in group G under the keys of a layout
+pub struct Signing<'a, G, K> {
+    pub msg: &'a [u8],
+    pub keys: &'a K,
+    group: PhantomData<G>,
+}
+
+impl<'a, G, K> Signing<'a, G, K> {
+    pub fn new(msg: &'a [u8], keys: &'a K) -> Self {
+        Self {
+            msg,
+            keys,
+            group: PhantomData,
+        }
+    }
+}
+
+impl<G: SignatureGroup, K: PrefixKeys<Key = G::PublicKey>> ThresholdScheme for Signing<'_, G, K> {
+    type Share = G::Signature;
+
+    fn verify(&self, prefix: &[usize], share: &G::Signature) -> Result<(), String> {
+        G::verify(self.msg, *share, self.keys.key(prefix))
+            .map_err(|_| format!("invalid signature for {:?}", prefix))
+    }
+
+    // the shares are checked together with one batch verification
+    fn find_invalid(&self, prefix: &[usize], shares: &BTreeMap<usize, G::Signature>) -> Vec<usize> {
+        let batch: Vec<_> = shares
+            .iter()
+            .map(|(i, sig)| (*sig, self.keys.key(&networking::child(prefix, *i))))
+            .collect();
+        let invalid = G::find_invalid(self.msg, &batch);
+        shares
+            .keys()
+            .enumerate()
+            .filter(|(k, _)| invalid.contains(k))
+            .map(|(_, i)| *i)
+            .collect()
+    }
+
+    fn combine(
+        &self,
+        _prefix: &[usize],
+        shares: &BTreeMap<usize, G::Signature>,
+        t: usize,
+    ) -> Result<G::Signature, String> {
+        G::combine(shares, t)
+    }
+
+    fn serialize(&self, share: &G::Signature) -> Vec<u8> {
+        G::serialize(share)
+    }
+
+    fn deserialize(&self, _path: &[usize], bytes: &[u8]) -> Option<G::Signature> {
+        G::deserialize(bytes)
+    }
+}
@@ -0,0 +1,508 @@
+use crate::{batch::*, scheme::G1, *};
+use bls12_381::{G1Projective, G2Projective, Scalar};
+use ic_crypto_internal_threshold_sig_bls12381::crypto::x_for_index;
+use networking::ThresholdScheme;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use types::bivariate::{Polynomial, PublicCoefficients};
+
+// a directory of its own for a test's files, removed when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "sign_tests_{}_{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// bivariate keys for n groups of m nodes, the secret shares are indexed by group then member
+fn keys((n, m): (u32, u32), threshold: (usize, usize)) -> (PublicCoefficients, Vec<Vec<Scalar>>) {
+    let mut rng = ChaChaRng::from_seed([7; 32]);
+    let poly = Polynomial::random(threshold, &mut rng);
+    let shares = (0..n)
+        .map(|i| {
+            (0..m)
+                .map(|j| poly.evaluate_at(&x_for_index(i), &x_for_index(j)))
+                .collect()
+        })
+        .collect();
+    (PublicCoefficients::from(&poly), shares)
+}
+
+// Tests a full round of nested signing, from shares to the final signature
+#[test]
+fn nested_signature() {
+    let (coefficients, shares) = keys((4, 5), (3, 4));
+    let msg: [u8; 32] = [0; 32];
+    let scheme = Signing::<G1, _>::new(&msg, &coefficients);
+
+    let mut group_sigs = BTreeMap::new();
+    for i in 0..3 {
+        let mut group_shares = BTreeMap::new();
+        for j in 1..5 {
+            let share = G1::sign(&msg, &shares[i][j]);
+            scheme.verify(&[i, j], &share).unwrap();
+            group_shares.insert(j, share);
+        }
+        assert!(scheme.find_invalid(&[i], &group_shares).is_empty());
+
+        let group_sig = scheme.combine(&[i], &group_shares, 4).unwrap();
+        scheme.verify(&[i], &group_sig).unwrap();
+        assert!(scheme.verify(&[i + 1], &group_sig).is_err());
+        group_sigs.insert(i, group_sig);
+    }
+
+    let signature = scheme.combine(&[], &group_sigs, 3).unwrap();
+    scheme.verify(&[], &signature).unwrap();
+    assert!(scheme.combine(&[], &group_sigs, 4).is_err());
+}
+
+// Tests batches with valid shares pass and bisection finds the bad ones
+#[test]
+fn batch_verify_finds_invalid_shares() {
+    let (coefficients, secret_shares) = keys((3, 4), (2, 3));
+    let messages: [[u8; 32]; 2] = [[0; 32], [1; 32]];
+
+    let mut shares = Vec::new();
+    for i in 0..3 {
+        for j in 0..4 {
+            let message = &messages[(i + j) % 2][..];
+            let signature = G1::sign(message, &secret_shares[i][j]);
+            let public_key = coefficients.individual_public_key((i as u32, j as u32));
+            shares.push((message, signature, public_key));
+        }
+    }
+    assert!(batch_verify(&shares));
+    assert!(find_invalid(&shares).is_empty());
+
+    // a share over the wrong message and a share that isn't a signature at all
+    shares[4].0 = &messages[0][..];
+    shares[9].1 += G1Projective::generator();
+    assert!(!batch_verify(&shares));
+    assert_eq!(find_invalid(&shares), vec![4, 9]);
+}
+
+// Tests bad shares and group signatures are found in one batch, and the others still combine
+#[test]
+fn signing_finds_faulty_shares() {
+    let (coefficients, shares) = keys((4, 3), (2, 2));
+    let msg: [u8; 32] = [0; 32];
+    let scheme = Signing::<G1, _>::new(&msg, &coefficients);
+
+    let mut group_sigs = BTreeMap::new();
+    for i in 0..4 {
+        let mut group_shares: BTreeMap<usize, G1Projective> =
+            (0..3).map(|j| (j, G1::sign(&msg, &shares[i][j]))).collect();
+        *group_shares.get_mut(&0).unwrap() += G1Projective::generator();
+
+        assert_eq!(scheme.find_invalid(&[i], &group_shares), vec![0]);
+        group_shares.remove(&0);
+        group_sigs.insert(i, scheme.combine(&[i], &group_shares, 2).unwrap());
+    }
+    *group_sigs.get_mut(&0).unwrap() += G1Projective::generator();
+    assert_eq!(scheme.find_invalid(&[], &group_sigs), vec![0]);
+
+    let valid: BTreeMap<usize, G1Projective> = group_sigs
+        .iter()
+        .filter(|(i, _)| **i != 0)
+        .map(|(i, sig)| (*i, *sig))
+        .collect();
+    let signature = scheme.combine(&[], &valid, 2).unwrap();
+    scheme.verify(&[], &signature).unwrap();
+
+    *group_sigs.get_mut(&1).unwrap() += G1Projective::generator();
+    *group_sigs.get_mut(&2).unwrap() += G1Projective::generator();
+    assert_eq!(scheme.find_invalid(&[], &group_sigs), vec![0, 1, 2]);
+}
+
+// Tests signatures survive serialization and garbage is rejected
+#[test]
+fn serialize_signature_roundtrip() {
+    let (_, shares) = keys((1, 1), (1, 1));
+    let signature = G1::sign(&[1; 32], &shares[0][0]);
+
+    let bytes = serialize_signature(&signature);
+    assert_eq!(deserialize_signature(&bytes), Some(signature));
+    assert_eq!(deserialize_signature(&bytes[1..]), None);
+    assert_eq!(deserialize_signature(&[0xff; 96]), None);
+}
+
+// Tests domains separate signatures and signature files survive a write and read
+#[test]
+fn signature_file_roundtrip() {
+    let (coefficients, shares) = keys((3, 3), (2, 2));
+    let signable = Signable::new("beacon", b"round 1".to_vec());
+    assert_ne!(
+        signable.bytes(),
+        Signable::new("beaco", b"nround 1".to_vec()).bytes()
+    );
+
+    let group_sigs: BTreeMap<usize, G1Projective> = (0..2)
+        .map(|i| {
+            let group_shares = (0..2)
+                .map(|j| (j, G1::sign(&signable.bytes(), &shares[i][j])))
+                .collect();
+            (i, combine_signatures(&group_shares, 2).unwrap())
+        })
+        .collect();
+    let file = SignatureFile {
+        signable,
+        public_key: coefficients.public_key(),
+        signature: combine_signatures(&group_sigs, 2).unwrap(),
+    };
+    file.verify().unwrap();
+
+    let parsed: SignatureFile = file.to_string().parse().unwrap();
+    assert_eq!(parsed, file);
+
+    let mut other = parsed;
+    other.signable.domain = "other".to_string();
+    assert!(other.verify().is_err());
+    assert!("nested-dkg-signature v1\ndomain zz\n"
+        .parse::<SignatureFile>()
+        .is_err());
+}
+
+// Tests coefficients survive a file and check signatures at every level
+#[test]
+fn verify_levels() {
+    let (coefficients, shares) = keys((3, 3), (2, 2));
+    let msg: [u8; 32] = [0; 32];
+    let dir = TempDir::new();
+    let path = dir.file("coefficients");
+    Coefficients::Bivariate(coefficients.clone())
+        .write(&path)
+        .unwrap();
+    let read = Coefficients::read(&path).unwrap();
+    assert_eq!(read, Coefficients::Bivariate(coefficients));
+
+    let share = G1::sign(&msg, &shares[1][2]);
+    let group_shares = (0..2).map(|j| (j, G1::sign(&msg, &shares[1][j]))).collect();
+    let group_sig = combine_signatures(&group_shares, 2).unwrap();
+    read.verify(&msg, share, "individual:1,2".parse().unwrap())
+        .unwrap();
+    read.verify(&msg, group_sig, "group:1".parse().unwrap())
+        .unwrap();
+    assert!(read.verify(&msg, group_sig, Level::Group(0)).is_err());
+    assert!(read.verify(&msg, group_sig, Level::Top).is_err());
+    assert!(read.verify(&msg, share, Level::Individual(1)).is_err());
+
+    assert_eq!("top".parse::<Level>(), Ok(Level::Top));
+    assert!("group:x".parse::<Level>().is_err());
+}
+
+// Tests coefficient files with a point off the curve, without t' or cut short are an error
+#[test]
+fn verify_rejects_malformed_coefficients() {
+    let (coefficients, _) = keys((3, 3), (2, 2));
+    let dir = TempDir::new();
+    let path = dir.file("coefficients");
+    Coefficients::Bivariate(coefficients).write(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    // bincode puts the variant and the length of the points first, and t' last
+    let mut off_curve = bytes.clone();
+    off_curve[20] ^= 1;
+    let mut no_t_prime = bytes.clone();
+    let len = bytes.len();
+    no_t_prime[len - 8..].copy_from_slice(&[0; 8]);
+    for bytes in [off_curve, no_t_prime, bytes[..len - 100].to_vec()] {
+        std::fs::write(&path, bytes).unwrap();
+        assert!(Coefficients::read(&path).is_err());
+    }
+}
+
+// Tests nested signing with signatures in G2 and keys in G1
+#[test]
+fn g2_nested_signature() {
+    let dealing = types::g1::Dealing::generate((3, 3), (2, 2));
+    let msg: [u8; 32] = [0; 32];
+
+    let mut group_sigs = BTreeMap::new();
+    for i in 0..2 {
+        let shares: BTreeMap<usize, _> = (1..3)
+            .map(|j| (j, g2::sign_share(&msg, &dealing.1[i][j])))
+            .collect();
+        for (j, share) in &shares {
+            let public_key = dealing.0.individual_public_key((i as u32, *j as u32));
+            g2::verify(&msg, *share, public_key).unwrap();
+        }
+        let group_sig = g2::combine_signatures(&shares, 2).unwrap();
+        g2::verify(&msg, group_sig, dealing.0.group_public_key(i as u32)).unwrap();
+        group_sigs.insert(i, group_sig);
+    }
+
+    let signature = g2::combine_signatures(&group_sigs, 2).unwrap();
+    g2::verify(&msg, signature, dealing.0.public_key()).unwrap();
+    assert!(g2::verify(&[1; 32], signature, dealing.0.public_key()).is_err());
+
+    let bytes = g2::serialize_signature(&signature);
+    assert_eq!(g2::deserialize_signature(&bytes), Some(signature));
+
+    // dealings survive serialization at half the size of keys in G2
+    let (coefficients, scalars) = dealing.serialize();
+    assert_eq!(coefficients.len(), 2 * 2 * 96);
+    let recovered =
+        types::g1::Dealing::deserialize(&coefficients, &scalars, (3, 3), (2, 2)).unwrap();
+    assert_eq!(recovered.0, dealing.0);
+    assert_eq!(recovered.1, dealing.1);
+}
+
+// Tests a beacon chain survives a file, verifies, and doesn't depend on which groups signed
+#[test]
+fn beacon_chain() {
+    let (coefficients, shares) = keys((3, 3), (2, 2));
+    let public_key = coefficients.public_key();
+    // the top level signature of two groups, each signing with members 0 and 1
+    let sign = |msg: &[u8], groups: [usize; 2]| {
+        let group_sigs: BTreeMap<usize, G1Projective> = groups
+            .iter()
+            .map(|i| {
+                let group_shares = (0..2).map(|j| (j, G1::sign(msg, &shares[*i][j]))).collect();
+                (*i, combine_signatures(&group_shares, 2).unwrap())
+            })
+            .collect();
+        combine_signatures(&group_sigs, 2).unwrap()
+    };
+
+    let dir = TempDir::new();
+    let path = &dir.file("chain");
+    let mut previous = beacon::genesis(&public_key);
+    for r in 1..4 {
+        let msg = beacon::beacon_message(&previous).bytes();
+        let round = beacon::Round::new(r, sign(&msg, [0, 1]));
+        assert_eq!(round, beacon::Round::new(r, sign(&msg, [1, 2])));
+        beacon::Chain::append(path, &round).unwrap();
+        previous = round.output;
+    }
+
+    let chain = beacon::Chain::read(path).unwrap();
+    assert_eq!(chain.rounds.len(), 3);
+    assert_eq!(chain.last_output(&public_key), previous);
+    chain.verify(public_key).unwrap();
+    chain.verify_round(2, public_key).unwrap();
+    assert!(chain.verify_round(4, public_key).is_err());
+
+    let mut tampered = chain.clone();
+    tampered.rounds[1].output = [0; 32];
+    assert!(tampered.verify(public_key).is_err());
+    assert!(tampered.verify_round(3, public_key).is_err());
+}
+
+// Tests nested decryption shares recover the message and bad shares and ciphertexts are caught
+#[test]
+fn nested_decryption() {
+    let (coefficients, shares) = keys((3, 3), (2, 2));
+    let mut rng = ChaChaRng::from_seed([8; 32]);
+    let msg = b"a message longer than a single block of the keystream";
+    let ciphertext = decrypt::encrypt(&coefficients.public_key(), msg, &mut rng);
+    let ciphertext = decrypt::Ciphertext::deserialize(&ciphertext.serialize()).unwrap();
+    ciphertext.check().unwrap();
+
+    let mut group_shares = BTreeMap::new();
+    for i in 1..3 {
+        let member_shares: BTreeMap<usize, G2Projective> = (0..2)
+            .map(|j| (j, decrypt::decryption_share(&ciphertext, &shares[i][j])))
+            .collect();
+        for (j, share) in &member_shares {
+            decrypt::verify_member_share(&ciphertext, *share, &coefficients, (i, *j)).unwrap();
+        }
+        let group_share = decrypt::combine_decryption_shares(&member_shares, 2).unwrap();
+        decrypt::verify_group_share(&ciphertext, group_share, &coefficients, i).unwrap();
+        assert!(decrypt::verify_group_share(&ciphertext, group_share, &coefficients, 0).is_err());
+        group_shares.insert(i, group_share);
+    }
+
+    let key = decrypt::combine_decryption_shares(&group_shares, 2).unwrap();
+    decrypt::verify_decryption_share(&ciphertext, key, coefficients.public_key()).unwrap();
+    assert_eq!(decrypt::decrypt(&ciphertext, key).unwrap(), msg.to_vec());
+
+    let bad = decrypt::decryption_share(&ciphertext, &shares[0][0]);
+    assert!(decrypt::verify_member_share(&ciphertext, bad, &coefficients, (0, 1)).is_err());
+    let mut tampered = ciphertext.clone();
+    tampered.v[0] ^= 1;
+    assert!(decrypt::decrypt(&tampered, key).is_err());
+    tampered.u2 += G2Projective::generator();
+    assert!(tampered.check().is_err());
+}
+
+// Tests accountability records survive a file, list the contributors and catch false listings
+#[test]
+fn accountability_record() {
+    let (coefficients, shares) = keys((3, 4), (2, 2));
+    let msg: [u8; 32] = [0; 32];
+
+    let mut contributions = BTreeMap::new();
+    for (i, members) in [(0, [1, 3]), (2, [0, 2])] {
+        let group_shares: BTreeMap<usize, G1Projective> = members
+            .iter()
+            .map(|j| (*j, G1::sign(&msg, &shares[i][*j])))
+            .collect();
+        let contribution = accountable::Contribution::new(&group_shares);
+        contribution.verify(&msg, &coefficients, (3, 4), i).unwrap();
+        assert!(contribution.verify(&msg, &coefficients, (3, 4), 1).is_err());
+        // a layout too small for the group or its members
+        assert!(contribution.verify(&msg, &coefficients, (i, 4), i).is_err());
+        assert!(contribution.verify(&msg, &coefficients, (3, 2), i).is_err());
+
+        let group_sig = combine_signatures(&group_shares, 2).unwrap();
+        let frame = accountable::serialize_group_signature(&group_sig, Some(&contribution));
+        let (sig, received) = accountable::deserialize_group_signature(&frame).unwrap();
+        assert_eq!((sig, received), (group_sig, Some(contribution.clone())));
+        contributions.insert(i, contribution);
+    }
+
+    let dir = TempDir::new();
+    let path = dir.file("accountability");
+    accountable::Accountability::new(&contributions)
+        .write(&path)
+        .unwrap();
+    let record = accountable::Accountability::read(&path).unwrap();
+    record.verify(&msg, &coefficients, (3, 4)).unwrap();
+    assert!(record.verify(&msg, &coefficients, (2, 4)).is_err());
+    assert_eq!(record.groups.indices(), vec![0, 2]);
+    assert_eq!(record.members[0].indices(), vec![1, 3]);
+
+    let mut forged = record.clone();
+    forged.members[1].set(3);
+    assert!(forged.verify(&msg, &coefficients, (3, 4)).is_err());
+    contributions.remove(&2);
+    let too_few = accountable::Accountability::new(&contributions);
+    assert!(too_few.verify(&msg, &coefficients, (3, 4)).is_err());
+}
+
+// Tests nested signing with groups of different sizes and thresholds
+#[test]
+fn heterogeneous_nested_signature() {
+    use types::heterogeneous::{Dealing, Shape};
+
+    let shape = Shape::new(vec![2, 5, 3], vec![1, 4, 2], 2).unwrap();
+    let dealings: Vec<Dealing> = (0..2).map(|_| Dealing::generate(&shape)).collect();
+    let msg: [u8; 32] = [0; 32];
+
+    let mut group_sigs = BTreeMap::new();
+    for i in 1..3 {
+        let t_prime = shape.thresholds[i];
+        let mut shares = BTreeMap::new();
+        for j in shape.sizes[i] - t_prime..shape.sizes[i] {
+            let (coefficients, sk) = Dealing::combine((i, j), &dealings);
+            let share = G1::sign(&msg, &sk);
+            Signing::<G1, _>::new(&msg, &coefficients)
+                .verify(&[i, j], &share)
+                .unwrap();
+            shares.insert(j, share);
+        }
+        let coefficients = Dealing::combine((i, 0), &dealings).0;
+        let scheme = Signing::<G1, _>::new(&msg, &coefficients);
+        // one share short of the threshold doesn't give the group signature
+        let short: BTreeMap<usize, _> = shares
+            .iter()
+            .take(t_prime - 1)
+            .map(|(j, share)| (*j, *share))
+            .collect();
+        let short = scheme.combine(&[i], &short, t_prime - 1).unwrap();
+        assert!(scheme.verify(&[i], &short).is_err());
+        let group_sig = scheme.combine(&[i], &shares, t_prime).unwrap();
+        scheme.verify(&[i], &group_sig).unwrap();
+        group_sigs.insert(i, group_sig);
+    }
+
+    let coefficients = Dealing::combine((0, 0), &dealings).0;
+    let scheme = Signing::<G1, _>::new(&msg, &coefficients);
+    let signature = scheme.combine(&[], &group_sigs, 2).unwrap();
+    scheme.verify(&[], &signature).unwrap();
+
+    // dealings survive serialization and have to fit the groups
+    let (bytes, scalars) = dealings[0].serialize();
+    // t top coefficients and t'_i - 1 per group
+    assert_eq!(bytes.len(), (2 + 3 + 1) * 192);
+    let recovered = Dealing::deserialize(&bytes, &scalars, &shape).unwrap();
+    assert_eq!(recovered.0, dealings[0].0);
+    assert_eq!(recovered.1, dealings[0].1);
+    let other = Shape::new(vec![2, 5, 3], vec![2, 4, 2], 2).unwrap();
+    assert!(Dealing::deserialize(&bytes, &scalars, &other).is_none());
+    assert!(Shape::new(vec![2, 5], vec![3, 4], 1).is_err());
+}
+
+// Tests nested signing over three levels, regions of groups of nodes
+#[test]
+fn trivariate_nested_signature() {
+    use multivariate::{find_invalid_level, verify_level};
+    use types::multivariate::{Dealing, Levels};
+
+    let levels = Levels::new(vec![3, 2, 4], vec![2, 2, 3]).unwrap();
+    let dealings: Vec<Dealing> = (0..2).map(|_| Dealing::generate(&levels)).collect();
+    let coefficients = Dealing::combine(0, &dealings).0;
+    let msg: [u8; 32] = [0; 32];
+
+    let mut region_sigs = BTreeMap::new();
+    for r in 1..3 {
+        let mut group_sigs = BTreeMap::new();
+        for g in 0..2 {
+            let mut shares = BTreeMap::new();
+            for k in 1..4 {
+                let position = levels.position(&[r, g, k]);
+                let (_, sk) = Dealing::combine(position, &dealings);
+                shares.insert(k, G1::sign(&msg, &sk));
+            }
+            assert!(find_invalid_level(&msg, &shares, &coefficients, &[r, g]).is_empty());
+            // the share of another node is found in the batch
+            let mut tampered = shares.clone();
+            tampered.insert(2, shares[&1]);
+            assert_eq!(
+                find_invalid_level(&msg, &tampered, &coefficients, &[r, g]),
+                vec![2]
+            );
+            let group_sig = combine_signatures(&shares, 3).unwrap();
+            verify_level(&msg, group_sig, &coefficients, &[r, g]).unwrap();
+            // the signature of one group isn't that of another
+            assert!(verify_level(&msg, group_sig, &coefficients, &[r, 1 - g]).is_err());
+            group_sigs.insert(g, group_sig);
+        }
+        let region_sig = combine_signatures(&group_sigs, 2).unwrap();
+        verify_level(&msg, region_sig, &coefficients, &[r]).unwrap();
+        region_sigs.insert(r, region_sig);
+    }
+
+    let signature = combine_signatures(&region_sigs, 2).unwrap();
+    verify_level(&msg, signature, &coefficients, &[]).unwrap();
+    assert_eq!(coefficients.key(&[]), coefficients.public_key());
+
+    // nodes are numbered in row major order
+    let nodes = levels.nodes();
+    assert_eq!(nodes.len(), 24);
+    assert_eq!(nodes[levels.position(&[2, 1, 3])], vec![2, 1, 3]);
+    assert_eq!(levels.to_string(), "3-2-4_2-2-3");
+
+    // dealings survive serialization and have to fit the levels
+    let (bytes, scalars) = dealings[0].serialize();
+    assert_eq!(bytes.len(), 2 * 2 * 3 * 192);
+    let recovered = Dealing::deserialize(&bytes, &scalars, &levels).unwrap();
+    assert_eq!(recovered.0, dealings[0].0);
+    assert_eq!(recovered.1, dealings[0].1);
+    let other = Levels::new(vec![3, 2, 4], vec![2, 1, 3]).unwrap();
+    assert!(Dealing::deserialize(&bytes, &scalars, &other).is_none());
+    assert!(Levels::new(vec![3, 2], vec![2, 3]).is_err());
+}
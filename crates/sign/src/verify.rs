@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use bls12_381::{G1Projective, Scalar};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::{verify_combined_sig, x_for_index},
+    types::{PublicCoefficients, PublicKey},
+};
+use serde::{Deserialize, Serialize};
+use types::{bivariate, univariate::Dealing};
+
+// Public coefficients of a dealing, enough to derive every public key and check a signature at
+// any level without access to the secret shares. They're written next to the share files.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Coefficients {
+    Univariate(PublicCoefficients),
+    Bivariate(bivariate::PublicCoefficients),
+}
+
+// the key a signature is checked against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    // the key of everyone
+    Top,
+    // the key of group i, bivariate only
+    Group(usize),
+    // the key of node i, univariate only
+    Individual(usize),
+    // the key of node (i, j), bivariate only
+    Member(usize, usize),
+}
+
+// bincode encoding of the coefficients, reusing the encodings of the dealings
+#[derive(Serialize, Deserialize)]
+enum Serialized {
+    Univariate(Vec<Vec<u8>>),
+    Bivariate(Vec<u8>, usize),
+}
+
+impl Coefficients {
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let serialized = match self {
+            Coefficients::Univariate(coefficients) => {
+                Serialized::Univariate(Dealing(coefficients.clone(), Vec::new()).serialize().0)
+            }
+            Coefficients::Bivariate(coefficients) => {
+                Serialized::Bivariate(coefficients.serialize(), coefficients.coefficients[0].len())
+            }
+        };
+        std::fs::write(path, bincode::serialize(&serialized).unwrap())
+            .map_err(|err| format!("{}: {}", path, err))
+    }
+
+    // read coefficients written by write, checking every point is in the subgroup. An error if
+    // the file doesn't hold valid coefficients
+    pub fn read(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        let invalid = || format!("{}: invalid coefficients file", path);
+        match bincode::deserialize::<Serialized>(&bytes).map_err(|_| invalid())? {
+            Serialized::Univariate(coefficients) if !coefficients.is_empty() => {
+                Dealing::deserialize(&coefficients, &[], coefficients.len(), 0)
+                    .map(|dealing| Coefficients::Univariate(dealing.0))
+                    .ok_or_else(invalid)
+            }
+            Serialized::Bivariate(coefficients, t_prime) if t_prime > 0 => {
+                let t = coefficients.len() / t_prime / 192;
+                bivariate::PublicCoefficients::from_bytes(&coefficients, (t, t_prime))
+                    .filter(|_| t > 0)
+                    .map(Coefficients::Bivariate)
+                    .ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    // the public key at `level`, an error if the level doesn't exist for these coefficients
+    pub fn public_key(&self, level: Level) -> Result<PublicKey, String> {
+        match (self, level) {
+            (Coefficients::Univariate(coefficients), Level::Top) => {
+                Ok(PublicKey(coefficients.evaluate_at(&Scalar::zero())))
+            }
+            (Coefficients::Univariate(coefficients), Level::Individual(i)) => {
+                Ok(PublicKey(coefficients.evaluate_at(&x_for_index(i as u32))))
+            }
+            (Coefficients::Bivariate(coefficients), Level::Top) => Ok(coefficients.public_key()),
+            (Coefficients::Bivariate(coefficients), Level::Group(i)) => {
+                Ok(coefficients.group_public_key(i as u32))
+            }
+            (Coefficients::Bivariate(coefficients), Level::Member(i, j)) => {
+                Ok(coefficients.individual_public_key((i as u32, j as u32)))
+            }
+            (_, level) => Err(format!("{:?} doesn't apply to these coefficients", level)),
+        }
+    }
+
+    // verify `signature` on `msg` under the key at `level`
+    pub fn verify(&self, msg: &[u8], signature: G1Projective, level: Level) -> Result<(), String> {
+        verify_combined_sig(msg, signature, self.public_key(level)?)
+            .map_err(|_| format!("invalid signature for {:?}", level))
+    }
+}
+
+// top, group:<i>, individual:<i> or individual:<i>,<j>
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |index: &str| {
+            index
+                .parse::<usize>()
+                .map_err(|_| format!("invalid index {}", index))
+        };
+        match s.split_once(':') {
+            None if s == "top" => Ok(Level::Top),
+            Some(("group", i)) => Ok(Level::Group(parse(i)?)),
+            Some(("individual", index)) => match index.split_once(',') {
+                Some((i, j)) => Ok(Level::Member(parse(i)?, parse(j)?)),
+                None => Ok(Level::Individual(parse(index)?)),
+            },
+            _ => Err(format!("invalid level {}", s)),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use networking::ThresholdScheme;
+use types::univariate::Weights;
+
+use crate::{PrefixKeys, SignatureGroup, Signing};
+
+// Weighted threshold signatures (see types::univariate::Weights)
+//
+// A node of weight w signs with each of its w shares and sends them together. Signatures are
+// keyed by node and interpolated at the points each node holds, so the threshold t is in total
+// weight rather than in nodes. Weighted runs them, in either group, over networking::ThresholdCore
+// with one level of nodes.
+
+// weighted signatures as a networking::ThresholdScheme, [i] is node i and [p] the key of point p
+pub struct Weighted<'a, G, K> {
+    pub signing: Signing<'a, G, K>,
+    pub weights: &'a Weights,
+}
+
+impl<G: SignatureGroup, K: PrefixKeys<Key = G::PublicKey>> Weighted<'_, G, K> {
+    // the signature of every point with its key and the node that sent it
+    fn by_point(
+        &self,
+        signatures: &BTreeMap<usize, Vec<G::Signature>>,
+    ) -> Vec<(usize, G::Signature, G::PublicKey)> {
+        signatures
+            .iter()
+            .flat_map(|(i, sigs)| {
+                self.weights
+                    .points(*i)
+                    .zip(sigs.iter().copied())
+                    .map(move |(point, sig)| (*i, point, sig))
+            })
+            .map(|(i, point, sig)| (i, sig, self.signing.keys.key(&[point])))
+            .collect()
+    }
+}
+
+impl<G: SignatureGroup, K: PrefixKeys<Key = G::PublicKey>> ThresholdScheme for Weighted<'_, G, K> {
+    // one signature per point of the node, or the combined signature
+    type Share = Vec<G::Signature>;
+
+    fn verify(&self, prefix: &[usize], share: &Vec<G::Signature>) -> Result<(), String> {
+        let points: Vec<usize> = match prefix {
+            [] => Vec::new(),
+            [i, ..] => self.weights.points(*i).collect(),
+        };
+        if share.len() != points.len().max(1) {
+            return Err(format!("wrong number of signatures for {:?}", prefix));
+        }
+        if points.is_empty() {
+            return self.signing.verify(prefix, &share[0]);
+        }
+        for (point, sig) in points.iter().zip(share) {
+            G::verify(self.signing.msg, *sig, self.signing.keys.key(&[*point]))
+                .map_err(|_| format!("invalid signature for point {}", point))?;
+        }
+        Ok(())
+    }
+
+    // every point is checked in one batch, a node is invalid if any of its points is
+    fn find_invalid(
+        &self,
+        _prefix: &[usize],
+        shares: &BTreeMap<usize, Vec<G::Signature>>,
+    ) -> Vec<usize> {
+        let points = self.by_point(shares);
+        let batch: Vec<_> = points.iter().map(|(_, sig, key)| (*sig, *key)).collect();
+        let mut invalid: Vec<usize> = G::find_invalid(self.signing.msg, &batch)
+            .into_iter()
+            .map(|k| points[k].0)
+            .collect();
+        invalid.dedup();
+        invalid
+    }
+
+    // interpolate the first t points
+    fn combine(
+        &self,
+        _prefix: &[usize],
+        shares: &BTreeMap<usize, Vec<G::Signature>>,
+        t: usize,
+    ) -> Result<Vec<G::Signature>, String> {
+        let points: BTreeMap<usize, G::Signature> = shares
+            .iter()
+            .flat_map(|(i, sigs)| self.weights.points(*i).zip(sigs.iter().copied()))
+            .take(t)
+            .collect();
+        Ok(vec![G::combine(&points, t)?])
+    }
+
+    fn weight(&self, path: &[usize]) -> usize {
+        path.first().map_or(1, |i| self.weights.0[*i])
+    }
+
+    fn serialize(&self, share: &Vec<G::Signature>) -> Vec<u8> {
+        share.iter().flat_map(G::serialize).collect()
+    }
+
+    // the signatures of every point of the node at `path`, None if they don't fit its weight
+    fn deserialize(&self, path: &[usize], bytes: &[u8]) -> Option<Vec<G::Signature>> {
+        let weight = match path.first() {
+            Some(i) => *self.weights.0.get(*i)?,
+            None => 1,
+        };
+        if bytes.len() != weight * G::SIZE {
+            return None;
+        }
+        bytes.chunks_exact(G::SIZE).map(G::deserialize).collect()
+    }
+}
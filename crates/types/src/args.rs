@@ -12,6 +12,15 @@ pub struct Cli {
 pub enum Command {
     UnivariateDKG(UnivariateDKGArgs),
     BivariateDKG(BivariateDKGArgs),
+    BivariateRefresh(BivariateRefreshArgs),
+    BivariateRecovery(BivariateRecoveryArgs),
+    BivariateReshare(BivariateReshareArgs),
+    HeterogeneousShareFile(HeterogeneousShareFileArgs),
+    HeterogeneousDKG(HeterogeneousDKGArgs),
+    HeterogeneousThresholdSignature(HeterogeneousThresholdSignatureArgs),
+    TrivariateShareFile(TrivariateShareFileArgs),
+    TrivariateDKG(TrivariateDKGArgs),
+    TrivariateThresholdSignature(TrivariateThresholdSignatureArgs),
     UnivariateNiDKG(UnivariateNiDKGArgs),
     BivariateNiDKG(BivariateNiDKGArgs),
     NiDKGKeyPairs(NiDKGKeyPairsArgs),
@@ -20,9 +29,15 @@ pub enum Command {
     BivariateThresholdSignature(BivariateThresholdSignatureArgs),
     UnivariateShareFile(UnivariateShareFileArgs),
     BivariateShareFile(BivariateShareFileArgs),
+    Verify(VerifyArgs),
+    Serve(ServeArgs),
+    Beacon(BeaconArgs),
+    VerifyBeacon(VerifyBeaconArgs),
+    Encrypt(EncryptArgs),
+    BivariateThresholdDecryption(BivariateThresholdDecryptionArgs),
 }
 
-#[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
 pub struct UnivariateDKGArgs {
     // #[clap(short = 'd')]
     // num_dealers: usize,
@@ -34,6 +49,14 @@ pub struct UnivariateDKGArgs {
     pub threshold: usize,
     #[clap(short = 'a')]
     pub aws: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
+    // comma separated weight of each node, t is then in total weight
+    #[clap(long)]
+    pub weights: Option<String>,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
 }
 
 #[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
@@ -54,6 +77,87 @@ pub struct BivariateDKGArgs {
     pub threshold_t_prime: usize,
     #[clap(short = 'a')]
     pub aws: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
+}
+
+// refresh the shares of a bivariate key, the public key stays the same
+#[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BivariateRefreshArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // required, so every node leaves out the same dealings
+    #[clap(short = 'r')]
+    pub reliable: bool,
+}
+
+// recover the share of a lost node from its peers
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct BivariateRecoveryArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // <i>,<j> of the node that lost its share
+    #[clap(long)]
+    pub lost: String,
+    // row, members of its group help, or column, member j of other groups help
+    #[clap(long, default_value = "row")]
+    pub from: String,
+    // comma separated members (row) or groups (column) that help, the first ones if not set
+    #[clap(long)]
+    pub helpers: Option<String>,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct BivariateReshareArgs {
+    // <i>,<j> of the node in the old layout, if it was in it
+    #[clap(long)]
+    pub old: Option<String>,
+    // <i>,<j> of the node in the new layout, if it joins it
+    #[clap(long)]
+    pub new: Option<String>,
+    // the old layout
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    // file describing the new layout
+    #[clap(long)]
+    pub membership: String,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
 }
 
 #[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
@@ -72,6 +176,8 @@ pub struct UnivariateNiDKGArgs {
     pub aws: bool,
     #[clap(short = 'o')]
     pub optimized: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
 }
 
 #[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
@@ -96,6 +202,8 @@ pub struct BivariateNiDKGArgs {
     pub aws: bool,
     #[clap(short = 'o')]
     pub optimized: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
 }
 
 #[derive(Args, Clone, Copy, Debug, Eq, PartialEq)]
@@ -126,6 +234,17 @@ pub struct UnivariateThresholdSignatureArgs {
     pub threshold: usize,
     #[clap(short = 'a')]
     pub aws: bool,
+    #[clap(flatten)]
+    pub message: MessageArgs,
+    // write the final signature to a file
+    #[clap(long)]
+    pub output: Option<String>,
+    // comma separated weight of each node, t is then in total weight
+    #[clap(long)]
+    pub weights: Option<String>,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
 }
 
 #[derive(Args, Clone, Debug, Eq, PartialEq)]
@@ -144,6 +263,182 @@ pub struct BivariateThresholdSignatureArgs {
     pub threshold_t_prime: usize,
     #[clap(short = 'a')]
     pub aws: bool,
+    // full, gateways:<g> or tree:<arity>
+    #[clap(long, default_value = "full")]
+    pub topology: String,
+    // random or schedule, who group signatures are sent to
+    #[clap(long, default_value = "random")]
+    pub dissemination: String,
+    // every or leader:<timeout in ms>, who combines group signatures
+    #[clap(long, default_value = "every")]
+    pub aggregation: String,
+    #[clap(flatten)]
+    pub message: MessageArgs,
+    // write the final signature to a file
+    #[clap(long)]
+    pub output: Option<String>,
+    // write the groups and members the final signature was combined from to a file
+    #[clap(long)]
+    pub accountability: Option<String>,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct EncryptArgs {
+    // public coefficients written by the share file commands
+    #[clap(short = 'c')]
+    pub coefficients: String,
+    #[clap(long)]
+    pub message_hex: Option<String>,
+    // a path, or - for stdin
+    #[clap(long)]
+    pub message_file: Option<String>,
+    #[clap(long, default_value = "ciphertext")]
+    pub output: String,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct BivariateThresholdDecryptionArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // full, gateways:<g> or tree:<arity>
+    #[clap(long, default_value = "full")]
+    pub topology: String,
+    // random or schedule, who group shares are sent to
+    #[clap(long, default_value = "random")]
+    pub dissemination: String,
+    // a ciphertext written by encrypt
+    #[clap(long, default_value = "ciphertext")]
+    pub ciphertext: String,
+    // write the plaintext to a file, it's printed as hex otherwise
+    #[clap(long)]
+    pub output: Option<String>,
+}
+
+// groups of different sizes with their own thresholds
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct ShapeArgs {
+    // comma separated number of nodes in each group
+    #[clap(long)]
+    pub groups: String,
+    // comma separated number of members that sign for each group
+    #[clap(long)]
+    pub thresholds: String,
+    // number of groups that sign for everyone
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct HeterogeneousShareFileArgs {
+    #[clap(flatten)]
+    pub shape: ShapeArgs,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct HeterogeneousDKGArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(flatten)]
+    pub shape: ShapeArgs,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct HeterogeneousThresholdSignatureArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(flatten)]
+    pub shape: ShapeArgs,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // full, gateways:<g> or tree:<arity>
+    #[clap(long, default_value = "full")]
+    pub topology: String,
+    #[clap(flatten)]
+    pub message: MessageArgs,
+}
+
+// regions of groups of nodes, sizes and thresholds from the top down
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct LevelArgs {
+    // comma separated number of regions, groups per region and nodes per group
+    #[clap(long)]
+    pub sizes: String,
+    // comma separated number of regions, groups and members that sign at each level
+    #[clap(long)]
+    pub thresholds: String,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct TrivariateShareFileArgs {
+    #[clap(flatten)]
+    pub levels: LevelArgs,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct TrivariateDKGArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'k')]
+    pub node_index_k: usize,
+    #[clap(flatten)]
+    pub levels: LevelArgs,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    #[clap(short = 'r')]
+    pub reliable: bool,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct TrivariateThresholdSignatureArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'k')]
+    pub node_index_k: usize,
+    #[clap(flatten)]
+    pub levels: LevelArgs,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    #[clap(flatten)]
+    pub message: MessageArgs,
+}
+
+// the message to sign or verify, defaults to 32 zero bytes
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct MessageArgs {
+    #[clap(long)]
+    pub message_hex: Option<String>,
+    // a path, or - for stdin
+    #[clap(long)]
+    pub message_file: Option<String>,
+    #[clap(long, default_value = "nested-dkg")]
+    pub domain: String,
 }
 
 #[derive(Args, Clone, Debug, Eq, PartialEq)]
@@ -152,6 +447,12 @@ pub struct UnivariateShareFileArgs {
     pub num_nodes: usize,
     #[clap(short = 't')]
     pub threshold_t: usize,
+    // comma separated weight of each node, t is then in total weight
+    #[clap(long)]
+    pub weights: Option<String>,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
 }
 
 #[derive(Args, Clone, Debug, Eq, PartialEq)]
@@ -164,4 +465,114 @@ pub struct BivariateShareFileArgs {
     pub threshold_t: usize,
     #[clap(short = 'p')]
     pub threshold_t_prime: usize,
+    // signatures in G2 and keys in G1
+    #[clap(long)]
+    pub g2_signatures: bool,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct ServeArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // full, gateways:<g> or tree:<arity>
+    #[clap(long, default_value = "full")]
+    pub topology: String,
+    // defaults to node_<i>_<j>.sock
+    #[clap(long)]
+    pub socket: Option<String>,
+    // sign in G2 with the keys of `bivariate_shares_g2`
+    #[clap(long)]
+    pub g2_signatures: bool,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct BeaconArgs {
+    #[clap(short = 'i')]
+    pub node_index_i: usize,
+    #[clap(short = 'j')]
+    pub node_index_j: usize,
+    #[clap(short = 'n')]
+    pub num_nodes_n: usize,
+    #[clap(short = 'm')]
+    pub num_nodes_m: usize,
+    #[clap(short = 't')]
+    pub threshold_t: usize,
+    #[clap(short = 'p')]
+    pub threshold_t_prime: usize,
+    #[clap(short = 'a')]
+    pub aws: bool,
+    // full, gateways:<g> or tree:<arity>
+    #[clap(long, default_value = "full")]
+    pub topology: String,
+    // ms between the start of two rounds
+    #[clap(long, default_value = "1000")]
+    pub period: u64,
+    // stop after this many rounds, runs until ctrl-c if not set
+    #[clap(long)]
+    pub rounds: Option<u64>,
+    // defaults to beacon_<i>_<j>
+    #[clap(long)]
+    pub chain: Option<String>,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct VerifyBeaconArgs {
+    // public coefficients written by the share file commands
+    #[clap(short = 'c')]
+    pub coefficients: String,
+    // a chain written by the beacon
+    #[clap(long)]
+    pub chain: String,
+    // check one round, checks the whole chain if not set
+    #[clap(long)]
+    pub round: Option<u64>,
+}
+
+// args of the load test client
+#[derive(Parser, Clone, Debug, Eq, PartialEq)]
+pub struct ClientArgs {
+    #[clap(short = 's')]
+    pub socket: String,
+    #[clap(short = 'r', default_value = "100")]
+    pub requests: usize,
+    #[clap(short = 'c', default_value = "1")]
+    pub concurrency: usize,
+    #[clap(long, default_value = "nested-dkg")]
+    pub domain: String,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct VerifyArgs {
+    // public coefficients written by the share file commands
+    #[clap(short = 'c')]
+    pub coefficients: String,
+    // hex of a compressed signature, the message is given with the message args
+    #[clap(long)]
+    pub signature: Option<String>,
+    // a signature file written with --output, it holds its own message
+    #[clap(long)]
+    pub signature_file: Option<String>,
+    // top, group:<i>, individual:<i> or individual:<i>,<j>
+    #[clap(long, default_value = "top")]
+    pub level: String,
+    // also check an accountability file written with the signature, bivariate only
+    #[clap(long)]
+    pub accountability: Option<String>,
+    // <n>,<m> of the layout, needed with --accountability
+    #[clap(long)]
+    pub nodes: Option<String>,
+    #[clap(flatten)]
+    pub message: MessageArgs,
 }
@@ -5,13 +5,16 @@ use ic_crypto_internal_threshold_sig_bls12381::{
     crypto::{public_key_from_secret_key, x_for_index},
     types::{PublicCoefficients as PC, PublicKey},
 };
-use rand::RngCore;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
 use serde::{Deserialize, Serialize};
 use std::ops::{AddAssign, Mul, MulAssign};
 
+use crate::Sharing;
+
 // Implements structures needed for bivariate dkg like bivariate Polynomial, PublicCoefficients, and dealings
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Message {
     Shares(
         #[serde(with = "serde_bytes")] Vec<u8>,
@@ -19,6 +22,15 @@ pub enum Message {
     ),
 }
 
+// the messages of a refresh: the public coefficients of a dealing go to every node, a share only
+// to the node it's for, and the dealers whose share didn't match to every node
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RefreshMessage {
+    Commitments(#[serde(with = "serde_bytes")] Vec<u8>),
+    Share(#[serde(with = "serde_bytes")] Vec<u8>),
+    Complaints(Vec<crate::Id>),
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Polynomial {
     pub coefficients: Vec<Vec<Scalar>>,
@@ -169,21 +181,46 @@ impl PublicCoefficients {
             .collect()
     }
 
-    // deserialize the public coefficients
-    pub fn deserialize(bytes: Vec<u8>, t_prime: usize) -> Self {
-        Self {
-            coefficients: bytes
-                .chunks_exact(192)
-                .map(|chunk| {
-                    PublicKey(G2Projective::from(
-                        &G2Affine::from_uncompressed_unchecked(chunk.try_into().unwrap()).unwrap(),
-                    ))
-                })
-                .collect::<Vec<PublicKey>>()
+    // deserialize public coefficients of thresholds (t, t') from a peer, checking every point is
+    // in the subgroup. None if they don't fit
+    pub fn from_bytes(bytes: &[u8], (t, t_prime): (usize, usize)) -> Option<Self> {
+        if t_prime == 0 || bytes.len() != t * t_prime * 192 {
+            return None;
+        }
+        let points = bytes
+            .chunks_exact(192)
+            .map(|chunk| {
+                Option::from(G2Affine::from_uncompressed(chunk.try_into().unwrap()))
+                    .map(|point: G2Affine| PublicKey(G2Projective::from(&point)))
+            })
+            .collect::<Option<Vec<PublicKey>>>()?;
+        Some(Self {
+            coefficients: points
                 .chunks_exact(t_prime)
                 .map(|chunk| chunk.to_vec())
                 .collect(),
-        }
+        })
+    }
+}
+
+// the keys of a nested sharing: of everyone, of a group and of a single node
+pub trait NestedKeys {
+    fn public_key(&self) -> PublicKey;
+    fn group_public_key(&self, group_index: u32) -> PublicKey;
+    fn individual_public_key(&self, index: (u32, u32)) -> PublicKey;
+}
+
+impl NestedKeys for PublicCoefficients {
+    fn public_key(&self) -> PublicKey {
+        PublicCoefficients::public_key(self)
+    }
+
+    fn group_public_key(&self, group_index: u32) -> PublicKey {
+        PublicCoefficients::group_public_key(self, group_index)
+    }
+
+    fn individual_public_key(&self, index: (u32, u32)) -> PublicKey {
+        PublicCoefficients::individual_public_key(self, index)
     }
 }
 
@@ -214,9 +251,50 @@ impl From<Polynomial> for PublicCoefficients {
     }
 }
 
+// the layout of a bivariate dkg, n groups of m nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub nodes: (u32, u32),
+    pub threshold: (usize, usize),
+}
+
 pub struct Dealing(pub PublicCoefficients, pub Vec<Vec<Scalar>>);
 
 impl Dealing {
+    // deal shares of a random polynomial to n groups of m nodes
+    pub fn generate((n, m): (u32, u32), (t, t_prime): (usize, usize)) -> Self {
+        let seed = rand::random::<[u8; 32]>();
+        let mut rng = ChaChaRng::from_seed(seed);
+        let poly = Polynomial::random((t, t_prime), &mut rng);
+
+        Self::deal(&poly, (n, m))
+    }
+
+    // evaluate the polynomial at every node
+    pub fn deal(poly: &Polynomial, (n, m): (u32, u32)) -> Self {
+        let shares = (0..n)
+            .map(|i| {
+                (0..m)
+                    .map(|j| poly.evaluate_at(&x_for_index(i), &x_for_index(j)))
+                    .collect::<Vec<Scalar>>()
+            })
+            .collect::<Vec<Vec<Scalar>>>();
+        Dealing(PublicCoefficients::from(poly), shares)
+    }
+
+    // sum the shares of node `index` and the public coefficients of all dealings
+    pub fn combine(index: (usize, usize), dealings: &[Dealing]) -> (PublicCoefficients, Scalar) {
+        dealings.iter().skip(1).fold(
+            (dealings[0].0.clone(), dealings[0].1[index.0][index.1]),
+            |(coefficients, share), dealing| {
+                (
+                    coefficients.add(&dealing.0),
+                    share + dealing.1[index.0][index.1],
+                )
+            },
+        )
+    }
+
     // serialize a dealing
     pub fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
         (
@@ -249,35 +327,67 @@ impl Dealing {
         )
     }
 
-    // deserialize a dealing
+    // deserialize a dealing to n groups of m nodes with thresholds (t, t') from a peer, checking
+    // every point is in the subgroup and every share is canonical. None if it doesn't fit
     pub fn deserialize(
-        coefficients: Vec<u8>,
-        scalars: Vec<u8>,
-        group_size: usize,
-        t_prime: usize,
-    ) -> Self {
-        Dealing(
-            PublicCoefficients {
-                coefficients: coefficients
-                    .chunks_exact(192)
-                    .map(|chunk| {
-                        PublicKey(G2Projective::from(
-                            &G2Affine::from_uncompressed_unchecked(chunk.try_into().unwrap())
-                                .unwrap(),
-                        ))
-                    })
-                    .collect::<Vec<PublicKey>>()
-                    .chunks_exact(t_prime)
-                    .map(|chunk| chunk.to_vec())
-                    .collect(),
-            },
+        coefficients: &[u8],
+        scalars: &[u8],
+        (n, m): (u32, u32),
+        threshold: (usize, usize),
+    ) -> Option<Self> {
+        let (n, m) = (n as usize, m as usize);
+        if m == 0 || scalars.len() != n * m * 32 {
+            return None;
+        }
+        let scalars = scalars
+            .chunks_exact(32)
+            .map(|chunk| Option::from(Scalar::from_bytes(chunk.try_into().unwrap())))
+            .collect::<Option<Vec<Scalar>>>()?;
+        Some(Dealing(
+            PublicCoefficients::from_bytes(coefficients, threshold)?,
             scalars
-                .chunks_exact(32)
-                .map(|chunk| Scalar::from_bytes(chunk.try_into().unwrap()).unwrap())
-                .collect::<Vec<Scalar>>()
-                .chunks_exact(group_size)
+                .chunks_exact(m)
                 .map(|chunk| chunk.to_vec())
                 .collect(),
-        )
+        ))
+    }
+}
+
+// nodes are at their row major position, node (i, j) at i * m + j
+impl Sharing for Dealing {
+    type Layout = Params;
+    type Output = (PublicCoefficients, Scalar);
+
+    fn node_count(layout: &Params) -> usize {
+        (layout.nodes.0 * layout.nodes.1) as usize
+    }
+
+    fn generate(layout: &Params) -> Self {
+        Dealing::generate(layout.nodes, layout.threshold)
+    }
+
+    fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        Dealing::serialize(self)
+    }
+
+    fn deserialize(layout: &Params, coefficients: &[u8], shares: &[u8]) -> Result<Self, String> {
+        Dealing::deserialize(coefficients, shares, layout.nodes, layout.threshold)
+            .ok_or("the dealing doesn't fit the layout".to_string())
+    }
+
+    fn combine(layout: &Params, position: usize, dealings: &[Self]) -> Self::Output {
+        let m = layout.nodes.1 as usize;
+        Dealing::combine((position / m, position % m), dealings)
+    }
+}
+
+// check t groups of n and t' members of m can sign, 1 <= t <= n and 1 <= t' <= m
+pub fn check_threshold((n, m): (usize, usize), (t, t_prime): (usize, usize)) -> Result<(), String> {
+    if t == 0 || t > n {
+        return Err(format!("t has to be between 1 and {}", n));
+    }
+    if t_prime == 0 || t_prime > m {
+        return Err(format!("t' has to be between 1 and {}", m));
     }
+    Ok(())
 }
@@ -0,0 +1,204 @@
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_threshold_sig_bls12381::crypto::x_for_index;
+
+use crate::{
+    bivariate::{Params, Polynomial},
+    util::horner,
+    Sharing,
+};
+
+// Keys in G1 for the variant with signatures in G2
+//
+// A G1 point is half the size of a G2 point, so dealings and public coefficients are half the
+// size on the wire. A univariate polynomial is a bivariate polynomial with t' = 1, so univariate
+// dealings use these types too, as n groups of one node where node i is (i, 0).
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicCoefficients {
+    pub coefficients: Vec<Vec<G1Projective>>,
+}
+
+impl PublicCoefficients {
+    // evaluate the polynomial at a point
+    pub fn evaluate_at(&self, x: &Scalar, y: &Scalar) -> G1Projective {
+        // Horner's method over both variables
+        let rows: Vec<G1Projective> = self
+            .coefficients
+            .iter()
+            .map(|row| horner(row, G1Projective::identity(), *y))
+            .collect();
+        horner(&rows, G1Projective::identity(), *x)
+    }
+
+    // add two polynomials together returning the result
+    // ! This assumes both are same size
+    pub fn add(&self, rhs: &Self) -> Self {
+        PublicCoefficients {
+            coefficients: self
+                .coefficients
+                .iter()
+                .zip(rhs.coefficients.iter())
+                .map(|(a, b)| a.iter().zip(b.iter()).map(|(a, b)| a + b).collect())
+                .collect(),
+        }
+    }
+
+    // return the public key of everyone
+    pub fn public_key(&self) -> G1Projective {
+        self.coefficients[0][0]
+    }
+
+    // return the public key of a specific group
+    pub fn group_public_key(&self, group_index: u32) -> G1Projective {
+        self.evaluate_at(&x_for_index(group_index), &Scalar::zero())
+    }
+
+    // return an individual nodes public key
+    pub fn individual_public_key(&self, index: (u32, u32)) -> G1Projective {
+        self.evaluate_at(&x_for_index(index.0), &x_for_index(index.1))
+    }
+
+    // serialize the public coefficients, 96 bytes per coefficient
+    pub fn serialize(&self) -> Vec<u8> {
+        self.coefficients
+            .iter()
+            .flatten()
+            .flat_map(|coefficient| coefficient.to_affine().to_uncompressed().to_vec())
+            .collect()
+    }
+
+    // deserialize public coefficients of thresholds (t, t') from a peer, checking every point is
+    // in the subgroup. None if they don't fit
+    pub fn from_bytes(bytes: &[u8], (t, t_prime): (usize, usize)) -> Option<Self> {
+        if t_prime == 0 || bytes.len() != t * t_prime * 96 {
+            return None;
+        }
+        let points = bytes
+            .chunks_exact(96)
+            .map(|chunk| {
+                Option::from(G1Affine::from_uncompressed(chunk.try_into().unwrap()))
+                    .map(|point: G1Affine| G1Projective::from(&point))
+            })
+            .collect::<Option<Vec<G1Projective>>>()?;
+        Some(Self {
+            coefficients: points
+                .chunks_exact(t_prime)
+                .map(|chunk| chunk.to_vec())
+                .collect(),
+        })
+    }
+}
+
+// generate public coefficients from a polynomial
+impl From<&Polynomial> for PublicCoefficients {
+    fn from(polynomial: &Polynomial) -> Self {
+        PublicCoefficients {
+            coefficients: polynomial
+                .coefficients
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|coefficient| G1Projective::generator() * coefficient)
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+// a dealing with keys in G1, shares are indexed by group then member
+pub struct Dealing(pub PublicCoefficients, pub Vec<Vec<Scalar>>);
+
+impl Dealing {
+    // deal shares of a random polynomial to n groups of m nodes
+    pub fn generate((n, m): (u32, u32), (t, t_prime): (usize, usize)) -> Self {
+        let poly = Polynomial::random((t, t_prime), &mut rand::thread_rng());
+        let shares = (0..n)
+            .map(|i| {
+                (0..m)
+                    .map(|j| poly.evaluate_at(&x_for_index(i), &x_for_index(j)))
+                    .collect()
+            })
+            .collect();
+        Dealing(PublicCoefficients::from(&poly), shares)
+    }
+
+    // serialize a dealing
+    pub fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        (
+            self.0.serialize(),
+            self.1
+                .iter()
+                .flatten()
+                .flat_map(|scalar| scalar.to_bytes().to_vec())
+                .collect(),
+        )
+    }
+
+    // deserialize a dealing to n groups of m nodes with thresholds (t, t') from a peer, checking
+    // every point is in the subgroup and every share is canonical. None if it doesn't fit
+    pub fn deserialize(
+        coefficients: &[u8],
+        scalars: &[u8],
+        (n, m): (u32, u32),
+        threshold: (usize, usize),
+    ) -> Option<Self> {
+        let (n, m) = (n as usize, m as usize);
+        if m == 0 || scalars.len() != n * m * 32 {
+            return None;
+        }
+        let scalars = scalars
+            .chunks_exact(32)
+            .map(|chunk| Option::from(Scalar::from_bytes(chunk.try_into().unwrap())))
+            .collect::<Option<Vec<Scalar>>>()?;
+        Some(Dealing(
+            PublicCoefficients::from_bytes(coefficients, threshold)?,
+            scalars
+                .chunks_exact(m)
+                .map(|chunk| chunk.to_vec())
+                .collect(),
+        ))
+    }
+
+    // sum the shares of node `index` and the public coefficients of all dealings
+    pub fn combine(index: (usize, usize), dealings: &[Dealing]) -> (PublicCoefficients, Scalar) {
+        dealings.iter().skip(1).fold(
+            (dealings[0].0.clone(), dealings[0].1[index.0][index.1]),
+            |(coefficients, share), dealing| {
+                (
+                    coefficients.add(&dealing.0),
+                    share + dealing.1[index.0][index.1],
+                )
+            },
+        )
+    }
+}
+
+// laid out like bivariate dealings, node (i, j) at i * m + j
+impl Sharing for Dealing {
+    type Layout = Params;
+    type Output = (PublicCoefficients, Scalar);
+
+    fn node_count(layout: &Params) -> usize {
+        (layout.nodes.0 * layout.nodes.1) as usize
+    }
+
+    fn generate(layout: &Params) -> Self {
+        Dealing::generate(layout.nodes, layout.threshold)
+    }
+
+    fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        Dealing::serialize(self)
+    }
+
+    fn deserialize(layout: &Params, coefficients: &[u8], shares: &[u8]) -> Result<Self, String> {
+        Dealing::deserialize(coefficients, shares, layout.nodes, layout.threshold)
+            .ok_or("the dealing doesn't fit the layout".to_string())
+    }
+
+    fn combine(layout: &Params, position: usize, dealings: &[Self]) -> Self::Output {
+        let m = layout.nodes.1 as usize;
+        Dealing::combine((position / m, position % m), dealings)
+    }
+}
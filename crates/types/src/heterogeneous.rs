@@ -0,0 +1,331 @@
+use std::fmt;
+
+use bls12_381::{G2Affine, G2Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_bls12381_common::random_bls12_381_scalar;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::x_for_index, types::PublicKey};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use crate::{
+    bivariate::NestedKeys,
+    util::{horner, join},
+    Sharing,
+};
+
+// Groups of different sizes with their own thresholds
+//
+// A bivariate polynomial gives every group the same threshold, f(i, y) has degree t' - 1 for
+// every i. Here the secret is g(0) for a polynomial g(x) of degree t - 1, and group i has its own
+// polynomial h_i(y) of degree t'_i - 1 with h_i(0) = g(i). Node (i, j) holds h_i(j). t'_i members
+// of group i sign under g(i), and t groups under g(0), the same way as with bivariate keys.
+// Each h_i is stored without its constant term, which is always g(i), so the public coefficients
+// are those of g and the rest of each h_i, and the key of a group comes from those of g alone.
+
+// the sizes and thresholds of the groups
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    // the number of nodes in each group
+    pub sizes: Vec<usize>,
+    // the number of members of each group that sign for it
+    pub thresholds: Vec<usize>,
+    // the number of groups that sign for everyone
+    pub t: usize,
+}
+
+impl Shape {
+    pub fn new(sizes: Vec<usize>, thresholds: Vec<usize>, t: usize) -> Result<Self, String> {
+        if sizes.len() != thresholds.len() {
+            return Err("one threshold is needed per group".to_string());
+        }
+        if t == 0 || t > sizes.len() {
+            return Err(format!("t has to be between 1 and {}", sizes.len()));
+        }
+        for (i, (size, threshold)) in sizes.iter().zip(&thresholds).enumerate() {
+            if *threshold == 0 || threshold > size {
+                return Err(format!(
+                    "threshold of group {} has to be between 1 and {}",
+                    i, size
+                ));
+            }
+        }
+        Ok(Self {
+            sizes,
+            thresholds,
+            t,
+        })
+    }
+
+    // every node, ordered by group then member
+    pub fn nodes(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.sizes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, size)| (0..*size).map(move |j| (i, j)))
+    }
+
+    // the number of nodes in all groups
+    pub fn node_count(&self) -> usize {
+        self.sizes.iter().sum()
+    }
+}
+
+// <sizes>_<t>,<thresholds>, lists joined with '-', used in results file names
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}_{},{}",
+            join(&self.sizes),
+            self.t,
+            join(&self.thresholds)
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polynomial {
+    // the coefficients of g
+    pub top: Vec<Scalar>,
+    // the coefficients of each h_i but the constant term
+    pub groups: Vec<Vec<Scalar>>,
+}
+
+impl Polynomial {
+    // generate a random polynomial
+    pub fn random<R: RngCore>(shape: &Shape, rng: &mut R) -> Self {
+        Self {
+            top: (0..shape.t).map(|_| random_bls12_381_scalar(rng)).collect(),
+            groups: shape
+                .thresholds
+                .iter()
+                .map(|t_prime| {
+                    (1..*t_prime)
+                        .map(|_| random_bls12_381_scalar(rng))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    // the share of member j of group i, h_i(j)
+    pub fn evaluate_at(&self, (i, j): (u32, u32)) -> Scalar {
+        let top = horner(&self.top, Scalar::zero(), x_for_index(i));
+        let y = x_for_index(j);
+        horner(&self.groups[i as usize], Scalar::zero(), y) * y + top
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicCoefficients {
+    pub top: Vec<G2Projective>,
+    pub groups: Vec<Vec<G2Projective>>,
+}
+
+impl PublicCoefficients {
+    // return the public key of everyone
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.top[0])
+    }
+
+    // return the public key of a specific group
+    pub fn group_public_key(&self, group_index: u32) -> PublicKey {
+        PublicKey(horner(
+            &self.top,
+            G2Projective::identity(),
+            x_for_index(group_index),
+        ))
+    }
+
+    // return an individual nodes public key
+    pub fn individual_public_key(&self, (i, j): (u32, u32)) -> PublicKey {
+        let y = x_for_index(j);
+        let rest = horner(&self.groups[i as usize], G2Projective::identity(), y);
+        PublicKey(rest * y + self.group_public_key(i).0)
+    }
+
+    // add two sets of coefficients together returning the result
+    // ! This assumes both have the same shape
+    pub fn add(&self, rhs: &Self) -> Self {
+        let add = |a: &[G2Projective], b: &[G2Projective]| {
+            a.iter().zip(b).map(|(a, b)| a + b).collect::<Vec<_>>()
+        };
+        Self {
+            top: add(&self.top, &rhs.top),
+            groups: self
+                .groups
+                .iter()
+                .zip(&rhs.groups)
+                .map(|(a, b)| add(a, b))
+                .collect(),
+        }
+    }
+
+    // serialize the coefficients of g then those of each group, 192 bytes per coefficient
+    pub fn serialize(&self) -> Vec<u8> {
+        self.top
+            .iter()
+            .chain(self.groups.iter().flatten())
+            .flat_map(|coefficient| coefficient.to_affine().to_uncompressed().to_vec())
+            .collect()
+    }
+
+    // deserialize the public coefficients, None if they don't fit the shape
+    pub fn deserialize(bytes: &[u8], shape: &Shape) -> Option<Self> {
+        let expected = shape.t + shape.thresholds.iter().map(|t| t - 1).sum::<usize>();
+        if bytes.len() != expected * 192 {
+            return None;
+        }
+        let mut coefficients = bytes.chunks_exact(192).map(|chunk| {
+            Option::<G2Affine>::from(G2Affine::from_uncompressed(&chunk.try_into().unwrap()))
+                .map(G2Projective::from)
+        });
+        let top = coefficients
+            .by_ref()
+            .take(shape.t)
+            .collect::<Option<Vec<_>>>()?;
+        let groups = shape
+            .thresholds
+            .iter()
+            .map(|t_prime| {
+                coefficients
+                    .by_ref()
+                    .take(t_prime - 1)
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { top, groups })
+    }
+}
+
+impl NestedKeys for PublicCoefficients {
+    fn public_key(&self) -> PublicKey {
+        PublicCoefficients::public_key(self)
+    }
+
+    fn group_public_key(&self, group_index: u32) -> PublicKey {
+        PublicCoefficients::group_public_key(self, group_index)
+    }
+
+    fn individual_public_key(&self, index: (u32, u32)) -> PublicKey {
+        PublicCoefficients::individual_public_key(self, index)
+    }
+}
+
+// generate public coefficients from a polynomial
+impl From<&Polynomial> for PublicCoefficients {
+    fn from(polynomial: &Polynomial) -> Self {
+        let commit = |coefficients: &[Scalar]| {
+            coefficients
+                .iter()
+                .map(|coefficient| G2Projective::generator() * coefficient)
+                .collect::<Vec<_>>()
+        };
+        Self {
+            top: commit(&polynomial.top),
+            groups: polynomial
+                .groups
+                .iter()
+                .map(|group| commit(group))
+                .collect(),
+        }
+    }
+}
+
+// a dealing for groups of different sizes, shares are indexed by group then member
+pub struct Dealing(pub PublicCoefficients, pub Vec<Vec<Scalar>>);
+
+impl Dealing {
+    // deal shares of a random polynomial to the groups of `shape`
+    pub fn generate(shape: &Shape) -> Self {
+        let seed = rand::random::<[u8; 32]>();
+        let mut rng = ChaChaRng::from_seed(seed);
+        let poly = Polynomial::random(shape, &mut rng);
+        let shares = shape
+            .sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| {
+                (0..*size)
+                    .map(|j| poly.evaluate_at((i as u32, j as u32)))
+                    .collect()
+            })
+            .collect();
+        Dealing(PublicCoefficients::from(&poly), shares)
+    }
+
+    // serialize a dealing
+    pub fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        (
+            self.0.serialize(),
+            self.1
+                .iter()
+                .flatten()
+                .flat_map(|scalar| scalar.to_bytes().to_vec())
+                .collect(),
+        )
+    }
+
+    // deserialize a dealing, None if it doesn't fit the shape
+    pub fn deserialize(coefficients: &[u8], scalars: &[u8], shape: &Shape) -> Option<Self> {
+        if scalars.len() != shape.node_count() * 32 {
+            return None;
+        }
+        let mut scalars = scalars
+            .chunks_exact(32)
+            .map(|chunk| Option::<Scalar>::from(Scalar::from_bytes(chunk.try_into().unwrap())));
+        let shares = shape
+            .sizes
+            .iter()
+            .map(|size| scalars.by_ref().take(*size).collect::<Option<Vec<_>>>())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Dealing(
+            PublicCoefficients::deserialize(coefficients, shape)?,
+            shares,
+        ))
+    }
+
+    // sum the shares of node `index` and the public coefficients of all dealings
+    pub fn combine(index: (usize, usize), dealings: &[Dealing]) -> (PublicCoefficients, Scalar) {
+        dealings.iter().skip(1).fold(
+            (dealings[0].0.clone(), dealings[0].1[index.0][index.1]),
+            |(coefficients, share), dealing| {
+                (
+                    coefficients.add(&dealing.0),
+                    share + dealing.1[index.0][index.1],
+                )
+            },
+        )
+    }
+}
+
+// nodes are at their position in Shape::nodes, group by group
+impl Sharing for Dealing {
+    type Layout = Shape;
+    type Output = (PublicCoefficients, Scalar);
+
+    fn node_count(shape: &Shape) -> usize {
+        shape.node_count()
+    }
+
+    fn generate(shape: &Shape) -> Self {
+        Dealing::generate(shape)
+    }
+
+    fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        Dealing::serialize(self)
+    }
+
+    fn deserialize(shape: &Shape, coefficients: &[u8], shares: &[u8]) -> Result<Self, String> {
+        Dealing::deserialize(coefficients, shares, shape)
+            .ok_or_else(|| "the dealing doesn't fit the groups".to_string())
+    }
+
+    fn combine(shape: &Shape, position: usize, dealings: &[Self]) -> Self::Output {
+        let index = shape
+            .nodes()
+            .nth(position)
+            .expect("no node at that position");
+        Dealing::combine(index, dealings)
+    }
+}
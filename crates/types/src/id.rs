@@ -6,6 +6,8 @@ use std::cmp::Ordering;
 pub enum Id {
     Univariate(usize),
     Bivariate(usize, usize),
+    // region, group, member
+    Trivariate(usize, usize, usize),
 }
 
 impl PartialOrd for Id {
@@ -27,8 +29,28 @@ impl Ord for Id {
                     b.cmp(d)
                 }
             }
-            (Id::Bivariate(_, _), Id::Univariate(_)) => Ordering::Greater,
-            (Id::Univariate(_), Id::Bivariate(_, _)) => Ordering::Less,
+            (Id::Trivariate(a, b, c), Id::Trivariate(d, e, f)) => (a, b, c).cmp(&(d, e, f)),
+            // univariate ids come first, then bivariate, then trivariate
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl Id {
+    // the indices of the node from the top level down, group before member
+    pub fn path(&self) -> Vec<usize> {
+        match self {
+            Id::Univariate(i) => vec![*i],
+            Id::Bivariate(i, j) => vec![*i, *j],
+            Id::Trivariate(r, g, k) => vec![*r, *g, *k],
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Id::Univariate(_) => 0,
+            Id::Bivariate(_, _) => 1,
+            Id::Trivariate(_, _, _) => 2,
         }
     }
 }
@@ -38,6 +60,7 @@ impl PartialEq for Id {
         match (self, other) {
             (Id::Univariate(a), Id::Univariate(b)) => a == b,
             (Id::Bivariate(a, b), Id::Bivariate(c, d)) => a == c && b == d,
+            (Id::Trivariate(a, b, c), Id::Trivariate(d, e, f)) => a == d && b == e && c == f,
             _ => false,
         }
     }
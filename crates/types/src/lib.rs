@@ -1,6 +1,12 @@
 mod args;
 pub mod bivariate;
+pub mod g1;
+pub mod heterogeneous;
 mod id;
+pub mod multivariate;
+mod sharing;
 pub mod univariate;
+mod util;
 pub use args::*;
 pub use id::Id;
+pub use sharing::Sharing;
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use bls12_381::{G2Affine, G2Projective, Scalar};
+use group::Curve;
+use ic_crypto_internal_bls12381_common::random_bls12_381_scalar;
+use ic_crypto_internal_threshold_sig_bls12381::{crypto::x_for_index, types::PublicKey};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use crate::{
+    util::{horner_nested, join},
+    Sharing,
+};
+
+// Nested sharing over any number of levels
+//
+// A polynomial f(x_1, ..., x_d) of degree t_k - 1 in x_k shares f(0, ..., 0) over d levels, and
+// node (i_1, ..., i_d) holds f(i_1, ..., i_d). t_d nodes that share the first d - 1 indices
+// interpolate in x_d to f(i_1, ..., i_{d-1}, 0), the key of the set they belong to. t_{d-1} of
+// those interpolate in x_{d-1}, and so on up to f(0, ..., 0), the key of everyone. With d = 2
+// this is the bivariate scheme, with d = 3 regions of groups of nodes.
+//
+// Coefficients are kept flat, the coefficient of x_1^e_1 ... x_d^e_d at the position of
+// (e_1, ..., e_d) in row major order.
+
+// the number of members and the threshold at each level, from the top down
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Levels {
+    pub sizes: Vec<usize>,
+    pub thresholds: Vec<usize>,
+}
+
+impl Levels {
+    pub fn new(sizes: Vec<usize>, thresholds: Vec<usize>) -> Result<Self, String> {
+        if sizes.is_empty() || sizes.len() != thresholds.len() {
+            return Err("one size and one threshold are needed per level".to_string());
+        }
+        for (k, (size, threshold)) in sizes.iter().zip(&thresholds).enumerate() {
+            if *threshold == 0 || threshold > size {
+                return Err(format!(
+                    "threshold of level {} has to be between 1 and {}",
+                    k, size
+                ));
+            }
+        }
+        Ok(Self { sizes, thresholds })
+    }
+
+    pub fn depth(&self) -> usize {
+        self.sizes.len()
+    }
+
+    // the number of nodes at the bottom level
+    pub fn node_count(&self) -> usize {
+        self.sizes.iter().product()
+    }
+
+    // the index of every node in row major order
+    pub fn nodes(&self) -> Vec<Vec<usize>> {
+        (0..self.node_count())
+            .map(|position| {
+                let mut index = vec![0; self.depth()];
+                let mut rest = position;
+                for (k, size) in self.sizes.iter().enumerate().rev() {
+                    index[k] = rest % size;
+                    rest /= size;
+                }
+                index
+            })
+            .collect()
+    }
+
+    // the position of a node in row major order
+    pub fn position(&self, index: &[usize]) -> usize {
+        index
+            .iter()
+            .zip(&self.sizes)
+            .fold(0, |position, (i, size)| position * size + i)
+    }
+}
+
+// <sizes>_<thresholds>, lists joined with '-', used in results file names
+impl fmt::Display for Levels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", join(&self.sizes), join(&self.thresholds))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polynomial {
+    // the number of coefficients in each variable
+    pub thresholds: Vec<usize>,
+    pub coefficients: Vec<Scalar>,
+}
+
+impl Polynomial {
+    // generate a random polynomial
+    pub fn random<R: RngCore>(thresholds: &[usize], rng: &mut R) -> Self {
+        Self {
+            thresholds: thresholds.to_vec(),
+            coefficients: (0..thresholds.iter().product())
+                .map(|_| random_bls12_381_scalar(rng))
+                .collect(),
+        }
+    }
+
+    // evaluate the polynomial at a point
+    pub fn evaluate_at(&self, point: &[Scalar]) -> Scalar {
+        horner_nested(&self.coefficients, &self.thresholds, point, Scalar::zero())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicCoefficients {
+    pub thresholds: Vec<usize>,
+    pub coefficients: Vec<G2Projective>,
+}
+
+impl PublicCoefficients {
+    // the key of the node or set of nodes with the given index prefix, everyone for an empty one
+    pub fn key(&self, prefix: &[usize]) -> PublicKey {
+        let point: Vec<Scalar> = (0..self.thresholds.len())
+            .map(|k| match prefix.get(k) {
+                Some(i) => x_for_index(*i as u32),
+                None => Scalar::zero(),
+            })
+            .collect();
+        PublicKey(horner_nested(
+            &self.coefficients,
+            &self.thresholds,
+            &point,
+            G2Projective::identity(),
+        ))
+    }
+
+    // return the public key of everyone
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.coefficients[0])
+    }
+
+    // add two sets of coefficients together returning the result
+    // ! This assumes both have the same thresholds
+    pub fn add(&self, rhs: &Self) -> Self {
+        Self {
+            thresholds: self.thresholds.clone(),
+            coefficients: self
+                .coefficients
+                .iter()
+                .zip(&rhs.coefficients)
+                .map(|(a, b)| a + b)
+                .collect(),
+        }
+    }
+
+    // serialize the public coefficients, 192 bytes per coefficient
+    pub fn serialize(&self) -> Vec<u8> {
+        self.coefficients
+            .iter()
+            .flat_map(|coefficient| coefficient.to_affine().to_uncompressed().to_vec())
+            .collect()
+    }
+
+    // deserialize the public coefficients, None if they don't fit the thresholds
+    pub fn deserialize(bytes: &[u8], thresholds: &[usize]) -> Option<Self> {
+        if bytes.len() != thresholds.iter().product::<usize>() * 192 {
+            return None;
+        }
+        Some(Self {
+            thresholds: thresholds.to_vec(),
+            coefficients: bytes
+                .chunks_exact(192)
+                .map(|chunk| {
+                    Option::<G2Affine>::from(G2Affine::from_uncompressed(
+                        &chunk.try_into().unwrap(),
+                    ))
+                    .map(G2Projective::from)
+                })
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+}
+
+// generate public coefficients from a polynomial
+impl From<&Polynomial> for PublicCoefficients {
+    fn from(polynomial: &Polynomial) -> Self {
+        Self {
+            thresholds: polynomial.thresholds.clone(),
+            coefficients: polynomial
+                .coefficients
+                .iter()
+                .map(|coefficient| G2Projective::generator() * coefficient)
+                .collect(),
+        }
+    }
+}
+
+// a dealing over several levels, shares are in the row major order of the nodes
+pub struct Dealing(pub PublicCoefficients, pub Vec<Scalar>);
+
+impl Dealing {
+    // deal shares of a random polynomial to every node
+    pub fn generate(levels: &Levels) -> Self {
+        let seed = rand::random::<[u8; 32]>();
+        let mut rng = ChaChaRng::from_seed(seed);
+        let poly = Polynomial::random(&levels.thresholds, &mut rng);
+        let shares = levels
+            .nodes()
+            .iter()
+            .map(|index| {
+                let point: Vec<Scalar> = index.iter().map(|i| x_for_index(*i as u32)).collect();
+                poly.evaluate_at(&point)
+            })
+            .collect();
+        Dealing(PublicCoefficients::from(&poly), shares)
+    }
+
+    // serialize a dealing
+    pub fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        (
+            self.0.serialize(),
+            self.1
+                .iter()
+                .flat_map(|scalar| scalar.to_bytes().to_vec())
+                .collect(),
+        )
+    }
+
+    // deserialize a dealing, None if it doesn't fit the levels
+    pub fn deserialize(coefficients: &[u8], scalars: &[u8], levels: &Levels) -> Option<Self> {
+        if scalars.len() != levels.node_count() * 32 {
+            return None;
+        }
+        Some(Dealing(
+            PublicCoefficients::deserialize(coefficients, &levels.thresholds)?,
+            scalars
+                .chunks_exact(32)
+                .map(|chunk| Option::from(Scalar::from_bytes(chunk.try_into().unwrap())))
+                .collect::<Option<Vec<Scalar>>>()?,
+        ))
+    }
+
+    // sum the shares at `position` and the public coefficients of all dealings
+    pub fn combine(position: usize, dealings: &[Dealing]) -> (PublicCoefficients, Scalar) {
+        dealings.iter().skip(1).fold(
+            (dealings[0].0.clone(), dealings[0].1[position]),
+            |(coefficients, share), dealing| {
+                (coefficients.add(&dealing.0), share + dealing.1[position])
+            },
+        )
+    }
+}
+
+// nodes are at their row major position
+impl Sharing for Dealing {
+    type Layout = Levels;
+    type Output = (PublicCoefficients, Scalar);
+
+    fn node_count(levels: &Levels) -> usize {
+        levels.node_count()
+    }
+
+    fn generate(levels: &Levels) -> Self {
+        Dealing::generate(levels)
+    }
+
+    fn serialize(&self) -> (Vec<u8>, Vec<u8>) {
+        Dealing::serialize(self)
+    }
+
+    fn deserialize(levels: &Levels, coefficients: &[u8], shares: &[u8]) -> Result<Self, String> {
+        Dealing::deserialize(coefficients, shares, levels)
+            .ok_or_else(|| "the dealing doesn't fit the levels".to_string())
+    }
+
+    fn combine(_: &Levels, position: usize, dealings: &[Self]) -> Self::Output {
+        Dealing::combine(position, dealings)
+    }
+}
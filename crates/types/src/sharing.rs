@@ -0,0 +1,31 @@
+// Dealings a dkg can run on
+//
+// Every sharing deals to a fixed layout of nodes, which have the positions 0..node_count in the
+// order of their ids. A node deals once, checks every dealing it gets against the layout and
+// sums the ones it holds into its key, so networking::SharingDkg runs the dkg for any of them.
+
+pub trait Sharing: Sized {
+    // the nodes and thresholds dealt to
+    type Layout: Clone;
+    // the public coefficients and secret share of a node
+    type Output;
+
+    // the number of nodes dealt to
+    fn node_count(layout: &Self::Layout) -> usize;
+
+    // deal shares of a random polynomial to every node
+    fn generate(layout: &Self::Layout) -> Self;
+
+    // the public coefficients and the shares
+    fn serialize(&self) -> (Vec<u8>, Vec<u8>);
+
+    // an error if the dealing doesn't fit the layout
+    fn deserialize(
+        layout: &Self::Layout,
+        coefficients: &[u8],
+        shares: &[u8],
+    ) -> Result<Self, String>;
+
+    // sum the shares of the node at `position` and the public coefficients of all dealings
+    fn combine(layout: &Self::Layout, position: usize, dealings: &[Self]) -> Self::Output;
+}
@@ -1,9 +1,13 @@
+use std::{fmt, ops::Range, str::FromStr};
+
 use bls12_381::{G2Affine, G2Projective, Scalar};
 use group::Curve;
 use ic_crypto_internal_threshold_sig_bls12381::types::{PublicCoefficients, PublicKey};
 use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+use crate::util::join;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Message {
     Shares(Vec<Vec<u8>>, Vec<Vec<u8>>),
 }
@@ -36,37 +40,114 @@ impl Dealing {
         )
     }
 
-    // deserialize the bytes into a dealing
-    pub fn deserialize(coefficients: Vec<Vec<u8>>, scalars: Vec<Vec<u8>>) -> Self {
-        Dealing(
-            PublicCoefficients {
-                coefficients: coefficients
-                    .iter()
-                    .fold(Vec::new(), |mut acc, coefficient| {
-                        acc.push(PublicKey(G2Projective::from(
-                            &G2Affine::from_uncompressed_unchecked(
-                                coefficient
-                                    .as_slice()
-                                    .try_into()
-                                    .expect("Slice for PublicCoefficient is not len 96"),
-                            )
-                            .unwrap(),
-                        )));
-                        acc
-                    }),
-            },
-            scalars.iter().fold(Vec::new(), |mut acc, scalar| {
-                acc.push(
-                    Scalar::from_bytes(
-                        scalar
-                            .as_slice()
-                            .try_into()
-                            .expect("Slice for Scalar is not len 32"),
-                    )
-                    .unwrap(), // unwrap since CtOption doesn't have expect
-                );
-                acc
-            }),
-        )
+    // deserialize a dealing of `threshold` coefficients and `points` shares from a peer, checking
+    // every point is in the subgroup and every share is canonical. None if it doesn't fit
+    pub fn deserialize(
+        coefficients: &[Vec<u8>],
+        scalars: &[Vec<u8>],
+        threshold: usize,
+        points: usize,
+    ) -> Option<Self> {
+        if coefficients.len() != threshold || scalars.len() != points {
+            return None;
+        }
+        let coefficients = coefficients
+            .iter()
+            .map(|coefficient| {
+                Option::from(G2Affine::from_uncompressed(
+                    coefficient.as_slice().try_into().ok()?,
+                ))
+                .map(|point: G2Affine| PublicKey(G2Projective::from(&point)))
+            })
+            .collect::<Option<Vec<PublicKey>>>()?;
+        let scalars = scalars
+            .iter()
+            .map(|scalar| Option::from(Scalar::from_bytes(scalar.as_slice().try_into().ok()?)))
+            .collect::<Option<Vec<Scalar>>>()?;
+        Some(Dealing(PublicCoefficients { coefficients }, scalars))
+    }
+}
+
+// The weight of each node. A node of weight w holds w evaluation points of the polynomial, so it
+// counts w times towards the threshold, which is in total weight. Points are handed out in node
+// order, node i holding the w_i points after those of the nodes before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Weights(pub Vec<usize>);
+
+impl Weights {
+    // every one of n nodes has weight 1, the unweighted scheme
+    pub fn uniform(n: usize) -> Self {
+        Weights(vec![1; n])
+    }
+
+    // the number of nodes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // the total weight, the number of points
+    pub fn total(&self) -> usize {
+        self.0.iter().sum()
+    }
+
+    // check t points can sign, 1 <= t <= the total weight
+    pub fn check_threshold(&self, t: usize) -> Result<(), String> {
+        if t == 0 || t > self.total() {
+            return Err(format!("t has to be between 1 and {}", self.total()));
+        }
+        Ok(())
+    }
+
+    // the weight of a set of nodes
+    pub fn weight_of<'a>(&self, nodes: impl IntoIterator<Item = &'a usize>) -> usize {
+        nodes.into_iter().map(|i| self.0[*i]).sum()
+    }
+
+    // the points held by node `index`
+    pub fn points(&self, index: usize) -> Range<usize> {
+        let start = self.0[..index].iter().sum();
+        start..start + self.0[index]
+    }
+
+    // the node holding `point`
+    pub fn owner(&self, point: usize) -> usize {
+        let mut end = 0;
+        self.0
+            .iter()
+            .position(|w| {
+                end += w;
+                point < end
+            })
+            .expect("point beyond the total weight")
+    }
+}
+
+// parses a comma separated list of weights
+impl FromStr for Weights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|w| match w.parse::<usize>() {
+                Ok(w) if w > 0 => Ok(w),
+                _ => Err(format!("invalid weight: {}", w)),
+            })
+            .collect::<Result<Vec<usize>, String>>()
+            .map(Weights)
+    }
+}
+
+// the node count when every weight is 1, the weights joined with '-' otherwise,
+// used in results file names
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.iter().all(|w| *w == 1) {
+            return write!(f, "{}", self.len());
+        }
+        write!(f, "{}", join(&self.0))
     }
 }
@@ -0,0 +1,50 @@
+use bls12_381::Scalar;
+
+// Helpers shared by the sharings
+//
+// Every polynomial is evaluated with Horner's method, whether its coefficients are scalars or
+// points, and layouts are written into results file names the same way.
+
+// evaluate a polynomial with coefficients in increasing degree at x, `zero` if it has none
+pub(crate) fn horner<T>(coefficients: &[T], zero: T, x: Scalar) -> T
+where
+    T: Copy + std::ops::Mul<Scalar, Output = T> + std::ops::Add<T, Output = T>,
+{
+    coefficients
+        .iter()
+        .rev()
+        .fold(zero, |acc, coefficient| acc * x + *coefficient)
+}
+
+// evaluate flat coefficients of a polynomial in several variables at `point`, with Horner's
+// method in one variable at a time. `thresholds` is the number of coefficients in each variable,
+// the coefficients are in row major order of their exponents
+pub(crate) fn horner_nested<T>(
+    coefficients: &[T],
+    thresholds: &[usize],
+    point: &[Scalar],
+    zero: T,
+) -> T
+where
+    T: Copy + std::ops::Mul<Scalar, Output = T> + std::ops::Add<T, Output = T>,
+{
+    match thresholds.split_first() {
+        None => coefficients[0],
+        Some((_, rest)) => {
+            let chunk = rest.iter().product::<usize>();
+            let inner: Vec<T> = coefficients
+                .chunks(chunk)
+                .map(|chunk| horner_nested(chunk, rest, &point[1..], zero))
+                .collect();
+            horner(&inner, zero, point[0])
+        }
+    }
+}
+
+// a list joined with '-', for results file names
+pub(crate) fn join(list: &[usize]) -> String {
+    list.iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<String>>()
+        .join("-")
+}
@@ -1,15 +1,15 @@
-use std::{collections::BTreeMap, ops::Add};
+use std::ops::Add;
 
-use bls12_381::{G1Projective, Scalar};
+use bls12_381::Scalar;
 use ic_crypto_internal_threshold_sig_bls12381::{
     crypto::x_for_index,
-    types::{Polynomial, PublicCoefficients, PublicKey},
+    types::{Polynomial, PublicCoefficients},
 };
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
-use types::univariate::Dealing;
+use types::univariate::{Dealing, Weights};
 
-// generates shares for a scheme with n nodes and t threshold
+// generates shares for a scheme with n points and t threshold, one point per node unweighted
 pub fn generate_shares(n: u32, t: usize) -> Dealing {
     let seed = rand::random::<[u8; 32]>();
     let mut rng = ChaChaRng::from_seed(seed);
@@ -22,34 +22,27 @@ pub fn generate_shares(n: u32, t: usize) -> Dealing {
     Dealing(public_coefficients, shares)
 }
 
-// sum all shares for node `index`
-pub fn combine_dealings(index: usize, dealings: &Vec<Dealing>) -> (PublicCoefficients, Scalar) {
+// sum all shares for each point of node `index`, a node of weight w holds w points
+pub fn combine_weighted_dealings(
+    index: usize,
+    weights: &Weights,
+    dealings: &[Dealing],
+) -> (PublicCoefficients, Vec<Scalar>) {
+    let points = weights.points(index);
     dealings.iter().fold(
-        (PublicCoefficients::zero(), Scalar::zero()),
+        (
+            PublicCoefficients::zero(),
+            vec![Scalar::zero(); points.len()],
+        ),
         |(coefficients, shares), dealing| {
-            (coefficients.add(&dealing.0), shares.add(dealing.1[index]))
+            (
+                coefficients.add(&dealing.0),
+                shares
+                    .iter()
+                    .zip(&dealing.1[points.clone()])
+                    .map(|(a, b)| a + b)
+                    .collect(),
+            )
         },
     )
 }
-
-// returns the public key for node `index`
-pub fn get_public_key(index: usize, coefficients: &PublicCoefficients) -> PublicKey {
-    // x_for_index(index) is a scalar equal to index + 1 for the evaluation
-    PublicKey(coefficients.evaluate_at(&x_for_index(index as u32)))
-}
-
-// TODO: Move this to a sign crate, this function is duplicated in bivar dkg as well
-pub fn combine_signatures(
-    signatures: &BTreeMap<usize, G1Projective>,
-    t: usize,
-) -> Result<G1Projective, String> {
-    if signatures.len() < t {
-        return Err("Invalid Threshold".to_string());
-    }
-
-    let signatures: Vec<(Scalar, G1Projective)> = signatures
-        .iter()
-        .map(|(k, v)| (x_for_index(*k as u32), *v))
-        .collect();
-    Ok(PublicCoefficients::interpolate_g1(&signatures).expect("Duplicate indices"))
-}
@@ -0,0 +1,71 @@
+use crate::api::{check_share_weights, read_addresses, run_weighted_signature};
+
+use sign::{scheme::G2, Signable};
+use types::{
+    g1::{Dealing, PublicCoefficients},
+    univariate::Weights,
+};
+
+// The univariate scheme with signatures in G2 and keys in G1
+//
+// A univariate dealing is a bivariate dealing with t' = 1, point p holds the share of (p, 0).
+// Signing runs the same weighted flow as api.rs with the group swapped, and the dkg the bivariate
+// machine on such dealings.
+
+// Generates shares with keys in G1 and writes them and the public coefficients to files
+// shares are in point order, a node of weight w holds w of them, and the weights are written along
+pub fn write_dealing_to_file(weights: &Weights, threshold: usize) {
+    let dealing = Dealing::generate((weights.total() as u32, 1), (threshold, 1));
+
+    std::fs::write(
+        "univariate_shares_g2",
+        bincode::serialize(&(&weights.0, dealing.serialize())).unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        "univariate_public_coefficients_g2",
+        bincode::serialize(&dealing.0.serialize()).unwrap(),
+    )
+    .unwrap();
+}
+
+// Runs a node for threshold signatures in G2 over `signable`, t is in total weight. Fails if the
+// run does
+pub async fn run_threshold_signature(
+    my_id: usize,
+    weights: Weights,
+    t: usize,
+    aws: bool,
+    signable: Signable,
+) -> Result<(), String> {
+    let addresses = read_addresses(weights.len() as u32, aws);
+
+    // read our shares from a file, one for each of our points
+    let (file_weights, dealing): (Vec<usize>, (Vec<u8>, Vec<u8>)) = bincode::deserialize(
+        &std::fs::read("univariate_shares_g2").expect("unable to read share file"),
+    )
+    .expect("unable to deserialize file");
+    check_share_weights(file_weights, &weights);
+    let dealing = Dealing::deserialize(&dealing.0, &dealing.1, (weights.total() as u32, 1), (t, 1))
+        .expect("share file doesn't fit the weights and threshold");
+    let sks: Vec<_> = weights
+        .points(my_id)
+        .map(|point| dealing.1[point][0])
+        .collect();
+
+    let filename = format!(
+        "results/univariate_threshold_signatures_g2_{}_{}",
+        weights, t
+    );
+    run_weighted_signature::<G2, PublicCoefficients>(
+        my_id,
+        &weights,
+        t,
+        addresses,
+        &signable.bytes(),
+        (&dealing.0, &sks),
+        &filename,
+    )
+    .await
+    .map(|_| ())
+}
@@ -1,4 +1,6 @@
 pub mod api;
 mod dkg;
+pub mod g2;
+pub mod machine;
 #[cfg(test)]
 mod tests;
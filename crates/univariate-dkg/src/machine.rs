@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+
+use crate::dkg::{combine_weighted_dealings, generate_shares};
+
+use bls12_381::Scalar;
+use ic_crypto_internal_threshold_sig_bls12381::types::PublicCoefficients;
+use networking::{DkgProtocol, Outgoing};
+use types::{
+    univariate::{Dealing, Message, Weights},
+    Id,
+};
+
+// The univariate dkg as a state machine without any IO
+//
+// Like the bivariate machine, it deals when it is created and gives its shares once it holds a
+// dealing from every node. main runs it over a Node with networking::run_dkg_node.
+
+// the weight of every node and the threshold, in total weight
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub weights: Weights,
+    pub threshold: usize,
+}
+
+pub struct UnivariateDkg {
+    params: Params,
+    my_id: usize,
+    // our dealing, sent on the first call to start
+    dealing: Option<Message>,
+    dealings: Vec<Dealing>,
+    senders: BTreeSet<Id>,
+    // whether poll_output gave our shares
+    done: bool,
+}
+
+impl UnivariateDkg {
+    // the number of dealings we hold, ours included
+    pub fn dealing_count(&self) -> usize {
+        self.dealings.len()
+    }
+
+    fn insert(&mut self, from: Id, dealing: Dealing) {
+        self.senders.insert(from);
+        self.dealings.push(dealing);
+    }
+}
+
+impl DkgProtocol for UnivariateDkg {
+    type Params = Params;
+    type Message = Message;
+    // a share for each of our points
+    type Output = (PublicCoefficients, Vec<Scalar>);
+    type Error = String;
+
+    // generate our dealing for node `my_id`
+    fn new(params: Params, my_id: Id) -> Result<Self, String> {
+        let my_id = match my_id {
+            Id::Univariate(i) if i < params.weights.len() => i,
+            _ => return Err(format!("{:?} isn't a node of the dkg", my_id)),
+        };
+
+        let dealing = generate_shares(params.weights.total() as u32, params.threshold);
+        let (serialized_coefficients, serialized_shares) = dealing.serialize();
+
+        let mut dkg = UnivariateDkg {
+            params,
+            my_id,
+            dealing: Some(Message::Shares(serialized_coefficients, serialized_shares)),
+            dealings: Vec::new(),
+            senders: BTreeSet::new(),
+            done: false,
+        };
+        dkg.insert(dkg.id(), dealing);
+        Ok(dkg)
+    }
+
+    fn id(&self) -> Id {
+        Id::Univariate(self.my_id)
+    }
+
+    fn participants(&self) -> Vec<Id> {
+        (0..self.params.weights.len()).map(Id::Univariate).collect()
+    }
+
+    // the dealing to send to every other node
+    fn start(&mut self) -> Vec<Outgoing<Message>> {
+        let me = self.id();
+        let to: Vec<Id> = self
+            .participants()
+            .into_iter()
+            .filter(|id| *id != me)
+            .collect();
+        self.dealing
+            .take()
+            .map(|msg| Outgoing { to, msg })
+            .into_iter()
+            .collect()
+    }
+
+    // messages that don't fit the weights and second dealings from the same node are rejected
+    fn handle_message(&mut self, from: Id, msg: Message) -> Result<Vec<Outgoing<Message>>, String> {
+        let known = matches!(from, Id::Univariate(i) if i < self.params.weights.len());
+        if !known {
+            return Err(format!("{:?} isn't a node of the dkg", from));
+        }
+        if self.senders.contains(&from) {
+            return Err(format!("{:?} already sent a dealing", from));
+        }
+
+        match msg {
+            Message::Shares(serialized_coefficients, serialized_shares) => {
+                let dealing = Dealing::deserialize(
+                    &serialized_coefficients,
+                    &serialized_shares,
+                    self.params.threshold,
+                    self.params.weights.total(),
+                )
+                .ok_or_else(|| format!("the dealing of {:?} doesn't fit the weights", from))?;
+                self.insert(from, dealing);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    // combined from the dealings once there is one from every node
+    fn poll_output(&mut self) -> Option<(PublicCoefficients, Vec<Scalar>)> {
+        if self.done || self.dealings.len() < self.params.weights.len() {
+            return None;
+        }
+        self.done = true;
+        Some(combine_weighted_dealings(
+            self.my_id,
+            &self.params.weights,
+            &self.dealings,
+        ))
+    }
+}
@@ -1,6 +1,13 @@
 use types::univariate::{Dealing, Message};
 
 use crate::dkg::*;
+use bls12_381::{G1Projective, Scalar};
+use ic_crypto_internal_threshold_sig_bls12381::{
+    crypto::{sign_message, verify_combined_sig},
+    types::PublicKey,
+};
+use sign::{combine_signatures_robust, PrefixKeys};
+use std::collections::BTreeMap;
 
 // #[test]
 // fn run_11_node_dkg() {
@@ -41,7 +48,7 @@ fn serialize() {
     );
 
     let recovered_dealing = match recovered_msg {
-        Message::Shares(c, s) => Dealing::deserialize(c, s),
+        Message::Shares(c, s) => Dealing::deserialize(&c, &s, 5, 11).unwrap(),
     };
 
     assert_eq!(
@@ -53,3 +60,196 @@ fn serialize() {
         "Scalars do not match"
     );
 }
+
+// Tests dealings with a point outside the subgroup, a share that isn't canonical or the wrong
+// size are rejected by the machine instead of panicking
+#[test]
+fn machine_rejects_invalid_dealings() {
+    use crate::machine::{Params, UnivariateDkg};
+    use bls12_381::G2Affine;
+    use networking::DkgProtocol;
+    use types::{univariate::Weights, Id};
+
+    let params = Params {
+        weights: Weights::uniform(3),
+        threshold: 2,
+    };
+    let (coefficients, scalars) = generate_shares(3, 2).serialize();
+    assert!(Dealing::deserialize(&coefficients, &scalars, 2, 3).is_some());
+
+    // a point on the curve outside the subgroup, from the first x that has one
+    let mut x = G2Affine::generator().to_compressed();
+    let outside = loop {
+        x[95] = x[95].wrapping_add(1);
+        let point: Option<G2Affine> = G2Affine::from_compressed_unchecked(&x).into();
+        match point {
+            Some(point) if !bool::from(point.is_torsion_free()) => break point,
+            _ => (),
+        }
+    };
+    let mut outside_subgroup = coefficients.clone();
+    outside_subgroup[1] = outside.to_uncompressed().to_vec();
+    let mut not_canonical = scalars.clone();
+    not_canonical[0] = vec![0xff; 32];
+    let invalid = [
+        (outside_subgroup, scalars.clone()),
+        (coefficients.clone(), not_canonical),
+        (coefficients.clone(), scalars[1..].to_vec()),
+        (coefficients[1..].to_vec(), scalars),
+    ];
+    for (coefficients, scalars) in invalid {
+        assert!(Dealing::deserialize(&coefficients, &scalars, 2, 3).is_none());
+        let mut machine = UnivariateDkg::new(params.clone(), Id::Univariate(0)).unwrap();
+        assert!(machine
+            .handle_message(Id::Univariate(1), Message::Shares(coefficients, scalars))
+            .is_err());
+    }
+}
+
+// Tests invalid shares are excluded and reported when the optimistic combination fails
+#[test]
+fn combine_signatures_robust_excludes_faulty() {
+    let dealing = generate_shares(7, 3);
+    let public_key = PublicKey(dealing.0.evaluate_at(&Scalar::zero()));
+    let msg: [u8; 32] = [0; 32];
+
+    let mut signatures: BTreeMap<usize, G1Projective> = (0..5)
+        .map(|i| (i, sign_message(&msg, &dealing.1[i])))
+        .collect();
+    let share_key = |i| dealing.0.key(&[i]);
+
+    let (signature, faulty) =
+        combine_signatures_robust(&msg, &signatures, 3, public_key, share_key);
+    verify_combined_sig(&msg, signature.unwrap(), public_key).unwrap();
+    assert!(faulty.is_empty());
+
+    *signatures.get_mut(&1).unwrap() += G1Projective::generator();
+    let (signature, faulty) =
+        combine_signatures_robust(&msg, &signatures, 3, public_key, share_key);
+    verify_combined_sig(&msg, signature.unwrap(), public_key).unwrap();
+    assert_eq!(faulty, vec![1]);
+
+    // too few valid shares left to combine
+    *signatures.get_mut(&3).unwrap() += G1Projective::generator();
+    *signatures.get_mut(&4).unwrap() += G1Projective::generator();
+    let (signature, faulty) =
+        combine_signatures_robust(&msg, &signatures, 3, public_key, share_key);
+    assert!(signature.is_err());
+    assert_eq!(faulty, vec![1, 3, 4]);
+}
+
+// Tests a weighted dkg and signing where the threshold is in total weight
+#[test]
+fn weighted_threshold_signature() {
+    use networking::ThresholdScheme;
+    use sign::{scheme::G1, weighted::Weighted, Signing};
+    use types::univariate::Weights;
+
+    let weights: Weights = "3,1,2,1".parse().unwrap();
+    assert_eq!(weights.total(), 7);
+    assert_eq!(weights.points(2), 4..6);
+    assert_eq!(weights.owner(5), 2);
+    assert_eq!(weights.to_string(), "3-1-2-1");
+    assert!(weights.check_threshold(7).is_ok());
+    assert!(weights.check_threshold(8).is_err());
+    assert!(weights.check_threshold(0).is_err());
+    assert_eq!(Weights::uniform(4).to_string(), "4");
+    assert!("3,0".parse::<Weights>().is_err());
+
+    let dealings: Vec<Dealing> = (0..4).map(|_| generate_shares(7, 4)).collect();
+    let msg: [u8; 32] = [0; 32];
+
+    let mut signatures = BTreeMap::new();
+    let mut coefficients = None;
+    for i in 0..4 {
+        let (node_coefficients, sks) = combine_weighted_dealings(i, &weights, &dealings);
+        assert_eq!(sks.len(), weights.0[i]);
+        let sigs: Vec<G1Projective> = sks.iter().map(|sk| sign_message(&msg, sk)).collect();
+        signatures.insert(i, sigs);
+        coefficients = Some(node_coefficients);
+    }
+    let coefficients = coefficients.unwrap();
+    let public_key = PublicKey(coefficients.evaluate_at(&Scalar::zero()));
+    let weighted = Weighted {
+        signing: Signing::<G1, _>::new(&msg, &coefficients),
+        weights: &weights,
+    };
+
+    for (i, sigs) in &signatures {
+        assert!(weighted.verify(&[*i], sigs).is_ok());
+        let bytes = weighted.serialize(sigs);
+        assert_eq!(weighted.deserialize(&[*i], &bytes).unwrap(), *sigs);
+    }
+    // node 0 sends 3 signatures and node 1 only one
+    let bytes = weighted.serialize(&signatures[&0]);
+    assert!(weighted.deserialize(&[1], &bytes).is_none());
+    assert!(weighted.deserialize(&[4], &bytes).is_none());
+
+    // nodes 0 and 1 hold 4 in weight, enough on their own
+    let heavy: BTreeMap<usize, Vec<G1Projective>> = signatures
+        .iter()
+        .filter(|(i, _)| **i < 2)
+        .map(|(i, sigs)| (*i, sigs.clone()))
+        .collect();
+    let signature = weighted.combine(&[], &heavy, 4).unwrap();
+    verify_combined_sig(&msg, signature[0], public_key).unwrap();
+    assert!(weighted.verify(&[], &signature).is_ok());
+
+    // nodes 1 and 3 hold only 2 in weight
+    let light: BTreeMap<usize, Vec<G1Projective>> = signatures
+        .iter()
+        .filter(|(i, _)| **i % 2 == 1)
+        .map(|(i, sigs)| (*i, sigs.clone()))
+        .collect();
+    assert!(weighted.combine(&[], &light, 4).is_err());
+
+    // a node with one bad signature is reported once, the rest still combine
+    signatures.get_mut(&0).unwrap()[1] += G1Projective::generator();
+    assert!(weighted.verify(&[0], &signatures[&0]).is_err());
+    assert_eq!(weighted.find_invalid(&[], &signatures), vec![0]);
+    signatures.remove(&0);
+    let signature = weighted.combine(&[], &signatures, 4).unwrap();
+    verify_combined_sig(&msg, signature[0], public_key).unwrap();
+}
+
+// Tests the dkg state machine run in memory gives every node its weight in shares of one key
+#[test]
+fn machine_runs_in_memory() {
+    use crate::machine::{Params, UnivariateDkg};
+    use networking::{run_in_memory, DkgProtocol, ThresholdScheme};
+    use sign::{scheme::G1, weighted::Weighted, Signing};
+    use types::Id;
+
+    let params = Params {
+        weights: "2,1,1".parse().unwrap(),
+        threshold: 3,
+    };
+    assert!(UnivariateDkg::new(params.clone(), Id::Univariate(3)).is_err());
+    let machines = (0..3)
+        .map(|i| UnivariateDkg::new(params.clone(), Id::Univariate(i)).unwrap())
+        .collect();
+    let outputs = run_in_memory::<UnivariateDkg>(machines);
+    assert_eq!(outputs.len(), 3);
+
+    let msg = rand::random::<[u8; 32]>();
+    let mut keys = None;
+    let mut signatures = BTreeMap::new();
+    for (id, (coefficients, sks)) in outputs {
+        assert_eq!(
+            *keys.get_or_insert_with(|| coefficients.clone()),
+            coefficients
+        );
+        if let Id::Univariate(i) = id {
+            assert_eq!(sks.len(), params.weights.0[i]);
+            signatures.insert(i, sks.iter().map(|sk| sign_message(&msg, sk)).collect());
+        }
+    }
+    let keys = keys.unwrap();
+    let weighted = Weighted {
+        signing: Signing::<G1, _>::new(&msg, &keys),
+        weights: &params.weights,
+    };
+    assert!(weighted.find_invalid(&[], &signatures).is_empty());
+    let signature = weighted.combine(&[], &signatures, 3).unwrap();
+    verify_combined_sig(&msg, signature[0], keys.key(&[])).unwrap();
+}
@@ -0,0 +1,21 @@
+#!/bin/bash
+
+N=$1
+M=$2
+T=$3
+P=$4
+ROUNDS=$5
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        rm -f "beacon_${i}_${j}"
+        ./target/release/main beacon -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" --rounds "$ROUNDS" &
+    done
+done
+
+wait
+./target/release/main verify-beacon -c bivariate_public_coefficients --chain beacon_0_0
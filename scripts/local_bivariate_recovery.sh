@@ -0,0 +1,28 @@
+#!/bin/bash
+
+N=$1
+M=$2
+T=$3
+P=$4
+LOST=${5:-0,0}
+FROM=${6:-row}
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+
+# nodes that don't take part exit straight away
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-recovery -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" --lost "$LOST" --from "$FROM" &
+    done
+done
+
+wait
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-threshold-signature -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" &
+    done
+done
@@ -0,0 +1,25 @@
+#!/bin/bash
+
+N=$1
+M=$2
+T=$3
+P=$4
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-refresh -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" -r &
+    done
+done
+
+wait
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-threshold-signature -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" &
+    done
+done
@@ -0,0 +1,64 @@
+#!/bin/bash
+
+# reshare the key of the old layout to the one in a membership file, e.g. 3 4 2 3 membership
+N=$1
+M=$2
+T=$3
+P=$4
+MEMBERSHIP=$5
+
+SIZE=$(awk '$1 == "size" && NF == 2 { print $2 }' "$MEMBERSHIP")
+IFS=',' read -ra NEW_GROUPS <<< "$(awk '$1 == "groups" { print $2 }' "$MEMBERSHIP")"
+
+# the size of each new group, "size <group> <m>" lines resize a single group
+SIZES=()
+for ((a=0; a<${#NEW_GROUPS[@]}; a++))
+do
+    SIZES[$a]=$SIZE
+done
+while read -r GROUP GROUP_SIZE
+do
+    SIZES[$GROUP]=$GROUP_SIZE
+done < <(awk '$1 == "size" && NF == 3 { print $2, $3 }' "$MEMBERSHIP")
+
+# every node of the old layout
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-reshare --old "$i,$j" -n "$N" -m "$M" -t "$T" -p "$P" --membership "$MEMBERSHIP" -r &
+    done
+done
+
+# nodes that join, in new groups or beyond the old size of a group
+for ((a=0; a<${#NEW_GROUPS[@]}; a++))
+do
+    START=$M
+    if [ "${NEW_GROUPS[$a]}" == "new" ]
+    then
+        START=0
+    fi
+    for ((b=START; b<${SIZES[$a]}; b++))
+    do
+        ./target/release/main bivariate-reshare --new "$a,$b" -n "$N" -m "$M" -t "$T" -p "$P" --membership "$MEMBERSHIP" -r &
+    done
+done
+
+# sign with the new layout, which the signer only runs with groups of the same size
+IFS=',' read -r NEW_T NEW_P <<< "$(awk '$1 == "threshold" { print $2 }' "$MEMBERSHIP")"
+wait
+for GROUP_SIZE in "${SIZES[@]}"
+do
+    if [ "$GROUP_SIZE" != "$SIZE" ]
+    then
+        echo "groups differ in size, not signing"
+        exit 0
+    fi
+done
+for ((a=0; a<${#NEW_GROUPS[@]}; a++))
+do
+    for ((b=0; b<SIZE; b++))
+    do
+        ./target/release/main bivariate-threshold-signature -i "$a" -j "$b" -n "${#NEW_GROUPS[@]}" -m "$SIZE" -t "$NEW_T" -p "$NEW_P" &
+    done
+done
@@ -0,0 +1,18 @@
+#!/bin/bash
+
+N=$1
+M=$2
+T=$3
+P=$4
+MESSAGE_HEX=$5
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+./target/release/main encrypt -c bivariate_public_coefficients --message-hex "$MESSAGE_HEX"
+
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-threshold-decryption -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" &
+    done
+done
@@ -0,0 +1,17 @@
+#!/bin/bash
+
+# groups and thresholds are comma separated, e.g. 3,5,4 2,3,3 2
+SIZES_LIST=$1
+THRESHOLDS=$2
+T=$3
+
+./target/release/main heterogeneous-share-file --groups "$SIZES_LIST" --thresholds "$THRESHOLDS" -t "$T"
+
+IFS=',' read -ra SIZES <<< "$SIZES_LIST"
+for ((i=0; i<${#SIZES[@]}; i++))
+do
+    for ((j=0; j<${SIZES[$i]}; j++))
+    do
+        ./target/release/main heterogeneous-threshold-signature -i "$i" -j "$j" --groups "$SIZES_LIST" --thresholds "$THRESHOLDS" -t "$T" &
+    done
+done
@@ -0,0 +1,20 @@
+#!/bin/bash
+
+# TOPOLOGY is full, gateways:<g> or tree:<arity>
+# a local check of the topology, results go to results/bivariate_threshold_signatures_N,M_T,P_<topology>
+# with the colon of the topology replaced by a dash, and without the suffix for full
+N=$1
+M=$2
+T=$3
+P=$4
+TOPOLOGY=$5
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        ./target/release/main bivariate-threshold-signature -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" --topology "$TOPOLOGY" &
+    done
+done
@@ -0,0 +1,34 @@
+#!/bin/bash
+
+N=$1
+M=$2
+T=$3
+P=$4
+REQUESTS=$5
+CONCURRENCY=$6
+
+./target/release/main bivariate-share-file -n "$N" -m "$M" -t "$T" -p "$P"
+
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        rm -f "node_${i}_${j}.sock"
+        ./target/release/main serve -i "$i" -j "$j" -n "$N" -m "$M" -t "$T" -p "$P" &
+    done
+done
+
+# nodes only create their socket once they are connected, wait for all of them before sending
+# requests to node 0, 0
+for ((i=0; i<$N; i++))
+do
+    for ((j=0; j<$M; j++))
+    do
+        while [ ! -S "node_${i}_${j}.sock" ]
+        do
+            sleep 1
+        done
+    done
+done
+./target/release/client -s node_0_0.sock -r "$REQUESTS" -c "$CONCURRENCY"
+kill $(jobs -p)
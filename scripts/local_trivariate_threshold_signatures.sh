@@ -0,0 +1,19 @@
+#!/bin/bash
+
+# sizes and thresholds of regions, groups and members are comma separated, e.g. 3,2,4 2,2,3
+SIZES=$1
+THRESHOLDS=$2
+
+./target/release/main trivariate-share-file --sizes "$SIZES" --thresholds "$THRESHOLDS"
+
+IFS=',' read -ra LEVELS <<< "$SIZES"
+for ((i=0; i<${LEVELS[0]}; i++))
+do
+    for ((j=0; j<${LEVELS[1]}; j++))
+    do
+        for ((k=0; k<${LEVELS[2]}; k++))
+        do
+            ./target/release/main trivariate-threshold-signature -i "$i" -j "$j" -k "$k" --sizes "$SIZES" --thresholds "$THRESHOLDS" &
+        done
+    done
+done
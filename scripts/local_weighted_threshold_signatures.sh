@@ -0,0 +1,15 @@
+#!/bin/bash
+
+# weights are comma separated, one per node, and the threshold is in total weight, e.g. 3,1,2,1 4
+WEIGHTS=$1
+T=$2
+
+IFS=',' read -ra WEIGHT_LIST <<< "$WEIGHTS"
+N=${#WEIGHT_LIST[@]}
+
+./target/release/main univariate-share-file -n "$N" -t "$T" --weights "$WEIGHTS"
+
+for ((i=0; i<$N; i++))
+do
+    ./target/release/main univariate-threshold-signature -i "$i" -n "$N" -t "$T" --weights "$WEIGHTS" &
+done